@@ -209,3 +209,32 @@ fn bench_mapping_with_1024_entries(bencher: &mut Bencher) {
             .is_ok());
     });
 }
+
+fn bench_randomized_valid_access(bencher: &mut Bencher, entries: usize) {
+    let mut prng = new_prng!();
+    let (memory_regions, _end_address) = generate_memory_regions(entries, false, None);
+    let config = Config::default();
+    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    bencher.iter(|| {
+        let vm_addr =
+            0x100000000 * (prng.gen::<u64>() % entries as u64 + 1) + prng.gen::<u64>() % 4;
+        assert!(memory_mapping
+            .map::<UserError>(AccessType::Load, vm_addr, 1)
+            .is_ok());
+    });
+}
+
+#[bench]
+fn bench_randomized_valid_access_with_0004_entries(bencher: &mut Bencher) {
+    bench_randomized_valid_access(bencher, 4);
+}
+
+#[bench]
+fn bench_randomized_valid_access_with_0064_entries(bencher: &mut Bencher) {
+    bench_randomized_valid_access(bencher, 64);
+}
+
+#[bench]
+fn bench_randomized_valid_access_with_1024_entries(bencher: &mut Bencher) {
+    bench_randomized_valid_access(bencher, 1024);
+}
@@ -243,3 +243,59 @@ fn bench_jit_vs_interpreter_call_depth_dynamic(bencher: &mut Bencher) {
         &mut [],
     );
 }
+
+#[bench]
+fn bench_run_batch_vs_per_input_vm(bencher: &mut Bencher) {
+    let executable = solana_rbpf::assembler::assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r2, [r1]
+        mov r0, 0
+        jeq r2, 0, +5
+        add r1, 1
+        ldxb r3, [r1]
+        add r0, r3
+        sub r2, 1
+        ja -6
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let inputs: Vec<Vec<u8>> = (0..256u32)
+        .map(|index| {
+            let len = (index % 16) as u8;
+            std::iter::once(len).chain(0..len).collect()
+        })
+        .collect();
+    let inputs: Vec<&[u8]> = inputs.iter().map(|input| input.as_slice()).collect();
+    let per_input_summary = bencher
+        .bench(|bencher| {
+            bencher.iter(|| {
+                for input in inputs.iter() {
+                    let mut mem = input.to_vec();
+                    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+                    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+                    let result = vm
+                        .execute_program_interpreted(&mut TestInstructionMeter { remaining: 1000 });
+                    assert!(result.is_ok(), "{:?}", result);
+                }
+            });
+        })
+        .unwrap();
+    let batch_summary = bencher
+        .bench(|bencher| {
+            bencher.iter(|| {
+                let results = Executable::run_batch(&executable, &inputs, &mut [], 0, || {
+                    TestInstructionMeter { remaining: 1000 }
+                })
+                .unwrap();
+                assert!(results.iter().all(|result| result.is_ok()));
+            });
+        })
+        .unwrap();
+    println!(
+        "per_input_vm_vs_run_batch_ratio={}",
+        per_input_summary.mean / batch_summary.mean
+    );
+}
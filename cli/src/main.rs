@@ -7,7 +7,7 @@ use solana_rbpf::{
     static_analysis::Analysis,
     syscalls::Result,
     user_error::UserError,
-    verifier::check,
+    verifier::{check, Verifier},
     vm::{Config, DynamicAnalysis, EbpfVm, SyscallObject, SyscallRegistry, TestInstructionMeter},
 };
 use std::{fs::File, io::Read, path::Path};
@@ -119,12 +119,11 @@ fn main() {
         enable_symbol_and_section_labels: true,
         ..Config::default()
     };
-    let verifier: Option<for<'r> fn(&'r [u8], &Config) -> std::result::Result<_, _>> =
-        if matches.is_present("verify") {
-            Some(check)
-        } else {
-            None
-        };
+    let verifier: Option<&dyn Verifier> = if matches.is_present("verify") {
+        Some(&check)
+    } else {
+        None
+    };
     let syscall_registry = SyscallRegistry::default();
     let mut executable = match matches.value_of("assembler") {
         Some(asm_file_name) => {
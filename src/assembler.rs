@@ -13,7 +13,7 @@ use self::InstructionType::{
 };
 use crate::{
     asm_parser::{
-        parse,
+        parse_with_lines,
         Operand::{Integer, Label, Memory, Register},
         Statement,
     },
@@ -221,7 +221,7 @@ fn insn(opc: u8, dst: i64, src: i64, off: i64, imm: i64) -> Result<Insn, String>
 /// ```
 pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
     src: &str,
-    verifier: Option<Verifier>,
+    verifier: Option<&dyn Verifier>,
     config: Config,
     syscall_registry: SyscallRegistry,
 ) -> Result<Pin<Box<Executable<E, I>>>, String> {
@@ -256,21 +256,23 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
         Ok(hash as i32 as i64)
     }
 
-    let statements = parse(src)?;
+    let statements = parse_with_lines(src)?;
     let instruction_map = make_instruction_map();
     let mut insn_ptr = 0;
     let mut labels = HashMap::new();
-    labels.insert("entrypoint", 0);
-    for statement in statements.iter() {
+    for (line, statement) in statements.iter() {
         match statement {
             Statement::Label { name } => {
-                labels.insert(name.as_str(), insn_ptr);
+                if labels.insert(name.as_str(), insn_ptr).is_some() {
+                    return Err(format!("Duplicate label {} at line {}", name, line));
+                }
             }
             Statement::Instruction { name, .. } => {
                 insn_ptr += if name == "lddw" { 2 } else { 1 };
             }
         }
     }
+    labels.entry("entrypoint").or_insert(0);
     insn_ptr = 0;
     let mut bpf_functions = BTreeMap::new();
     resolve_call(
@@ -282,9 +284,10 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
         None,
     )?;
     let mut instructions: Vec<Insn> = Vec::new();
-    for statement in statements.iter() {
+    for (line, statement) in statements.iter() {
         if let Statement::Instruction { name, operands } = statement {
             let name = name.as_str();
+            let at_line = |err: String| format!("{} at line {}", err, line);
             match instruction_map.get(name) {
                 Some(&(inst_type, opc)) => {
                     let mut insn = match (inst_type, operands.as_slice()) {
@@ -312,9 +315,13 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                         (JumpConditional, [Register(dst), Integer(imm), Integer(off)]) => {
                             insn(opc | ebpf::BPF_K, *dst, 0, *off, *imm)
                         }
-                        (JumpUnconditional, [Label(label)]) => {
-                            insn(opc, 0, 0, resolve_label(insn_ptr, &labels, label)?, 0)
-                        }
+                        (JumpUnconditional, [Label(label)]) => insn(
+                            opc,
+                            0,
+                            0,
+                            resolve_label(insn_ptr, &labels, label).map_err(at_line)?,
+                            0,
+                        ),
                         (CallImm, [Integer(imm)]) => {
                             let target_pc = (*imm + insn_ptr as i64 + 1) as usize;
                             let label = format!("function_{}", target_pc);
@@ -333,14 +340,14 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                             opc | ebpf::BPF_X,
                             *dst,
                             *src,
-                            resolve_label(insn_ptr, &labels, label)?,
+                            resolve_label(insn_ptr, &labels, label).map_err(at_line)?,
                             0,
                         ),
                         (JumpConditional, [Register(dst), Integer(imm), Label(label)]) => insn(
                             opc | ebpf::BPF_K,
                             *dst,
                             0,
-                            resolve_label(insn_ptr, &labels, label)?,
+                            resolve_label(insn_ptr, &labels, label).map_err(at_line)?,
                             *imm,
                         ),
                         (Syscall, [Label(label)]) => insn(
@@ -348,7 +355,7 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                             0,
                             0,
                             0,
-                            ebpf::hash_symbol_name(label.as_bytes()) as i32 as i64,
+                            syscall_registry.hash_name(label.as_bytes()) as i32 as i64,
                         ),
                         (CallImm, [Label(label)]) => {
                             let hash = resolve_call(
@@ -358,7 +365,8 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                                 &labels,
                                 label,
                                 None,
-                            )?;
+                            )
+                            .map_err(at_line)?;
                             insn(opc, 0, 1, 0, hash as i32 as i64)
                         }
                         (Endian(size), [Register(dst)]) => insn(opc, *dst, 0, 0, size),
@@ -52,19 +52,29 @@ impl<'a> CallFrames<'a> {
             ],
         };
 
-        let frame = &mut frames.frames[0];
-        if config.dynamic_stack_frames {
+        frames.reset();
+        frames
+    }
+
+    /// Returns to the root frame, as at the start of a program
+    pub fn reset(&mut self) {
+        self.frame_index = 0;
+        let frame = &mut self.frames[0];
+        if self.config.dynamic_stack_frames {
             // the stack is fully descending, frames start as empty and change
             // size as resize_stack() is invoked anytime r11 is modified
-            frame.frame_ptr = MM_STACK_START + stack_len as u64;
-            frames.stack_ptr = frame.frame_ptr;
+            frame.frame_ptr = MM_STACK_START + self.config.stack_size() as u64;
+            self.stack_ptr = frame.frame_ptr;
         } else {
             // within a frame the stack grows down, but frames are ascending
-            frame.frame_ptr = MM_STACK_START + config.stack_frame_size as u64;
-            frames.stack_ptr = MM_STACK_START;
+            frame.frame_ptr = MM_STACK_START + self.config.stack_frame_size as u64;
+            self.stack_ptr = MM_STACK_START;
         }
+    }
 
-        frames
+    /// Zeroes the stack memory of all frames
+    pub fn zero_memory(&mut self) {
+        self.stack.as_slice_mut().fill(0);
     }
 
     /// Get stack memory region
@@ -88,6 +98,14 @@ impl<'a> CallFrames<'a> {
             .collect()
     }
 
+    /// Get the return address of each caller frame, outermost first
+    pub fn get_return_pointers(&self) -> Vec<usize> {
+        self.frames[..self.frame_index]
+            .iter()
+            .map(|frame| frame.return_ptr)
+            .collect()
+    }
+
     /// Get the frame pointer for the current frame
     pub fn get_frame_ptr(&self) -> u64 {
         self.frames[self.frame_index].frame_ptr
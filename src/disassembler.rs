@@ -12,26 +12,32 @@ use crate::ebpf;
 use crate::error::UserDefinedError;
 use crate::static_analysis::Analysis;
 use crate::vm::InstructionMeter;
+use std::fmt;
 
-fn resolve_label<'a, E: UserDefinedError, I: InstructionMeter>(
-    analysis: &'a Analysis<E, I>,
+fn resolve_label<E: UserDefinedError, I: InstructionMeter>(
+    analysis: &Analysis<E, I>,
     pc: usize,
-) -> &'a str {
+    jump_labels: bool,
+) -> String {
+    if jump_labels {
+        return format!("lbl_{}", pc);
+    }
     analysis
         .cfg_nodes
         .get(&pc)
         .map(|cfg_node| cfg_node.label.as_str())
         .unwrap_or("[invalid]")
+        .to_string()
 }
 
 #[inline]
-fn alu_imm_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!("{} r{}, {}", name, insn.dst, insn.imm)
+fn alu_imm_str(insn: &ebpf::Insn) -> String {
+    format!("r{}, {}", insn.dst, insn.imm)
 }
 
 #[inline]
-fn alu_reg_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!("{} r{}, r{}", name, insn.dst, insn.src)
+fn alu_reg_str(insn: &ebpf::Insn) -> String {
+    format!("r{}, r{}", insn.dst, insn.src)
 }
 
 #[inline]
@@ -43,7 +49,7 @@ fn byteswap_str(name: &str, insn: &ebpf::Insn) -> String {
             name
         ),
     }
-    format!("{}{} r{}", name, insn.imm, insn.dst)
+    format!("r{}", insn.dst)
 }
 
 #[inline]
@@ -56,225 +62,265 @@ fn signed_off_str(value: i16) -> String {
 }
 
 #[inline]
-fn ld_st_imm_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!(
-        "{} [r{}{}], {}",
-        name,
-        insn.dst,
-        signed_off_str(insn.off),
-        insn.imm
-    )
+fn ld_st_imm_str(insn: &ebpf::Insn) -> String {
+    format!("[r{}{}], {}", insn.dst, signed_off_str(insn.off), insn.imm)
 }
 
 #[inline]
-fn ld_reg_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!(
-        "{} r{}, [r{}{}]",
-        name,
-        insn.dst,
-        insn.src,
-        signed_off_str(insn.off)
-    )
+fn ld_reg_str(insn: &ebpf::Insn) -> String {
+    format!("r{}, [r{}{}]", insn.dst, insn.src, signed_off_str(insn.off))
 }
 
 #[inline]
-fn st_reg_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!(
-        "{} [r{}{}], r{}",
-        name,
-        insn.dst,
-        signed_off_str(insn.off),
-        insn.src
-    )
+fn st_reg_str(insn: &ebpf::Insn) -> String {
+    format!("[r{}{}], r{}", insn.dst, signed_off_str(insn.off), insn.src)
 }
 
 #[inline]
-fn ldabs_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!("{} {}", name, insn.imm)
+fn ldabs_str(insn: &ebpf::Insn) -> String {
+    insn.imm.to_string()
 }
 
 #[inline]
-fn ldind_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!("{} r{}, {}", name, insn.src, insn.imm)
+fn ldind_str(insn: &ebpf::Insn) -> String {
+    format!("r{}, {}", insn.src, insn.imm)
 }
 
 #[inline]
 fn jmp_imm_str<E: UserDefinedError, I: InstructionMeter>(
-    name: &str,
     insn: &ebpf::Insn,
     analysis: &Analysis<E, I>,
+    jump_labels: bool,
 ) -> String {
     let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
     format!(
-        "{} r{}, {}, {}",
-        name,
+        "r{}, {}, {}",
         insn.dst,
         insn.imm,
-        resolve_label(analysis, target_pc)
+        resolve_label(analysis, target_pc, jump_labels)
     )
 }
 
 #[inline]
 fn jmp_reg_str<E: UserDefinedError, I: InstructionMeter>(
-    name: &str,
     insn: &ebpf::Insn,
     analysis: &Analysis<E, I>,
+    jump_labels: bool,
 ) -> String {
     let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
     format!(
-        "{} r{}, r{}, {}",
-        name,
+        "r{}, r{}, {}",
         insn.dst,
         insn.src,
-        resolve_label(analysis, target_pc)
+        resolve_label(analysis, target_pc, jump_labels)
     )
 }
 
+/// An eBPF instruction decoded by the disassembler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    /// Instruction offset in the text section
+    pub pc: usize,
+    /// Operation code
+    pub opcode: u8,
+    /// Destination register
+    pub dst: u8,
+    /// Source register
+    pub src: u8,
+    /// Offset
+    pub off: i16,
+    /// Immediate, the full 64 bit value for `lddw`
+    pub imm: i64,
+    /// Mnemonic, e.g. `add64` or `le16`
+    pub mnemonic: String,
+    /// Operands with jump and call targets resolved to labels, empty for `exit`
+    pub operands: String,
+}
+
+impl fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.operands.is_empty() {
+            write!(f, "{}", self.mnemonic)
+        } else {
+            write!(f, "{} {}", self.mnemonic, self.operands)
+        }
+    }
+}
+
 /// Disassemble an eBPF instruction
+pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(
+    insn: &ebpf::Insn,
+    analysis: &Analysis<E, I>,
+) -> String {
+    decode_instruction(insn, analysis).to_string()
+}
+
+/// Decode an eBPF instruction, its Display impl is the text of `disassemble_instruction()`
+pub fn decode_instruction<E: UserDefinedError, I: InstructionMeter>(
+    insn: &ebpf::Insn,
+    analysis: &Analysis<E, I>,
+) -> DisassembledInstruction {
+    decode_instruction_with_labels(insn, analysis, false)
+}
+
+/// Decode an eBPF instruction, with jump and call targets as `lbl_<pc>` if `jump_labels` is set
 #[rustfmt::skip]
-pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &ebpf::Insn, analysis: &Analysis<E, I>) -> String {
+pub(crate) fn decode_instruction_with_labels<E: UserDefinedError, I: InstructionMeter>(insn: &ebpf::Insn, analysis: &Analysis<E, I>, jump_labels: bool) -> DisassembledInstruction {
     let name;
-    let desc;
+    let operands;
     match insn.opc {
         // BPF_LD class
-        ebpf::LD_ABS_B   => { name = "ldabsb";  desc = ldabs_str(name, insn); },
-        ebpf::LD_ABS_H   => { name = "ldabsh";  desc = ldabs_str(name, insn); },
-        ebpf::LD_ABS_W   => { name = "ldabsw";  desc = ldabs_str(name, insn); },
-        ebpf::LD_ABS_DW  => { name = "ldabsdw"; desc = ldabs_str(name, insn); },
-        ebpf::LD_IND_B   => { name = "ldindb";  desc = ldind_str(name, insn); },
-        ebpf::LD_IND_H   => { name = "ldindh";  desc = ldind_str(name, insn); },
-        ebpf::LD_IND_W   => { name = "ldindw";  desc = ldind_str(name, insn); },
-        ebpf::LD_IND_DW  => { name = "ldinddw"; desc = ldind_str(name, insn); },
+        ebpf::LD_ABS_B   => { name = "ldabsb";  operands = ldabs_str(insn); },
+        ebpf::LD_ABS_H   => { name = "ldabsh";  operands = ldabs_str(insn); },
+        ebpf::LD_ABS_W   => { name = "ldabsw";  operands = ldabs_str(insn); },
+        ebpf::LD_ABS_DW  => { name = "ldabsdw"; operands = ldabs_str(insn); },
+        ebpf::LD_IND_B   => { name = "ldindb";  operands = ldind_str(insn); },
+        ebpf::LD_IND_H   => { name = "ldindh";  operands = ldind_str(insn); },
+        ebpf::LD_IND_W   => { name = "ldindw";  operands = ldind_str(insn); },
+        ebpf::LD_IND_DW  => { name = "ldinddw"; operands = ldind_str(insn); },
 
-        ebpf::LD_DW_IMM  => { name = "lddw"; desc = format!("{} r{:}, {:#x}", name, insn.dst, insn.imm); },
+        ebpf::LD_DW_IMM  => { name = "lddw"; operands = format!("r{:}, {:#x}", insn.dst, insn.imm); },
 
         // BPF_LDX class
-        ebpf::LD_B_REG   => { name = "ldxb";  desc = ld_reg_str(name, insn); },
-        ebpf::LD_H_REG   => { name = "ldxh";  desc = ld_reg_str(name, insn); },
-        ebpf::LD_W_REG   => { name = "ldxw";  desc = ld_reg_str(name, insn); },
-        ebpf::LD_DW_REG  => { name = "ldxdw"; desc = ld_reg_str(name, insn); },
+        ebpf::LD_B_REG   => { name = "ldxb";  operands = ld_reg_str(insn); },
+        ebpf::LD_H_REG   => { name = "ldxh";  operands = ld_reg_str(insn); },
+        ebpf::LD_W_REG   => { name = "ldxw";  operands = ld_reg_str(insn); },
+        ebpf::LD_DW_REG  => { name = "ldxdw"; operands = ld_reg_str(insn); },
 
         // BPF_ST class
-        ebpf::ST_B_IMM   => { name = "stb";  desc = ld_st_imm_str(name, insn); },
-        ebpf::ST_H_IMM   => { name = "sth";  desc = ld_st_imm_str(name, insn); },
-        ebpf::ST_W_IMM   => { name = "stw";  desc = ld_st_imm_str(name, insn); },
-        ebpf::ST_DW_IMM  => { name = "stdw"; desc = ld_st_imm_str(name, insn); },
+        ebpf::ST_B_IMM   => { name = "stb";  operands = ld_st_imm_str(insn); },
+        ebpf::ST_H_IMM   => { name = "sth";  operands = ld_st_imm_str(insn); },
+        ebpf::ST_W_IMM   => { name = "stw";  operands = ld_st_imm_str(insn); },
+        ebpf::ST_DW_IMM  => { name = "stdw"; operands = ld_st_imm_str(insn); },
 
         // BPF_STX class
-        ebpf::ST_B_REG   => { name = "stxb";      desc = st_reg_str(name, insn); },
-        ebpf::ST_H_REG   => { name = "stxh";      desc = st_reg_str(name, insn); },
-        ebpf::ST_W_REG   => { name = "stxw";      desc = st_reg_str(name, insn); },
-        ebpf::ST_DW_REG  => { name = "stxdw";     desc = st_reg_str(name, insn); },
-        ebpf::ST_W_XADD  => { name = "stxxaddw";  desc = st_reg_str(name, insn); },
-        ebpf::ST_DW_XADD => { name = "stxxadddw"; desc = st_reg_str(name, insn); },
+        ebpf::ST_B_REG   => { name = "stxb";      operands = st_reg_str(insn); },
+        ebpf::ST_H_REG   => { name = "stxh";      operands = st_reg_str(insn); },
+        ebpf::ST_W_REG   => { name = "stxw";      operands = st_reg_str(insn); },
+        ebpf::ST_DW_REG  => { name = "stxdw";     operands = st_reg_str(insn); },
+        ebpf::ST_W_XADD  => { name = "stxxaddw";  operands = st_reg_str(insn); },
+        ebpf::ST_DW_XADD => { name = "stxxadddw"; operands = st_reg_str(insn); },
 
         // BPF_ALU class
-        ebpf::ADD32_IMM  => { name = "add32";  desc = alu_imm_str(name, insn);  },
-        ebpf::ADD32_REG  => { name = "add32";  desc = alu_reg_str(name, insn);  },
-        ebpf::SUB32_IMM  => { name = "sub32";  desc = alu_imm_str(name, insn);  },
-        ebpf::SUB32_REG  => { name = "sub32";  desc = alu_reg_str(name, insn);  },
-        ebpf::MUL32_IMM  => { name = "mul32";  desc = alu_imm_str(name, insn);  },
-        ebpf::MUL32_REG  => { name = "mul32";  desc = alu_reg_str(name, insn);  },
-        ebpf::DIV32_IMM  => { name = "div32";  desc = alu_imm_str(name, insn);  },
-        ebpf::DIV32_REG  => { name = "div32";  desc = alu_reg_str(name, insn);  },
-        ebpf::SDIV32_IMM => { name = "sdiv32"; desc = alu_imm_str(name, insn);  },
-        ebpf::SDIV32_REG => { name = "sdiv32"; desc = alu_reg_str(name, insn);  },
-        ebpf::OR32_IMM   => { name = "or32";   desc = alu_imm_str(name, insn);  },
-        ebpf::OR32_REG   => { name = "or32";   desc = alu_reg_str(name, insn);  },
-        ebpf::AND32_IMM  => { name = "and32";  desc = alu_imm_str(name, insn);  },
-        ebpf::AND32_REG  => { name = "and32";  desc = alu_reg_str(name, insn);  },
-        ebpf::LSH32_IMM  => { name = "lsh32";  desc = alu_imm_str(name, insn);  },
-        ebpf::LSH32_REG  => { name = "lsh32";  desc = alu_reg_str(name, insn);  },
-        ebpf::RSH32_IMM  => { name = "rsh32";  desc = alu_imm_str(name, insn);  },
-        ebpf::RSH32_REG  => { name = "rsh32";  desc = alu_reg_str(name, insn);  },
-        ebpf::NEG32      => { name = "neg32";  desc = format!("{} r{}", name, insn.dst); },
-        ebpf::MOD32_IMM  => { name = "mod32";  desc = alu_imm_str(name, insn);  },
-        ebpf::MOD32_REG  => { name = "mod32";  desc = alu_reg_str(name, insn);  },
-        ebpf::XOR32_IMM  => { name = "xor32";  desc = alu_imm_str(name, insn);  },
-        ebpf::XOR32_REG  => { name = "xor32";  desc = alu_reg_str(name, insn);  },
-        ebpf::MOV32_IMM  => { name = "mov32";  desc = alu_imm_str(name, insn);  },
-        ebpf::MOV32_REG  => { name = "mov32";  desc = alu_reg_str(name, insn);  },
-        ebpf::ARSH32_IMM => { name = "arsh32"; desc = alu_imm_str(name, insn);  },
-        ebpf::ARSH32_REG => { name = "arsh32"; desc = alu_reg_str(name, insn);  },
-        ebpf::LE         => { name = "le";     desc = byteswap_str(name, insn); },
-        ebpf::BE         => { name = "be";     desc = byteswap_str(name, insn); },
+        ebpf::ADD32_IMM  => { name = "add32";  operands = alu_imm_str(insn);  },
+        ebpf::ADD32_REG  => { name = "add32";  operands = alu_reg_str(insn);  },
+        ebpf::SUB32_IMM  => { name = "sub32";  operands = alu_imm_str(insn);  },
+        ebpf::SUB32_REG  => { name = "sub32";  operands = alu_reg_str(insn);  },
+        ebpf::MUL32_IMM  => { name = "mul32";  operands = alu_imm_str(insn);  },
+        ebpf::MUL32_REG  => { name = "mul32";  operands = alu_reg_str(insn);  },
+        ebpf::DIV32_IMM  => { name = "div32";  operands = alu_imm_str(insn);  },
+        ebpf::DIV32_REG  => { name = "div32";  operands = alu_reg_str(insn);  },
+        ebpf::SDIV32_IMM => { name = "sdiv32"; operands = alu_imm_str(insn);  },
+        ebpf::SDIV32_REG => { name = "sdiv32"; operands = alu_reg_str(insn);  },
+        ebpf::OR32_IMM   => { name = "or32";   operands = alu_imm_str(insn);  },
+        ebpf::OR32_REG   => { name = "or32";   operands = alu_reg_str(insn);  },
+        ebpf::AND32_IMM  => { name = "and32";  operands = alu_imm_str(insn);  },
+        ebpf::AND32_REG  => { name = "and32";  operands = alu_reg_str(insn);  },
+        ebpf::LSH32_IMM  => { name = "lsh32";  operands = alu_imm_str(insn);  },
+        ebpf::LSH32_REG  => { name = "lsh32";  operands = alu_reg_str(insn);  },
+        ebpf::RSH32_IMM  => { name = "rsh32";  operands = alu_imm_str(insn);  },
+        ebpf::RSH32_REG  => { name = "rsh32";  operands = alu_reg_str(insn);  },
+        ebpf::NEG32      => { name = "neg32";  operands = format!("r{}", insn.dst); },
+        ebpf::MOD32_IMM  => { name = "mod32";  operands = alu_imm_str(insn);  },
+        ebpf::MOD32_REG  => { name = "mod32";  operands = alu_reg_str(insn);  },
+        ebpf::XOR32_IMM  => { name = "xor32";  operands = alu_imm_str(insn);  },
+        ebpf::XOR32_REG  => { name = "xor32";  operands = alu_reg_str(insn);  },
+        ebpf::MOV32_IMM  => { name = "mov32";  operands = alu_imm_str(insn);  },
+        ebpf::MOV32_REG  => { name = "mov32";  operands = alu_reg_str(insn);  },
+        ebpf::ARSH32_IMM => { name = "arsh32"; operands = alu_imm_str(insn);  },
+        ebpf::ARSH32_REG => { name = "arsh32"; operands = alu_reg_str(insn);  },
+        ebpf::LE         => { name = "le";     operands = byteswap_str(name, insn); },
+        ebpf::BE         => { name = "be";     operands = byteswap_str(name, insn); },
 
         // BPF_ALU64 class
-        ebpf::ADD64_IMM  => { name = "add64";  desc = alu_imm_str(name, insn); },
-        ebpf::ADD64_REG  => { name = "add64";  desc = alu_reg_str(name, insn); },
-        ebpf::SUB64_IMM  => { name = "sub64";  desc = alu_imm_str(name, insn); },
-        ebpf::SUB64_REG  => { name = "sub64";  desc = alu_reg_str(name, insn); },
-        ebpf::MUL64_IMM  => { name = "mul64";  desc = alu_imm_str(name, insn); },
-        ebpf::MUL64_REG  => { name = "mul64";  desc = alu_reg_str(name, insn); },
-        ebpf::DIV64_IMM  => { name = "div64";  desc = alu_imm_str(name, insn); },
-        ebpf::DIV64_REG  => { name = "div64";  desc = alu_reg_str(name, insn); },
-        ebpf::SDIV64_IMM => { name = "sdiv64"; desc = alu_imm_str(name, insn); },
-        ebpf::SDIV64_REG => { name = "sdiv64"; desc = alu_reg_str(name, insn); },
-        ebpf::OR64_IMM   => { name = "or64";   desc = alu_imm_str(name, insn); },
-        ebpf::OR64_REG   => { name = "or64";   desc = alu_reg_str(name, insn); },
-        ebpf::AND64_IMM  => { name = "and64";  desc = alu_imm_str(name, insn); },
-        ebpf::AND64_REG  => { name = "and64";  desc = alu_reg_str(name, insn); },
-        ebpf::LSH64_IMM  => { name = "lsh64";  desc = alu_imm_str(name, insn); },
-        ebpf::LSH64_REG  => { name = "lsh64";  desc = alu_reg_str(name, insn); },
-        ebpf::RSH64_IMM  => { name = "rsh64";  desc = alu_imm_str(name, insn); },
-        ebpf::RSH64_REG  => { name = "rsh64";  desc = alu_reg_str(name, insn); },
-        ebpf::NEG64      => { name = "neg64";  desc = format!("{} r{}", name, insn.dst); },
-        ebpf::MOD64_IMM  => { name = "mod64";  desc = alu_imm_str(name, insn); },
-        ebpf::MOD64_REG  => { name = "mod64";  desc = alu_reg_str(name, insn); },
-        ebpf::XOR64_IMM  => { name = "xor64";  desc = alu_imm_str(name, insn); },
-        ebpf::XOR64_REG  => { name = "xor64";  desc = alu_reg_str(name, insn); },
-        ebpf::MOV64_IMM  => { name = "mov64";  desc = alu_imm_str(name, insn); },
-        ebpf::MOV64_REG  => { name = "mov64";  desc = alu_reg_str(name, insn); },
-        ebpf::ARSH64_IMM => { name = "arsh64"; desc = alu_imm_str(name, insn); },
-        ebpf::ARSH64_REG => { name = "arsh64"; desc = alu_reg_str(name, insn); },
+        ebpf::ADD64_IMM  => { name = "add64";  operands = alu_imm_str(insn); },
+        ebpf::ADD64_REG  => { name = "add64";  operands = alu_reg_str(insn); },
+        ebpf::SUB64_IMM  => { name = "sub64";  operands = alu_imm_str(insn); },
+        ebpf::SUB64_REG  => { name = "sub64";  operands = alu_reg_str(insn); },
+        ebpf::MUL64_IMM  => { name = "mul64";  operands = alu_imm_str(insn); },
+        ebpf::MUL64_REG  => { name = "mul64";  operands = alu_reg_str(insn); },
+        ebpf::DIV64_IMM  => { name = "div64";  operands = alu_imm_str(insn); },
+        ebpf::DIV64_REG  => { name = "div64";  operands = alu_reg_str(insn); },
+        ebpf::SDIV64_IMM => { name = "sdiv64"; operands = alu_imm_str(insn); },
+        ebpf::SDIV64_REG => { name = "sdiv64"; operands = alu_reg_str(insn); },
+        ebpf::OR64_IMM   => { name = "or64";   operands = alu_imm_str(insn); },
+        ebpf::OR64_REG   => { name = "or64";   operands = alu_reg_str(insn); },
+        ebpf::AND64_IMM  => { name = "and64";  operands = alu_imm_str(insn); },
+        ebpf::AND64_REG  => { name = "and64";  operands = alu_reg_str(insn); },
+        ebpf::LSH64_IMM  => { name = "lsh64";  operands = alu_imm_str(insn); },
+        ebpf::LSH64_REG  => { name = "lsh64";  operands = alu_reg_str(insn); },
+        ebpf::RSH64_IMM  => { name = "rsh64";  operands = alu_imm_str(insn); },
+        ebpf::RSH64_REG  => { name = "rsh64";  operands = alu_reg_str(insn); },
+        ebpf::NEG64      => { name = "neg64";  operands = format!("r{}", insn.dst); },
+        ebpf::MOD64_IMM  => { name = "mod64";  operands = alu_imm_str(insn); },
+        ebpf::MOD64_REG  => { name = "mod64";  operands = alu_reg_str(insn); },
+        ebpf::XOR64_IMM  => { name = "xor64";  operands = alu_imm_str(insn); },
+        ebpf::XOR64_REG  => { name = "xor64";  operands = alu_reg_str(insn); },
+        ebpf::MOV64_IMM  => { name = "mov64";  operands = alu_imm_str(insn); },
+        ebpf::MOV64_REG  => { name = "mov64";  operands = alu_reg_str(insn); },
+        ebpf::ARSH64_IMM => { name = "arsh64"; operands = alu_imm_str(insn); },
+        ebpf::ARSH64_REG => { name = "arsh64"; operands = alu_reg_str(insn); },
 
         // BPF_JMP class
         ebpf::JA         => {
             name = "ja";
             let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
-            desc = format!("{} {}", name, resolve_label(analysis, target_pc));
+            operands = resolve_label(analysis, target_pc, jump_labels);
         },
-        ebpf::JEQ_IMM    => { name = "jeq";  desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JEQ_REG    => { name = "jeq";  desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JGT_IMM    => { name = "jgt";  desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JGT_REG    => { name = "jgt";  desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JGE_IMM    => { name = "jge";  desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JGE_REG    => { name = "jge";  desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JLT_IMM    => { name = "jlt";  desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JLT_REG    => { name = "jlt";  desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JLE_IMM    => { name = "jle";  desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JLE_REG    => { name = "jle";  desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JSET_IMM   => { name = "jset"; desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JSET_REG   => { name = "jset"; desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JNE_IMM    => { name = "jne";  desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JNE_REG    => { name = "jne";  desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JSGT_IMM   => { name = "jsgt"; desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JSGT_REG   => { name = "jsgt"; desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JSGE_IMM   => { name = "jsge"; desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JSGE_REG   => { name = "jsge"; desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JSLT_IMM   => { name = "jslt"; desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JSLT_REG   => { name = "jslt"; desc = jmp_reg_str(name, insn, analysis); },
-        ebpf::JSLE_IMM   => { name = "jsle"; desc = jmp_imm_str(name, insn, analysis); },
-        ebpf::JSLE_REG   => { name = "jsle"; desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JEQ_IMM    => { name = "jeq";  operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JEQ_REG    => { name = "jeq";  operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JGT_IMM    => { name = "jgt";  operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JGT_REG    => { name = "jgt";  operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JGE_IMM    => { name = "jge";  operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JGE_REG    => { name = "jge";  operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JLT_IMM    => { name = "jlt";  operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JLT_REG    => { name = "jlt";  operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JLE_IMM    => { name = "jle";  operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JLE_REG    => { name = "jle";  operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JSET_IMM   => { name = "jset"; operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JSET_REG   => { name = "jset"; operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JNE_IMM    => { name = "jne";  operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JNE_REG    => { name = "jne";  operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JSGT_IMM   => { name = "jsgt"; operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JSGT_REG   => { name = "jsgt"; operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JSGE_IMM   => { name = "jsge"; operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JSGE_REG   => { name = "jsge"; operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JSLT_IMM   => { name = "jslt"; operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JSLT_REG   => { name = "jslt"; operands = jmp_reg_str(insn, analysis, jump_labels); },
+        ebpf::JSLE_IMM   => { name = "jsle"; operands = jmp_imm_str(insn, analysis, jump_labels); },
+        ebpf::JSLE_REG   => { name = "jsle"; operands = jmp_reg_str(insn, analysis, jump_labels); },
         ebpf::CALL_IMM   => {
-            desc = if let Some(syscall_name) = analysis.executable.get_syscall_symbols().get(&(insn.imm as u32)) {
+            operands = if let Some(syscall_name) = analysis.executable.get_syscall_symbols().get(&(insn.imm as u32)) {
                 name = "syscall";
-                format!("{} {}", name, syscall_name)
+                syscall_name.to_string()
             } else {
                 name = "call";
                 if let Some(target_pc) = analysis
                     .executable
                     .lookup_bpf_function(insn.imm as u32) {
-                    format!("{} {}", name, resolve_label(analysis, target_pc))
+                    resolve_label(analysis, target_pc, jump_labels)
                 } else {
-                    format!("{} [invalid]", name)
+                    "[invalid]".to_string()
                 }
             };
         },
-        ebpf::CALL_REG   => { name = "callx"; desc = format!("{} r{}", name, insn.imm); },
-        ebpf::EXIT       => { name = "exit"; desc = name.to_string(); },
+        ebpf::CALL_REG   => { name = "callx"; operands = format!("r{}", insn.imm); },
+        ebpf::EXIT       => { name = "exit"; operands = String::new(); },
 
-        _                => { name = "unknown"; desc = format!("{} opcode={:#x}", name, insn.opc); },
+        _                => { name = "unknown"; operands = format!("opcode={:#x}", insn.opc); },
+    };
+    let mnemonic = match insn.opc {
+        ebpf::LE | ebpf::BE => format!("{}{}", name, insn.imm),
+        _ => name.to_string(),
     };
-    desc
+    DisassembledInstruction {
+        pc: insn.ptr,
+        opcode: insn.opc,
+        dst: insn.dst,
+        src: insn.src,
+        off: insn.off,
+        imm: insn.imm,
+        mnemonic,
+        operands,
+    }
 }
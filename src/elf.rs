@@ -12,10 +12,12 @@ extern crate scroll;
 use crate::{
     aligned_memory::AlignedMemory,
     ebpf::{self, EF_SBF_V2, INSN_SIZE},
+    elf_writer::{write_elf, ElfImage},
     error::{EbpfError, UserDefinedError},
-    jit::JitProgram,
+    jit::{JitCompiler, JitProgram},
     memory_region::MemoryRegion,
-    vm::{Config, InstructionMeter, SyscallRegistry},
+    verifier::check_call_graph,
+    vm::{Config, EbpfVm, InstructionMeter, ProgramResult, SyscallRegistry, Verifier},
 };
 use byteorder::{ByteOrder, LittleEndian};
 use goblin::{
@@ -24,6 +26,7 @@ use goblin::{
 };
 use std::{
     collections::{btree_map::Entry, BTreeMap},
+    convert::TryFrom,
     fmt::Debug,
     mem,
     ops::Range,
@@ -192,7 +195,7 @@ const BYTE_LENGTH_IMMEDIATE: usize = 4;
 /// BPF relocation types.
 #[allow(non_camel_case_types)]
 #[derive(Debug, PartialEq, Copy, Clone)]
-enum BpfRelocationType {
+pub(crate) enum BpfRelocationType {
     /// No relocation, placeholder
     R_Bpf_None = 0,
     /// R_BPF_64_64 relocation type is used for ld_imm64 instruction.
@@ -264,6 +267,17 @@ pub(crate) enum Section {
     Borrowed(usize, Range<usize>),
 }
 
+/// Estimated output of the JIT compiler, see `Executable::estimate_jit_cost()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitEstimate {
+    /// Number of instructions to compile, compilation time is roughly proportional to it
+    pub instructions: usize,
+    /// Estimated length of the machine code in bytes
+    pub bytes: usize,
+    /// Upper bound of the length of the machine code in bytes, which the JIT allocates
+    pub max_bytes: usize,
+}
+
 /// Elf loader/relocator
 #[derive(Debug, PartialEq)]
 pub struct Executable<E: UserDefinedError, I: InstructionMeter> {
@@ -300,17 +314,193 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
             }
         };
 
-        let offset = self
-            .text_section_info
-            .vaddr
-            .saturating_sub(ebpf::MM_PROGRAM_START)
-            .saturating_sub(ro_offset as u64) as usize;
+        let offset = self.get_text_offset(ro_offset);
         (
             self.text_section_info.vaddr,
             &ro_section[offset..offset.saturating_add(self.text_section_info.offset_range.len())],
         )
     }
 
+    /// Returns the raw instruction at `pc`, for `lddw` with the full 64 bit immediate
+    ///
+    /// Returns None if `pc` is out of range or the second slot of a `lddw`. See
+    /// `Analysis::instruction_at()` for the instruction with mnemonic and operands.
+    pub fn raw_instruction_at(&self, pc: usize) -> Option<ebpf::Insn> {
+        let (_program_vm_addr, program) = self.get_text_bytes();
+        let insn_count = program.len() / ebpf::INSN_SIZE;
+        let mut insn_ptr = 0;
+        while insn_ptr < pc && insn_ptr < insn_count {
+            insn_ptr = insn_ptr.saturating_add(
+                if program[insn_ptr.saturating_mul(ebpf::INSN_SIZE)] == ebpf::LD_DW_IMM {
+                    2
+                } else {
+                    1
+                },
+            );
+        }
+        if insn_ptr != pc || pc >= insn_count {
+            return None;
+        }
+        let mut insn = ebpf::get_insn_unchecked(program, pc);
+        if insn.opc == ebpf::LD_DW_IMM {
+            if pc.saturating_add(1) >= insn_count {
+                return None;
+            }
+            ebpf::augment_lddw_unchecked(program, &mut insn);
+        }
+        Some(insn)
+    }
+
+    fn get_text_bytes_mut(&mut self) -> &mut [u8] {
+        let ro_offset = match &self.ro_section {
+            Section::Owned(offset, _data) => *offset,
+            Section::Borrowed(offset, _byte_range) => *offset,
+        };
+        let offset = self.get_text_offset(ro_offset);
+        let len = self.text_section_info.offset_range.len();
+        let ro_section = match &mut self.ro_section {
+            Section::Owned(_offset, data) => data.as_mut_slice(),
+            Section::Borrowed(_offset, byte_range) => {
+                &mut self.elf_bytes.as_slice_mut()[byte_range.clone()]
+            }
+        };
+        &mut ro_section[offset..offset.saturating_add(len)]
+    }
+
+    fn get_text_offset(&self, ro_offset: usize) -> usize {
+        self.text_section_info
+            .vaddr
+            .saturating_sub(ebpf::MM_PROGRAM_START)
+            .saturating_sub(ro_offset as u64) as usize
+    }
+
+    /// Overwrites the instruction at `pc` in the text section
+    ///
+    /// Both slots of an `lddw` are off limits, as is patching in a new `lddw`. The `imm` of a
+    /// patched in call to a BPF function is relative like in the ELF and is resolved to the hash
+    /// of the target, unless it already names a registered syscall or function. The patched text
+    /// is verified if a `verifier` is given and its call graph checked, the patch is reverted if
+    /// either fails. Any JIT compiled program is discarded, as it no longer matches the text.
+    pub fn patch_instruction(
+        &mut self,
+        pc: usize,
+        bytes: &[u8; INSN_SIZE],
+        verifier: Option<&dyn Verifier>,
+    ) -> Result<(), EbpfError<E>> {
+        let (_text_vaddr, text_bytes) = self.get_text_bytes();
+        let insn_count = text_bytes.len().checked_div(INSN_SIZE).unwrap_or(0);
+        if pc >= insn_count {
+            return Err(ElfError::ValueOutOfBounds.into());
+        }
+        let dump_pc = pc.saturating_add(ebpf::ELF_INSN_DUMP_OFFSET);
+        let mut insn_ptr = 0;
+        while insn_ptr <= pc {
+            let opc = text_bytes[insn_ptr.saturating_mul(INSN_SIZE)];
+            let slots = if opc == ebpf::LD_DW_IMM { 2 } else { 1 };
+            if opc == ebpf::LD_DW_IMM && insn_ptr.saturating_add(1) >= pc {
+                return Err(EbpfError::InvalidInstruction(dump_pc));
+            }
+            insn_ptr = insn_ptr.saturating_add(slots);
+        }
+        let mut insn = ebpf::get_insn(bytes, 0);
+        if insn.opc == ebpf::LD_DW_IMM {
+            return Err(EbpfError::InvalidInstruction(dump_pc));
+        }
+        let original_bpf_functions = self.bpf_functions.clone();
+        if insn.opc == ebpf::CALL_IMM
+            && insn.imm != -1
+            && !(self.config.static_syscalls && insn.src == 0)
+            && self
+                .syscall_registry
+                .lookup_syscall(insn.imm as u32)
+                .is_none()
+            && self.lookup_bpf_function(insn.imm as u32).is_none()
+        {
+            let target_pc = (pc as isize)
+                .saturating_add(1)
+                .saturating_add(insn.imm as isize);
+            if target_pc < 0 || target_pc >= insn_count as isize {
+                return Err(ElfError::RelativeJumpOutOfBounds(dump_pc).into());
+            }
+            let name = if self.config.enable_symbol_and_section_labels {
+                format!("function_{}", target_pc)
+            } else {
+                String::default()
+            };
+            insn.imm = register_bpf_function(
+                &self.config,
+                &mut self.bpf_functions,
+                &self.syscall_registry,
+                target_pc as usize,
+                name,
+            )? as i64;
+        }
+        let range = pc.saturating_mul(INSN_SIZE)..pc.saturating_add(1).saturating_mul(INSN_SIZE);
+        let mut original = [0u8; INSN_SIZE];
+        original.copy_from_slice(&self.get_text_bytes_mut()[range.clone()]);
+        self.get_text_bytes_mut()[range.clone()].copy_from_slice(&insn.to_array());
+        let result = verifier
+            .map_or(Ok(()), |verifier| {
+                verifier.verify(self.get_text_bytes().1, &self.config)
+            })
+            .and_then(|()| check_call_graph(self));
+        if let Err(err) = result {
+            self.get_text_bytes_mut()[range].copy_from_slice(&original);
+            self.bpf_functions = original_bpf_functions;
+            return Err(err.into());
+        }
+        self.compiled_program = None;
+        Ok(())
+    }
+
+    /// Rewrites the immediates in the text section
+    ///
+    /// Does not verify the result, only call this before the executable is verified.
+    /// See `Executable::from_elf_with_rewriter()`.
+    pub(crate) fn rewrite_immediates<F>(
+        &mut self,
+        parameters: &BTreeMap<String, i64>,
+        mut rewriter: F,
+    ) -> Result<(), EbpfError<E>>
+    where
+        F: FnMut(usize, &ebpf::Insn, &BTreeMap<String, i64>) -> Option<i64>,
+    {
+        let mut text_bytes = self.get_text_bytes().1.to_vec();
+        let insn_count = text_bytes.len().checked_div(INSN_SIZE).unwrap_or(0);
+        let mut insn_ptr = 0;
+        while insn_ptr < insn_count {
+            let mut insn = ebpf::get_insn(&text_bytes, insn_ptr);
+            let dump_pc = insn_ptr.saturating_add(ebpf::ELF_INSN_DUMP_OFFSET);
+            let is_lddw = insn.opc == ebpf::LD_DW_IMM;
+            if is_lddw {
+                if insn_ptr.saturating_add(1) >= insn_count {
+                    return Err(EbpfError::InvalidInstruction(dump_pc));
+                }
+                ebpf::augment_lddw_unchecked(&text_bytes, &mut insn);
+            }
+            if let Some(imm) = rewriter(insn_ptr, &insn, parameters) {
+                let offset = insn_ptr
+                    .saturating_mul(INSN_SIZE)
+                    .saturating_add(BYTE_OFFSET_IMMEDIATE);
+                if is_lddw {
+                    LittleEndian::write_u32(&mut text_bytes[offset..], imm as u32);
+                    LittleEndian::write_u32(
+                        &mut text_bytes[offset.saturating_add(INSN_SIZE)..],
+                        (imm as u64).wrapping_shr(32) as u32,
+                    );
+                } else if insn.opc == ebpf::CALL_IMM || i32::try_from(imm).is_err() {
+                    return Err(EbpfError::InvalidInstruction(dump_pc));
+                } else {
+                    LittleEndian::write_i32(&mut text_bytes[offset..], imm as i32);
+                }
+            }
+            insn_ptr = insn_ptr.saturating_add(if is_lddw { 2 } else { 1 });
+        }
+        self.get_text_bytes_mut().copy_from_slice(&text_bytes);
+        self.compiled_program = None;
+        Ok(())
+    }
+
     /// Get the concatenated read-only sections (including the text section)
     pub fn get_ro_section(&self) -> &[u8] {
         match &self.ro_section {
@@ -339,6 +529,17 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         self.bpf_functions.get(&hash).map(|(pc, _name)| *pc)
     }
 
+    /// Get the name of the BPF function which contains the instruction at `pc`
+    /// (if debug symbols are not stripped)
+    pub fn lookup_function_name(&self, pc: usize) -> Option<&str> {
+        self.bpf_functions
+            .values()
+            .filter(|(function_pc, _name)| *function_pc <= pc)
+            .max_by_key(|(function_pc, _name)| *function_pc)
+            .map(|(_pc, name)| name.as_str())
+            .filter(|name| !name.is_empty())
+    }
+
     /// Get the syscall registry
     pub fn get_syscall_registry(&self) -> &SyscallRegistry {
         &self.syscall_registry
@@ -349,6 +550,23 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         self.compiled_program.as_ref()
     }
 
+    /// Estimate the size of the JIT compiled program without compiling it
+    pub fn estimate_jit_cost(&self) -> JitEstimate {
+        crate::jit::estimate_jit_cost(self.get_text_bytes().1, &self.config)
+    }
+
+    /// Whether the program is large enough for JIT compilation to pay off,
+    /// see Config::jit_threshold_instructions
+    pub fn is_jit_recommended(&self) -> bool {
+        self.get_text_bytes().1.len() / ebpf::INSN_SIZE >= self.config.jit_threshold_instructions
+    }
+
+    /// Whether the JIT supports the host, otherwise jit_compile() fails with
+    /// EbpfError::JitNotSupported
+    pub const fn is_jit_supported() -> bool {
+        JitCompiler::is_supported()
+    }
+
     /// JIT compile the executable
     pub fn jit_compile(executable: &mut Pin<Box<Self>>) -> Result<(), EbpfError<E>> {
         // TODO: Turn back to `executable: &mut self` once Self::report_unresolved_symbol() is gone
@@ -356,6 +574,39 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         Ok(())
     }
 
+    /// Runs the program once per input, which is mapped writable at MM_INPUT_START
+    ///
+    /// A single VM is reused for all inputs, running the JIT compiled program if there is one.
+    /// Each input is copied into a shared buffer and the heap and the stack are zeroed before
+    /// every run. Syscalls are bound to clones of `syscall_context` and every run consumes from
+    /// a fresh meter returned by `new_instruction_meter`. Only errors of setting up the VM fail
+    /// the whole batch.
+    pub fn run_batch<C: Clone>(
+        executable: &Pin<Box<Self>>,
+        inputs: &[&[u8]],
+        heap: &mut [u8],
+        syscall_context: C,
+        mut new_instruction_meter: impl FnMut() -> I,
+    ) -> Result<Vec<ProgramResult<E>>, EbpfError<E>> {
+        let max_len = inputs.iter().map(|input| input.len()).max().unwrap_or(0);
+        let mut buffer = AlignedMemory::new_with_size(max_len, ebpf::HOST_ALIGN);
+        let input_region = MemoryRegion::new_writable(buffer.as_slice_mut(), ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(executable, heap, vec![input_region])?;
+        vm.bind_syscall_context_objects(syscall_context)?;
+        let input_index = ebpf::MM_INPUT_START
+            .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+            .unwrap_or(0) as usize;
+        let mut results = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            buffer.as_slice_mut()[..input.len()].copy_from_slice(input);
+            vm.resize_region(input_index, input.len() as u64)?;
+            heap.fill(0);
+            vm.zero_stack();
+            results.push(vm.execute_program(&mut new_instruction_meter()));
+        }
+        Ok(results)
+    }
+
     /// Report information on a symbol that failed to be resolved
     pub fn report_unresolved_symbol(&self, insn_offset: usize) -> Result<u64, EbpfError<E>> {
         let file_offset = insn_offset
@@ -407,6 +658,81 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         &self.syscall_symbols
     }
 
+    /// Serialize into an ELF which can be loaded again
+    ///
+    /// The read-only sections keep their addresses. Resolved calls are turned back into relative
+    /// calls and syscalls into relocations, which requires the syscall names, so unless static
+    /// syscalls are used the executable must be loaded with `enable_symbol_and_section_labels`.
+    pub fn to_elf(&self) -> Result<Vec<u8>, ElfError> {
+        let ro_offset = match &self.ro_section {
+            Section::Owned(offset, _data) => *offset,
+            Section::Borrowed(offset, _byte_range) => *offset,
+        };
+        let mut ro_data = self.get_ro_section().to_vec();
+        let text_start = self.get_text_offset(ro_offset);
+        let text_range =
+            text_start..text_start.saturating_add(self.text_section_info.offset_range.len());
+        let text = ro_data
+            .get_mut(text_range.clone())
+            .ok_or(ElfError::ValueOutOfBounds)?;
+        let instruction_count = text
+            .len()
+            .checked_div(ebpf::INSN_SIZE)
+            .ok_or(ElfError::ValueOutOfBounds)?;
+        let mut syscall_relocations = Vec::new();
+        let mut insn_ptr = 0;
+        while insn_ptr < instruction_count {
+            let mut insn = ebpf::get_insn(text, insn_ptr);
+            if insn.opc == ebpf::CALL_IMM && !(self.config.static_syscalls && insn.src == 0) {
+                let hash = insn.imm as u32;
+                if let Some((target_pc, _name)) = self.bpf_functions.get(&hash) {
+                    insn.imm = (*target_pc as i64)
+                        .saturating_sub(insn_ptr as i64)
+                        .saturating_sub(1);
+                } else if let Some(name) = self.syscall_symbols.get(&hash) {
+                    insn.imm = -1;
+                    syscall_relocations.push((insn_ptr, name.as_str()));
+                } else {
+                    return Err(ElfError::UnresolvedSymbol(
+                        format!("{:#x}", hash),
+                        insn_ptr.saturating_add(ebpf::ELF_INSN_DUMP_OFFSET),
+                        self.text_section_info
+                            .offset_range
+                            .start
+                            .saturating_add(insn_ptr.saturating_mul(ebpf::INSN_SIZE)),
+                    ));
+                }
+                let offset = insn_ptr.saturating_mul(ebpf::INSN_SIZE);
+                text[offset..offset.saturating_add(ebpf::INSN_SIZE)]
+                    .copy_from_slice(&insn.to_array());
+            }
+            insn_ptr = insn_ptr.saturating_add(if insn.opc == ebpf::LD_DW_IMM { 2 } else { 1 });
+        }
+        let entrypoint_pc = self
+            .bpf_functions
+            .get(&ebpf::hash_symbol_name(b"entrypoint"))
+            .map(|(pc, _name)| *pc)
+            .ok_or(ElfError::InvalidEntrypoint)?;
+        Ok(write_elf(ElfImage {
+            ro_addr: ro_offset,
+            ro_data,
+            text_range,
+            entrypoint_pc,
+            functions: self
+                .bpf_functions
+                .values()
+                .filter(|(_pc, name)| !name.is_empty())
+                .map(|(pc, name)| (*pc, name.as_str()))
+                .collect(),
+            syscall_relocations,
+            e_flags: if self.config.dynamic_stack_frames {
+                EF_SBF_V2
+            } else {
+                0
+            },
+        }))
+    }
+
     /// Create from raw text section bytes (list of instructions)
     pub fn new_from_text_bytes(
         config: Config,
@@ -1109,7 +1435,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
                         // Else it's a syscall
                         let hash = syscall_cache
                             .entry(symbol.st_name)
-                            .or_insert_with(|| (ebpf::hash_symbol_name(name.as_bytes()), name))
+                            .or_insert_with(|| (syscall_registry.hash_name(name.as_bytes()), name))
                             .0;
                         if config.reject_broken_elfs
                             && syscall_registry.lookup_syscall(hash).is_none()
@@ -2070,6 +2396,6 @@ mod test {
             Executable::jit_compile(&mut executable).unwrap();
         }
 
-        assert_eq!(18640, executable.mem_size());
+        assert_eq!(19064, executable.mem_size());
     }
 }
@@ -0,0 +1,356 @@
+#![allow(clippy::integer_arithmetic)]
+//! Serializes an executable into an ELF which the loader accepts
+
+use crate::{ebpf::INSN_SIZE, elf::BpfRelocationType};
+use goblin::elf::{
+    dynamic::{DT_NULL, DT_REL, DT_RELENT, DT_RELSZ, DT_STRSZ, DT_STRTAB, DT_SYMENT, DT_SYMTAB},
+    header::{EM_BPF, ET_DYN},
+    program_header::{PF_R, PT_DYNAMIC, PT_LOAD},
+    section_header::{
+        SHF_ALLOC, SHF_EXECINSTR, SHT_DYNAMIC, SHT_DYNSYM, SHT_PROGBITS, SHT_REL, SHT_STRTAB,
+        SHT_SYMTAB,
+    },
+};
+use std::ops::Range;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+const REL_SIZE: usize = 16;
+const DYN_SIZE: usize = 16;
+const STB_GLOBAL_STT_NOTYPE: u8 = 0x10;
+const STB_GLOBAL_STT_FUNC: u8 = 0x12;
+
+/// Contents of the ELF to be written
+pub(crate) struct ElfImage<'a> {
+    /// Offset of the read-only data from MM_PROGRAM_START
+    pub ro_addr: usize,
+    /// Read-only data including the text section
+    pub ro_data: Vec<u8>,
+    /// Location of the text section in `ro_data`
+    pub text_range: Range<usize>,
+    /// Instruction offset of the entrypoint
+    pub entrypoint_pc: usize,
+    /// Instruction offsets and names of the functions
+    pub functions: Vec<(usize, &'a str)>,
+    /// Instruction offsets and names of the syscalls which need to be relocated
+    pub syscall_relocations: Vec<(usize, &'a str)>,
+    /// Value of e_flags
+    pub e_flags: u32,
+}
+
+struct SectionHeader {
+    name: usize,
+    sh_type: u32,
+    flags: u64,
+    addr: usize,
+    offset: usize,
+    size: usize,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: usize,
+}
+
+fn align(bytes: &mut Vec<u8>) {
+    bytes.resize((bytes.len() + 7) & !7, 0);
+}
+
+fn push_name(strtab: &mut Vec<u8>, name: &str) -> usize {
+    let offset = strtab.len();
+    strtab.extend_from_slice(name.as_bytes());
+    strtab.push(0);
+    offset
+}
+
+fn push_symbol(bytes: &mut Vec<u8>, name: usize, info: u8, shndx: u16, value: usize) {
+    bytes.extend_from_slice(&(name as u32).to_le_bytes());
+    bytes.push(info);
+    bytes.push(0);
+    bytes.extend_from_slice(&shndx.to_le_bytes());
+    bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    bytes.extend_from_slice(&0u64.to_le_bytes());
+}
+
+/// Writes the ELF
+///
+/// The read-only sections are placed at their original addresses. If syscalls need to be
+/// relocated, a program header maps the whole file at address 0 so that the dynamic section
+/// and the relocations can refer to file offsets.
+pub(crate) fn write_elf(mut image: ElfImage) -> Vec<u8> {
+    // Zeros in front of the first section are recreated by the loader
+    let skip = image.ro_data[..image.text_range.start]
+        .iter()
+        .take_while(|byte| **byte == 0)
+        .count();
+    image.ro_data.drain(..skip);
+    image.ro_addr += skip;
+    image.text_range = image.text_range.start - skip..image.text_range.end - skip;
+
+    let mut shstrtab = vec![0];
+    let mut section_headers = vec![SectionHeader {
+        name: 0,
+        sh_type: 0,
+        flags: 0,
+        addr: 0,
+        offset: 0,
+        size: 0,
+        link: 0,
+        info: 0,
+        align: 0,
+        entsize: 0,
+    }];
+    let phnum = if image.syscall_relocations.is_empty() {
+        0
+    } else {
+        2
+    };
+
+    // The loader expects the file offsets to match the addresses, which is only possible if the
+    // headers leave room for it
+    let headers_len = EHDR_SIZE + phnum * PHDR_SIZE;
+    let ro_file_offset = if image.ro_addr >= headers_len {
+        image.ro_addr
+    } else {
+        headers_len
+    };
+    let mut bytes = vec![0; ro_file_offset];
+    bytes.extend_from_slice(&image.ro_data);
+    let text_flags = (SHF_ALLOC | SHF_EXECINSTR) as u64;
+    let mut ro_sections = vec![(".text", image.text_range.clone(), text_flags, 8)];
+    if image.text_range.start > 0 {
+        ro_sections.insert(
+            0,
+            (".rodata", 0..image.text_range.start, SHF_ALLOC as u64, 1),
+        );
+    }
+    if image.text_range.end < image.ro_data.len() {
+        let range = image.text_range.end..image.ro_data.len();
+        ro_sections.push((".rodata", range, SHF_ALLOC as u64, 1));
+    }
+    let mut text_index = 0;
+    for (name, range, flags, align) in ro_sections {
+        if flags == text_flags {
+            text_index = section_headers.len();
+        }
+        section_headers.push(SectionHeader {
+            name: push_name(&mut shstrtab, name),
+            sh_type: SHT_PROGBITS,
+            flags,
+            addr: image.ro_addr + range.start,
+            offset: ro_file_offset + range.start,
+            size: range.len(),
+            link: 0,
+            info: 0,
+            align,
+            entsize: 0,
+        });
+    }
+    let text_addr = image.ro_addr + image.text_range.start;
+    let text_file_offset = ro_file_offset + image.text_range.start;
+
+    // Function symbols
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; SYM_SIZE];
+    for (pc, name) in image.functions.iter() {
+        let name = push_name(&mut strtab, name);
+        push_symbol(
+            &mut symtab,
+            name,
+            STB_GLOBAL_STT_FUNC,
+            text_index as u16,
+            text_addr + pc * INSN_SIZE,
+        );
+    }
+    align(&mut bytes);
+    let strtab_index = section_headers.len() + 1;
+    section_headers.push(SectionHeader {
+        name: push_name(&mut shstrtab, ".symtab"),
+        sh_type: SHT_SYMTAB,
+        flags: 0,
+        addr: 0,
+        offset: bytes.len(),
+        size: symtab.len(),
+        link: strtab_index as u32,
+        info: 1,
+        align: 8,
+        entsize: SYM_SIZE,
+    });
+    bytes.extend_from_slice(&symtab);
+    section_headers.push(SectionHeader {
+        name: push_name(&mut shstrtab, ".strtab"),
+        sh_type: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        offset: bytes.len(),
+        size: strtab.len(),
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+    bytes.extend_from_slice(&strtab);
+
+    // Syscall relocations
+    let mut dynamic_range = 0..0;
+    if !image.syscall_relocations.is_empty() {
+        let mut dynstr = vec![0];
+        let mut dynsym = vec![0; SYM_SIZE];
+        let mut symbol_indices: Vec<&str> = Vec::new();
+        let mut rel_dyn = Vec::new();
+        for (pc, name) in image.syscall_relocations.iter() {
+            let symbol_index = match symbol_indices.iter().position(|known| known == name) {
+                Some(index) => index + 1,
+                None => {
+                    let name_offset = push_name(&mut dynstr, name);
+                    push_symbol(&mut dynsym, name_offset, STB_GLOBAL_STT_NOTYPE, 0, 0);
+                    symbol_indices.push(name);
+                    symbol_indices.len()
+                }
+            };
+            let r_offset = text_file_offset + pc * INSN_SIZE;
+            rel_dyn.extend_from_slice(&(r_offset as u64).to_le_bytes());
+            rel_dyn.extend_from_slice(
+                &(((symbol_index as u64) << 32) | BpfRelocationType::R_Bpf_64_32 as u64)
+                    .to_le_bytes(),
+            );
+        }
+
+        align(&mut bytes);
+        let dynsym_offset = bytes.len();
+        let dynstr_index = section_headers.len() + 1;
+        section_headers.push(SectionHeader {
+            name: push_name(&mut shstrtab, ".dynsym"),
+            sh_type: SHT_DYNSYM,
+            flags: SHF_ALLOC as u64,
+            addr: dynsym_offset,
+            offset: dynsym_offset,
+            size: dynsym.len(),
+            link: dynstr_index as u32,
+            info: 1,
+            align: 8,
+            entsize: SYM_SIZE,
+        });
+        bytes.extend_from_slice(&dynsym);
+        let dynstr_offset = bytes.len();
+        section_headers.push(SectionHeader {
+            name: push_name(&mut shstrtab, ".dynstr"),
+            sh_type: SHT_STRTAB,
+            flags: SHF_ALLOC as u64,
+            addr: dynstr_offset,
+            offset: dynstr_offset,
+            size: dynstr.len(),
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+        });
+        bytes.extend_from_slice(&dynstr);
+        align(&mut bytes);
+        let rel_dyn_offset = bytes.len();
+        section_headers.push(SectionHeader {
+            name: push_name(&mut shstrtab, ".rel.dyn"),
+            sh_type: SHT_REL,
+            flags: SHF_ALLOC as u64,
+            addr: rel_dyn_offset,
+            offset: rel_dyn_offset,
+            size: rel_dyn.len(),
+            link: (dynstr_index - 1) as u32,
+            info: 0,
+            align: 8,
+            entsize: REL_SIZE,
+        });
+        bytes.extend_from_slice(&rel_dyn);
+
+        let mut dynamic = Vec::new();
+        for (tag, value) in [
+            (DT_REL, rel_dyn_offset),
+            (DT_RELSZ, rel_dyn.len()),
+            (DT_RELENT, REL_SIZE),
+            (DT_SYMTAB, dynsym_offset),
+            (DT_SYMENT, SYM_SIZE),
+            (DT_STRTAB, dynstr_offset),
+            (DT_STRSZ, dynstr.len()),
+            (DT_NULL, 0),
+        ] {
+            dynamic.extend_from_slice(&tag.to_le_bytes());
+            dynamic.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+        dynamic_range = bytes.len()..bytes.len() + dynamic.len();
+        section_headers.push(SectionHeader {
+            name: push_name(&mut shstrtab, ".dynamic"),
+            sh_type: SHT_DYNAMIC,
+            flags: SHF_ALLOC as u64,
+            addr: dynamic_range.start,
+            offset: dynamic_range.start,
+            size: dynamic.len(),
+            link: dynstr_index as u32,
+            info: 0,
+            align: 8,
+            entsize: DYN_SIZE,
+        });
+        bytes.extend_from_slice(&dynamic);
+    }
+
+    let shstrndx = section_headers.len();
+    let shstrtab_name = push_name(&mut shstrtab, ".shstrtab");
+    section_headers.push(SectionHeader {
+        name: shstrtab_name,
+        sh_type: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        offset: bytes.len(),
+        size: shstrtab.len(),
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+    bytes.extend_from_slice(&shstrtab);
+    align(&mut bytes);
+    let shoff = bytes.len();
+    for section_header in section_headers.iter() {
+        bytes.extend_from_slice(&(section_header.name as u32).to_le_bytes());
+        bytes.extend_from_slice(&section_header.sh_type.to_le_bytes());
+        bytes.extend_from_slice(&section_header.flags.to_le_bytes());
+        bytes.extend_from_slice(&(section_header.addr as u64).to_le_bytes());
+        bytes.extend_from_slice(&(section_header.offset as u64).to_le_bytes());
+        bytes.extend_from_slice(&(section_header.size as u64).to_le_bytes());
+        bytes.extend_from_slice(&section_header.link.to_le_bytes());
+        bytes.extend_from_slice(&section_header.info.to_le_bytes());
+        bytes.extend_from_slice(&section_header.align.to_le_bytes());
+        bytes.extend_from_slice(&(section_header.entsize as u64).to_le_bytes());
+    }
+
+    let mut header = Vec::with_capacity(headers_len);
+    header.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    header.extend_from_slice(&[0; 8]);
+    header.extend_from_slice(&ET_DYN.to_le_bytes());
+    header.extend_from_slice(&EM_BPF.to_le_bytes());
+    header.extend_from_slice(&1u32.to_le_bytes());
+    header.extend_from_slice(&((text_addr + image.entrypoint_pc * INSN_SIZE) as u64).to_le_bytes());
+    header.extend_from_slice(&(if phnum > 0 { EHDR_SIZE as u64 } else { 0 }).to_le_bytes());
+    header.extend_from_slice(&(shoff as u64).to_le_bytes());
+    header.extend_from_slice(&image.e_flags.to_le_bytes());
+    header.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&(phnum as u16).to_le_bytes());
+    header.extend_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&(section_headers.len() as u16).to_le_bytes());
+    header.extend_from_slice(&(shstrndx as u16).to_le_bytes());
+    if phnum > 0 {
+        for (p_type, range) in [(PT_LOAD, 0..bytes.len()), (PT_DYNAMIC, dynamic_range)] {
+            header.extend_from_slice(&p_type.to_le_bytes());
+            header.extend_from_slice(&PF_R.to_le_bytes());
+            header.extend_from_slice(&(range.start as u64).to_le_bytes());
+            header.extend_from_slice(&(range.start as u64).to_le_bytes());
+            header.extend_from_slice(&(range.start as u64).to_le_bytes());
+            header.extend_from_slice(&(range.len() as u64).to_le_bytes());
+            header.extend_from_slice(&(range.len() as u64).to_le_bytes());
+            header.extend_from_slice(&8u64.to_le_bytes());
+        }
+    }
+    bytes[0..header.len()].copy_from_slice(&header);
+    bytes
+}
@@ -43,6 +43,36 @@ pub enum EbpfError<E: UserDefinedError> {
     /// Exceeded max BPF to BPF call depth
     #[error("exceeded max BPF to BPF call depth of {1} at instruction #{0}")]
     CallDepthExceeded(usize, usize),
+    /// Syscall returned a value outside of its declared return range
+    #[error("syscall {0:#x} returned out of range value {1}")]
+    SyscallReturnOutOfRange(u32, u64),
+    /// Syscall which was declared pure attempted to store to memory
+    #[error("pure syscall {0:#x} attempted to store to memory")]
+    PureSyscallViolation(u32),
+    /// Virtual address ranges of two memory regions overlap
+    #[error("memory regions at {0:#x} and {1:#x} overlap")]
+    OverlappingMemoryRegions(u64, u64),
+    /// Memory regions exceed Config::max_footprint_bytes (footprint, limit)
+    #[error("memory footprint of {0} bytes exceeds the limit of {1} bytes")]
+    FootprintExceeded(u64, u64),
+    /// Load of a heap byte which was never written while Config::trap_uninitialized_heap_reads is set
+    #[error("uninitialized read of heap address {1:#x} at instruction #{0}")]
+    UninitializedRead(usize, u64),
+    /// Exceeded Config::max_memory_accesses (pc, quota)
+    #[error("exceeded the quota of {1} memory accesses at instruction #{0}")]
+    MemoryAccessQuotaExceeded(usize, u64),
+    /// Copy between overlapping ranges (destination, source, length)
+    #[error("copy from {1:#x} to {0:#x} of {2} bytes overlaps")]
+    CopyOverlapping(u64, u64, u64),
+    /// Syscall did not match the next record of the replayed syscall log
+    #[error("syscall at instruction #{0} does not match the replayed syscall log")]
+    SyscallReplayMismatch(usize),
+    /// Program exited with a nonzero r0 while Config::nonzero_exit_is_failure is set
+    #[error("program failed with code {0}")]
+    ProgramFailed(u64),
+    /// Program exited with an r0 outside of Config::allowed_exit_codes
+    #[error("program exited with disallowed code {0}")]
+    InvalidExitCode(u64),
     /// Attempt to exit from root call frame
     #[error("attempted to exit root call frame")]
     ExitRootCallFrame,
@@ -66,6 +96,9 @@ pub enum EbpfError<E: UserDefinedError> {
     /// Program has not been JIT-compiled
     #[error("program has not been JIT-compiled")]
     JitNotCompiled,
+    /// JIT does not support the host
+    #[error("JIT is not supported on this host")]
+    JitNotSupported,
     /// Invalid virtual address
     #[error("invalid virtual address {0:x?}")]
     InvalidVirtualAddress(u64),
@@ -80,6 +113,12 @@ pub enum EbpfError<E: UserDefinedError> {
         "Access violation in stack frame {4} at address {2:#x} of size {3:?} by instruction #{0}"
     )]
     StackAccessViolation(usize, AccessType, u64, u64, i64),
+    /// Access which starts inside a region but ends past its end
+    #[error("Access at address {2:#x} of size {3:?} by instruction #{0} straddles the end of the {4} section")]
+    RegionStraddleViolation(usize, AccessType, u64, u64, &'static str),
+    /// Store into the context region while Config::reject_context_writes is set
+    #[error("store into the context region by instruction #{0}")]
+    ContextWriteViolation(usize),
     /// Invalid instruction
     #[error("invalid instruction at {0}")]
     InvalidInstruction(usize),
@@ -92,7 +131,20 @@ pub enum EbpfError<E: UserDefinedError> {
     /// Libc function call returned an error
     #[error("Libc calling {0} {1:?} returned error code {2}")]
     LibcInvocationFailed(&'static str, Vec<String>, i32),
+    /// Assembler error
+    #[error("Assembler error: {0}")]
+    AssemblerError(String),
     /// ELF error
     #[error("Verifier error: {0}")]
     VerifierError(#[from] VerifierError),
+    /// Manifest error
+    #[cfg(feature = "serde")]
+    #[error("Manifest error: {0}")]
+    ManifestError(#[from] crate::manifest::ManifestError),
+    /// Config option the JIT can not compile
+    #[error("JIT does not support {0}")]
+    UnsupportedJitOption(&'static str),
+    /// Config option which requires a cargo feature that is not enabled
+    #[error("{0} requires the {1} feature")]
+    FeatureNotEnabled(&'static str, &'static str),
 }
@@ -25,9 +25,9 @@ use std::{
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 
 use crate::{
-    elf::Executable,
-    vm::{Config, ProgramResult, InstructionMeter, Tracer, SYSCALL_CONTEXT_OBJECTS_OFFSET},
-    ebpf::{self, INSN_SIZE, FIRST_SCRATCH_REG, SCRATCH_REGS, FRAME_PTR_REG, MM_STACK_START, STACK_PTR_REG},
+    elf::{Executable, JitEstimate},
+    vm::{Config, ProgramResult, InstructionMeter, ShiftOverflowMode, Tracer, SYSCALL_CONTEXT_OBJECTS_OFFSET},
+    ebpf::{self, INSN_SIZE, FIRST_SCRATCH_REG, SCRATCH_REGS, FRAME_PTR_REG, STACK_PTR_REG},
     error::{UserDefinedError, EbpfError},
     memory_region::{AccessType, MemoryMapping, MemoryRegion},
     user_error::UserError,
@@ -151,6 +151,8 @@ impl Drop for JitProgramSections {
 pub struct JitProgram<E: UserDefinedError, I: InstructionMeter> {
     /// Holds and manages the protected memory
     sections: JitProgramSections,
+    /// Instruction meter positions of Config::instruction_costs, read by the compiled code
+    instruction_cost_prefix: Option<Vec<u64>>,
     /// Call this with JitProgramArgument to execute the compiled code
     pub main: unsafe fn(&ProgramResult<E>, u64, &JitProgramArgument, &mut I) -> i64,
 }
@@ -169,19 +171,82 @@ impl<E: UserDefinedError, I: InstructionMeter> PartialEq for JitProgram<E, I> {
 
 impl<E: UserDefinedError, I: InstructionMeter> JitProgram<E, I> {
     pub fn new(executable: &Pin<Box<Executable<E, I>>>) -> Result<Self, EbpfError<E>> {
+        if !JitCompiler::is_supported() {
+            return Err(EbpfError::JitNotSupported);
+        }
+        if executable.get_config().trace_syscall_accesses {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::trace_syscall_accesses",
+            ));
+        }
+        if executable.get_config().track_store_high_water_marks {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::track_store_high_water_marks",
+            ));
+        }
+        if executable.get_config().count_region_accesses {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::count_region_accesses",
+            ));
+        }
+        if executable.get_config().track_context_reads {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::track_context_reads",
+            ));
+        }
+        if executable.get_config().trap_uninitialized_heap_reads {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::trap_uninitialized_heap_reads",
+            ));
+        }
+        if executable.get_config().max_memory_accesses.is_some() {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::max_memory_accesses",
+            ));
+        }
+        if executable.get_config().track_branch_edges {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::track_branch_edges",
+            ));
+        }
+        if executable.get_config().shift_overflow_mode != ShiftOverflowMode::Mask {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::shift_overflow_mode",
+            ));
+        }
+        if executable.get_config().enable_execution_receipt {
+            return Err(EbpfError::UnsupportedJitOption(
+                "Config::enable_execution_receipt",
+            ));
+        }
+        if executable
+            .get_syscall_registry()
+            .get_exit_finalizer()
+            .is_some()
+        {
+            return Err(EbpfError::UnsupportedJitOption("exit finalizers"));
+        }
+        if executable.get_syscall_registry().has_pure_syscalls() {
+            return Err(EbpfError::UnsupportedJitOption("pure syscalls"));
+        }
+        if executable.get_syscall_registry().has_return_ranges() {
+            return Err(EbpfError::UnsupportedJitOption("syscall return ranges"));
+        }
         let program = executable.get_text_bytes().1;
         let mut jit = JitCompiler::new::<E>(program, executable.get_config())?;
         jit.compile::<E, I>(executable)?;
         let main = unsafe { mem::transmute(jit.result.text_section.as_ptr()) };
         Ok(Self {
             sections: jit.result,
+            instruction_cost_prefix: jit.instruction_cost_prefix.take(),
             main,
         })
     }
 
     pub fn mem_size(&self) -> usize {
         mem::size_of::<Self>() +
-        self.sections.mem_size()
+        self.sections.mem_size() +
+        self.instruction_cost_prefix.as_ref().map_or(0, |instruction_cost_prefix| instruction_cost_prefix.len() * mem::size_of::<u64>())
     }
 
     pub fn machine_code_length(&self) -> usize {
@@ -189,6 +254,91 @@ impl<E: UserDefinedError, I: InstructionMeter> JitProgram<E, I> {
     }
 }
 
+// Averages for `estimate_jit_cost()`, in bytes of x86_64 machine code, measured with the default
+// config. The per instruction figures are the growth of the text section when a program is
+// extended by the same instruction repeated, divided by the number of repetitions.
+
+/// Machine code which does not depend on the program: the prologue, the shared subroutines
+/// (address translation, syscall and call dispatch) and the exception handlers
+const AVERAGE_EMPTY_PROGRAM_MACHINE_CODE_LENGTH: usize = 2700;
+const AVERAGE_LD_DW_IMM_LENGTH: usize = 43;
+const AVERAGE_CALL_IMM_LENGTH: usize = 60;
+const AVERAGE_CALL_REG_LENGTH: usize = 74;
+const AVERAGE_EXIT_LENGTH: usize = 40;
+const AVERAGE_JA_LENGTH: usize = 32;
+/// Byte swaps and negations
+const AVERAGE_UNARY_ALU_LENGTH: usize = 4;
+/// Loads and stores, including the address translation call
+const AVERAGE_MEMORY_ACCESS_LENGTH: usize = 24;
+/// Conditional jumps, including the instruction meter check
+const AVERAGE_CONDITIONAL_JUMP_LENGTH: usize = 47;
+const AVERAGE_MUL_LENGTH: usize = 20;
+/// Division by a register, including the checks for zero and overflow
+const AVERAGE_DIV_REG_LENGTH: usize = 35;
+const AVERAGE_DIV_IMM_LENGTH: usize = 23;
+const AVERAGE_ALU_REG_LENGTH: usize = 3;
+const AVERAGE_ALU_IMM_LENGTH: usize = 7;
+/// Added to 32 bit ALU operations for the zero extension of the result
+const AVERAGE_ALU32_ZERO_EXTENSION_LENGTH: usize = 3;
+
+/// Average machine code length of an instruction, measured with the default config
+fn average_machine_code_length(opc: u8) -> usize {
+    match opc {
+        ebpf::LD_DW_IMM => AVERAGE_LD_DW_IMM_LENGTH,
+        ebpf::CALL_IMM => AVERAGE_CALL_IMM_LENGTH,
+        ebpf::CALL_REG => AVERAGE_CALL_REG_LENGTH,
+        ebpf::EXIT => AVERAGE_EXIT_LENGTH,
+        ebpf::JA => AVERAGE_JA_LENGTH,
+        ebpf::LE | ebpf::BE | ebpf::NEG32 | ebpf::NEG64 => AVERAGE_UNARY_ALU_LENGTH,
+        _ => match opc & ebpf::BPF_CLS_MASK {
+            ebpf::BPF_LD | ebpf::BPF_LDX | ebpf::BPF_ST | ebpf::BPF_STX => {
+                AVERAGE_MEMORY_ACCESS_LENGTH
+            }
+            ebpf::BPF_JMP => AVERAGE_CONDITIONAL_JUMP_LENGTH,
+            class => {
+                let length = match opc & 0xf0 {
+                    ebpf::BPF_MUL => AVERAGE_MUL_LENGTH,
+                    ebpf::BPF_DIV | ebpf::BPF_MOD | ebpf::BPF_SDIV if opc & ebpf::BPF_X != 0 => {
+                        AVERAGE_DIV_REG_LENGTH
+                    }
+                    ebpf::BPF_DIV | ebpf::BPF_MOD | ebpf::BPF_SDIV => AVERAGE_DIV_IMM_LENGTH,
+                    _ if opc & ebpf::BPF_X != 0 => AVERAGE_ALU_REG_LENGTH,
+                    _ => AVERAGE_ALU_IMM_LENGTH,
+                };
+                if class == ebpf::BPF_ALU {
+                    length + AVERAGE_ALU32_ZERO_EXTENSION_LENGTH
+                } else {
+                    length
+                }
+            }
+        },
+    }
+}
+
+/// Estimates the output of the JIT compiler from the opcode mix, without emitting any code
+pub fn estimate_jit_cost(program: &[u8], config: &Config) -> JitEstimate {
+    let mut instructions = 0;
+    let mut bytes = AVERAGE_EMPTY_PROGRAM_MACHINE_CODE_LENGTH;
+    let mut pc = 0;
+    while (pc + 1) * ebpf::INSN_SIZE <= program.len() {
+        let insn = ebpf::get_insn_unchecked(program, pc);
+        bytes += average_machine_code_length(insn.opc);
+        instructions += 1;
+        pc += match insn.opc {
+            ebpf::LD_DW_IMM => 2,
+            _ => 1,
+        };
+    }
+    let mut max_bytes = MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH + MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION * pc;
+    max_bytes += (max_bytes as f64 * config.noop_instruction_ratio) as usize;
+    bytes += (bytes as f64 * config.noop_instruction_ratio) as usize;
+    JitEstimate {
+        instructions,
+        bytes: bytes.min(max_bytes),
+        max_bytes,
+    }
+}
+
 // Special values for target_pc in struct Jump
 const TARGET_PC_LOCAL_ANCHOR: usize = std::usize::MAX - 100;
 const TARGET_PC_DIV_OVERFLOW: usize = std::usize::MAX - 33;
@@ -240,7 +390,7 @@ pub fn emit<T, E: UserDefinedError>(jit: &mut JitCompiler, data: T) -> Result<()
     unsafe {
         #[allow(clippy::cast_ptr_alignment)]
         let ptr = jit.result.text_section.as_ptr().add(jit.offset_in_text_section) as *mut T;
-        *ptr = data as T;
+        ptr.write_unaligned(data);
     }
     jit.offset_in_text_section += size;
     Ok(())
@@ -485,13 +635,54 @@ fn emit_stopwatch<E: UserDefinedError>(jit: &mut JitCompiler, begin: bool) -> Re
     and then after returning use the undo profiling. The trick is, that the undo profiling now
     has the current pc which is the BPF return address. The virtual target pc we count towards
     and undo again can be anything, so we just set it to zero.
+
+    With Config::instruction_costs the cost function is not constant anymore and the pc is no
+    longer an antiderivative of it. Instead, every pc is mapped to the summed cost of all the
+    instructions in front of it, its position on the instruction meter. These positions are
+    known at compile time, except for the pcs of register calls, exits and exceptions, which
+    are looked up in a table at runtime. The second slot of a LD_DW_IMM carries its cost, so
+    that the instruction needs no profiling of its own. Once the meter runs out, the table is
+    searched for the first instruction to reach the limit, as the validation might only notice
+    at a later checkpoint and the position alone does not tell the pc.
 */
 
+#[inline]
+fn instruction_meter_position(jit: &JitCompiler, pc: usize) -> i64 {
+    match &jit.instruction_cost_prefix {
+        Some(instruction_cost_prefix) => instruction_cost_prefix[pc.min(instruction_cost_prefix.len() - 1)] as i64,
+        None => pc as i64,
+    }
+}
+
+#[inline]
+fn emit_instruction_meter_position_lookup<E: UserDefinedError>(jit: &mut JitCompiler, destination: u8, pc: u8) -> Result<(), EbpfError<E>> {
+    let instruction_cost_prefix = jit.instruction_cost_prefix.as_ref().map_or(std::ptr::null(), |instruction_cost_prefix| instruction_cost_prefix.as_ptr());
+    X86Instruction::load_immediate(OperandSize::S64, destination, instruction_cost_prefix as i64).emit(jit)?;
+    X86Instruction::load(OperandSize::S64, destination, destination, X86IndirectAccess::OffsetIndexShift(0, pc, 3)).emit(jit) // destination = instruction_cost_prefix[pc];
+}
+
+/// Finds the end of the first instruction whose instruction meter position reaches the limit
+fn exhausted_instruction_end(instruction_cost_prefix: *const u64, len: usize, limit: u64) -> u64 {
+    let instruction_cost_prefix = unsafe { std::slice::from_raw_parts(instruction_cost_prefix, len) };
+    (1 + instruction_cost_prefix[1..].partition_point(|position| *position < limit)).min(len - 1) as u64
+}
+
+/// Consumes no more than the remaining instructions and returns the rest
+fn consume_instructions_capped<I: InstructionMeter>(instruction_meter: &mut I, amount: u64) -> u64 {
+    let remaining = instruction_meter.get_remaining();
+    instruction_meter.consume(amount.min(remaining));
+    amount.saturating_sub(remaining)
+}
+
 #[inline]
 fn emit_validate_instruction_count<E: UserDefinedError>(jit: &mut JitCompiler, exclusive: bool, pc: Option<usize>) -> Result<(), EbpfError<E>> {
     if let Some(pc) = pc {
         jit.last_instruction_meter_validation_pc = pc;
-        X86Instruction::cmp_immediate(OperandSize::S64, ARGUMENT_REGISTERS[0], pc as i64 + 1, None).emit(jit)?;
+        let limit = if exclusive { instruction_meter_position(jit, pc) + 1 } else { instruction_meter_position(jit, pc + 1) };
+        X86Instruction::cmp_immediate(OperandSize::S64, ARGUMENT_REGISTERS[0], limit, None).emit(jit)?;
+    } else if jit.instruction_cost_prefix.is_some() {
+        emit_instruction_meter_position_lookup(jit, R10, R11)?;
+        X86Instruction::cmp(OperandSize::S64, R10, ARGUMENT_REGISTERS[0], None).emit(jit)?;
     } else {
         X86Instruction::cmp(OperandSize::S64, R11, ARGUMENT_REGISTERS[0], None).emit(jit)?;
     }
@@ -502,11 +693,18 @@ fn emit_validate_instruction_count<E: UserDefinedError>(jit: &mut JitCompiler, e
 fn emit_profile_instruction_count<E: UserDefinedError>(jit: &mut JitCompiler, target_pc: Option<usize>) -> Result<(), EbpfError<E>> {
     match target_pc {
         Some(target_pc) => {
-            emit_alu(jit, OperandSize::S64, 0x81, 0, ARGUMENT_REGISTERS[0], target_pc as i64 - jit.pc as i64 - 1, None)?; // instruction_meter += target_pc - (jit.pc + 1);
+            emit_alu(jit, OperandSize::S64, 0x81, 0, ARGUMENT_REGISTERS[0], instruction_meter_position(jit, target_pc) - instruction_meter_position(jit, jit.pc + 1), None)?; // instruction_meter += target_pc - (jit.pc + 1);
         },
         None => {
-            emit_alu(jit, OperandSize::S64, 0x81, 5, ARGUMENT_REGISTERS[0], jit.pc as i64 + 1, None)?; // instruction_meter -= jit.pc + 1;
-            emit_alu(jit, OperandSize::S64, 0x01, R11, ARGUMENT_REGISTERS[0], jit.pc as i64, None)?; // instruction_meter += target_pc;
+            emit_alu(jit, OperandSize::S64, 0x81, 5, ARGUMENT_REGISTERS[0], instruction_meter_position(jit, jit.pc + 1), None)?; // instruction_meter -= jit.pc + 1;
+            if jit.instruction_cost_prefix.is_some() {
+                X86Instruction::push(R10, None).emit(jit)?;
+                emit_instruction_meter_position_lookup(jit, R10, R11)?;
+                emit_alu(jit, OperandSize::S64, 0x01, R10, ARGUMENT_REGISTERS[0], 0, None)?; // instruction_meter += target_pc;
+                X86Instruction::pop(R10).emit(jit)?;
+            } else {
+                emit_alu(jit, OperandSize::S64, 0x01, R11, ARGUMENT_REGISTERS[0], jit.pc as i64, None)?; // instruction_meter += target_pc;
+            }
         },
     }
     Ok(())
@@ -524,7 +722,7 @@ fn emit_validate_and_profile_instruction_count<E: UserDefinedError>(jit: &mut Ji
 #[inline]
 fn emit_undo_profile_instruction_count<E: UserDefinedError>(jit: &mut JitCompiler, target_pc: usize) -> Result<(), EbpfError<E>> {
     if jit.config.enable_instruction_meter {
-        emit_alu(jit, OperandSize::S64, 0x81, 0, ARGUMENT_REGISTERS[0], jit.pc as i64 + 1 - target_pc as i64, None)?; // instruction_meter += (jit.pc + 1) - target_pc;
+        emit_alu(jit, OperandSize::S64, 0x81, 0, ARGUMENT_REGISTERS[0], instruction_meter_position(jit, jit.pc + 1) - instruction_meter_position(jit, target_pc), None)?; // instruction_meter += (jit.pc + 1) - target_pc;
     }
     Ok(())
 }
@@ -535,7 +733,12 @@ fn emit_profile_instruction_count_finalize<E: UserDefinedError>(jit: &mut JitCom
         emit_alu(jit, OperandSize::S64, 0x81, 0, R11, 1, None)?; // R11 += 1;
     }
     if jit.config.enable_instruction_meter {
-        emit_alu(jit, OperandSize::S64, 0x29, R11, ARGUMENT_REGISTERS[0], 0, None)?; // instruction_meter -= pc + 1;
+        if jit.instruction_cost_prefix.is_some() {
+            emit_instruction_meter_position_lookup(jit, R10, R11)?;
+            emit_alu(jit, OperandSize::S64, 0x29, R10, ARGUMENT_REGISTERS[0], 0, None)?; // instruction_meter -= pc + 1;
+        } else {
+            emit_alu(jit, OperandSize::S64, 0x29, R11, ARGUMENT_REGISTERS[0], 0, None)?; // instruction_meter -= pc + 1;
+        }
     }
     if store_pc_in_exception {
         X86Instruction::load(OperandSize::S64, RBP, R10, X86IndirectAccess::Offset(slot_on_environment_stack(jit, EnvironmentStackSlot::OptRetValPtr))).emit(jit)?;
@@ -948,6 +1151,7 @@ pub struct JitCompiler {
     offset_in_text_section: usize,
     pc: usize,
     last_instruction_meter_validation_pc: usize,
+    instruction_cost_prefix: Option<Vec<u64>>,
     program_vm_addr: u64,
     handler_anchors: HashMap<usize, usize>,
     config: Config,
@@ -991,6 +1195,11 @@ impl std::fmt::Debug for JitCompiler {
 }
 
 impl JitCompiler {
+    /// Whether the JIT can emit code for the host, which must be x86_64 and not windows
+    pub const fn is_supported() -> bool {
+        cfg!(all(not(windows), target_arch = "x86_64"))
+    }
+
     // Arguments are unused on windows
     fn new<E: UserDefinedError>(program: &[u8], config: &Config) -> Result<Self, EbpfError<E>> {
         #[cfg(target_os = "windows")]
@@ -1008,18 +1217,40 @@ impl JitCompiler {
         }
 
         // Scan through program to find actual number of instructions
+        // and the instruction meter positions of weighted costs
         let mut pc = 0;
+        let instruction_costs = config.instruction_cost_table();
+        let mut instruction_cost_prefix = instruction_costs.as_ref().map(|_| vec![0u64]);
         while (pc + 1) * ebpf::INSN_SIZE <= program.len() {
             let insn = ebpf::get_insn_unchecked(program, pc);
             pc += match insn.opc {
                 ebpf::LD_DW_IMM => 2,
                 _ => 1,
             };
+            if let (Some(instruction_cost_prefix), Some(instruction_costs)) = (&mut instruction_cost_prefix, &instruction_costs) {
+                let position = *instruction_cost_prefix.last().unwrap();
+                if insn.opc == ebpf::LD_DW_IMM {
+                    instruction_cost_prefix.push(position);
+                }
+                instruction_cost_prefix.push(position.saturating_add(instruction_costs[insn.opc as usize]));
+            }
+        }
+        if let Some(instruction_cost_prefix) = &mut instruction_cost_prefix {
+            // The instruction meter positions are encoded as 32 bit immediates
+            let position = *instruction_cost_prefix.last().unwrap();
+            if position > i32::MAX as u64 {
+                return Err(EbpfError::UnsupportedJitOption("Config::instruction_costs"));
+            }
+            // Bumper for the pc after the end of the program
+            instruction_cost_prefix.push(position);
         }
 
         let mut code_length_estimate = MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH + MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION * pc;
         code_length_estimate += (code_length_estimate as f64 * config.noop_instruction_ratio) as usize;
-        let mut diversification_rng = SmallRng::from_rng(rand::thread_rng()).unwrap();
+        let mut diversification_rng = match config.random_seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_rng(rand::thread_rng()).unwrap(),
+        };
         let (environment_stack_key, program_argument_key) =
             if config.encrypt_environment_registers {
                 (
@@ -1035,9 +1266,10 @@ impl JitCompiler {
             offset_in_text_section: 0,
             pc: 0,
             last_instruction_meter_validation_pc: 0,
+            instruction_cost_prefix,
             program_vm_addr: 0,
             handler_anchors: HashMap::new(),
-            config: *config,
+            config: config.clone(),
             diversification_rng,
             stopwatch_is_active: false,
             environment_stack_key,
@@ -1055,7 +1287,7 @@ impl JitCompiler {
         // Jump to entry point
         let entry = executable.get_entrypoint_instruction_offset().unwrap_or(0);
         if self.config.enable_instruction_meter {
-            emit_profile_instruction_count(self, Some(entry + 1))?;
+            emit_alu(self, OperandSize::S64, 0x81, 0, ARGUMENT_REGISTERS[0], instruction_meter_position(self, entry), None)?; // instruction_meter += entry;
         }
         X86Instruction::load_immediate(OperandSize::S64, R11, entry as i64).emit(self)?;
         emit_jmp(self, entry)?;
@@ -1131,7 +1363,11 @@ impl JitCompiler {
                 },
 
                 ebpf::LD_DW_IMM  => {
-                    emit_validate_and_profile_instruction_count(self, true, Some(self.pc + 2))?;
+                    if self.instruction_cost_prefix.is_none() {
+                        emit_validate_and_profile_instruction_count(self, true, Some(self.pc + 2))?;
+                    } else if self.config.enable_instruction_meter {
+                        emit_validate_instruction_count(self, true, Some(self.pc))?;
+                    }
                     self.pc += 1;
                     self.pc_section_jumps.push(Jump { location: self.pc, target_pc: TARGET_PC_CALL_UNSUPPORTED_INSTRUCTION });
                     ebpf::augment_lddw_unchecked(program, &mut insn);
@@ -1352,6 +1588,10 @@ impl JitCompiler {
                             X86Instruction::cmp_immediate(OperandSize::S64, R11, 0, Some(X86IndirectAccess::Offset(0))).emit(self)?;
                             X86Instruction::load_immediate(OperandSize::S64, R11, self.pc as i64).emit(self)?;
                             emit_jcc(self, 0x85, TARGET_PC_RUST_EXCEPTION)?;
+                            // Weighted costs can exhaust the instruction meter with the call itself
+                            if self.config.enable_instruction_meter && self.instruction_cost_prefix.is_some() {
+                                emit_validate_instruction_count(self, false, Some(self.pc))?;
+                            }
 
                             resolved = true;
                         }
@@ -1460,10 +1700,18 @@ impl JitCompiler {
             // RDI = *PrevInsnMeter - RDI;
             emit_alu(self, OperandSize::S64, 0x2B, ARGUMENT_REGISTERS[0], RBP, 0, Some(X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::PrevInsnMeter))))?; // RDI -= *PrevInsnMeter;
             emit_alu(self, OperandSize::S64, 0xf7, 3, ARGUMENT_REGISTERS[0], 0, None)?; // RDI = -RDI;
-            emit_rust_call(self, Value::Constant64(I::consume as *const u8 as i64, false), &[
-                Argument { index: 1, value: Value::Register(ARGUMENT_REGISTERS[0]) },
-                Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::InsnMeterPtr), false) },
-            ], None, false)?;
+            if self.instruction_cost_prefix.is_some() {
+                emit_rust_call(self, Value::Constant64(consume_instructions_capped::<I> as *const u8 as i64, false), &[
+                    Argument { index: 1, value: Value::Register(ARGUMENT_REGISTERS[0]) },
+                    Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::InsnMeterPtr), false) },
+                ], Some(ARGUMENT_REGISTERS[0]), false)?;
+                X86Instruction::store(OperandSize::S64, ARGUMENT_REGISTERS[0], RSP, X86IndirectAccess::OffsetIndexShift(0, RSP, 0)).emit(self)?; // Keep the overshoot in the padding
+            } else {
+                emit_rust_call(self, Value::Constant64(I::consume as *const u8 as i64, false), &[
+                    Argument { index: 1, value: Value::Register(ARGUMENT_REGISTERS[0]) },
+                    Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::InsnMeterPtr), false) },
+                ], None, false)?;
+            }
         }
         emit_rust_call(self, Value::Register(R11), &[
             Argument { index: 7, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::OptRetValPtr), false) },
@@ -1480,6 +1728,9 @@ impl JitCompiler {
                 Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::InsnMeterPtr), false) },
             ], Some(ARGUMENT_REGISTERS[0]), false)?;
             X86Instruction::store(OperandSize::S64, ARGUMENT_REGISTERS[0], RBP, X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::PrevInsnMeter))).emit(self)?;
+            if self.instruction_cost_prefix.is_some() {
+                emit_alu(self, OperandSize::S64, 0x2B, ARGUMENT_REGISTERS[0], RSP, 0, Some(X86IndirectAccess::OffsetIndexShift(0, RSP, 0)))?; // RDI -= overshoot;
+            }
         }
         X86Instruction::pop(R11).emit(self)?;
         // Store Ok value in result register
@@ -1657,8 +1908,24 @@ impl JitCompiler {
         // Handler for EbpfError::ExceededMaxInstructions
         set_anchor(self, TARGET_PC_CALL_EXCEEDED_MAX_INSTRUCTIONS);
         emit_set_exception_kind::<E>(self, EbpfError::ExceededMaxInstructions(0, 0))?;
-        X86Instruction::mov(OperandSize::S64, ARGUMENT_REGISTERS[0], R11).emit(self)?; // R11 = instruction_meter;
-        emit_profile_instruction_count_finalize(self, true)?;
+        if let Some(instruction_cost_prefix) = &self.instruction_cost_prefix {
+            let (instruction_cost_prefix, len) = (instruction_cost_prefix.as_ptr() as i64, instruction_cost_prefix.len() as i64);
+            emit_alu(self, OperandSize::S64, 0x81, 4, RSP, -16, None)?; // Align the stack, the epilogue restores it
+            emit_rust_call(self, Value::Constant64(exhausted_instruction_end as *const u8 as i64, false), &[
+                Argument { index: 2, value: Value::Register(ARGUMENT_REGISTERS[0]) },
+                Argument { index: 1, value: Value::Constant64(len, false) },
+                Argument { index: 0, value: Value::Constant64(instruction_cost_prefix, false) },
+            ], Some(R11), false)?;
+            emit_instruction_meter_position_lookup(self, R10, R11)?;
+            emit_alu(self, OperandSize::S64, 0x29, R10, ARGUMENT_REGISTERS[0], 0, None)?; // instruction_meter -= instruction_cost_prefix[R11];
+            X86Instruction::load(OperandSize::S64, RBP, R10, X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::OptRetValPtr))).emit(self)?;
+            X86Instruction::store_immediate(OperandSize::S64, R10, X86IndirectAccess::Offset(0), 1).emit(self)?; // is_err = true;
+            emit_alu(self, OperandSize::S64, 0x81, 0, R11, ebpf::ELF_INSN_DUMP_OFFSET as i64, None)?;
+            X86Instruction::store(OperandSize::S64, R11, R10, X86IndirectAccess::Offset(16)).emit(self)?; // pc = R11 + ebpf::ELF_INSN_DUMP_OFFSET;
+        } else {
+            X86Instruction::mov(OperandSize::S64, ARGUMENT_REGISTERS[0], R11).emit(self)?; // R11 = instruction_meter;
+            emit_profile_instruction_count_finalize(self, true)?;
+        }
         emit_jmp(self, TARGET_PC_EPILOGUE)?;
 
         // Handler for EbpfError::CallDepthExceeded
@@ -1725,16 +1992,17 @@ impl JitCompiler {
         X86Instruction::push(REGISTER_MAP[FRAME_PTR_REG], None).emit(self)?;
 
         // Initialize the BPF frame and stack pointers (BpfFramePtr and BpfStackPtr)
+        let initial_registers = self.config.initial_registers();
         if self.config.dynamic_stack_frames {
             // The stack is fully descending from MM_STACK_START + stack_size to MM_STACK_START
-            X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], MM_STACK_START as i64 + self.config.stack_size() as i64).emit(self)?;
+            X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], initial_registers[FRAME_PTR_REG] as i64).emit(self)?;
             // Push BpfFramePtr
             X86Instruction::push(REGISTER_MAP[FRAME_PTR_REG], None).emit(self)?;
             // Push BpfStackPtr
             X86Instruction::push(REGISTER_MAP[FRAME_PTR_REG], None).emit(self)?;
         } else {
             // The frames are ascending from MM_STACK_START to MM_STACK_START + stack_size. The stack within the frames is descending.
-            X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], MM_STACK_START as i64 + self.config.stack_frame_size as i64).emit(self)?;
+            X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], initial_registers[FRAME_PTR_REG] as i64).emit(self)?;
             // Push BpfFramePtr
             X86Instruction::push(REGISTER_MAP[FRAME_PTR_REG], None).emit(self)?;
             // When using static frames BpfStackPtr is not used
@@ -1766,10 +2034,10 @@ impl JitCompiler {
         // Save JitProgramArgument
         X86Instruction::lea(OperandSize::S64, ARGUMENT_REGISTERS[2], R10, Some(X86IndirectAccess::Offset(-self.program_argument_key))).emit(self)?;
 
-        // Zero BPF registers
-        for reg in REGISTER_MAP.iter() {
+        // Initialize the other BPF registers, R1 is the input pointer argument
+        for (reg, value) in REGISTER_MAP.iter().zip(initial_registers.iter()) {
             if *reg != REGISTER_MAP[1] && *reg != REGISTER_MAP[FRAME_PTR_REG] {
-                X86Instruction::load_immediate(OperandSize::S64, *reg, 0).emit(self)?;
+                X86Instruction::load_immediate(OperandSize::S64, *reg, *value as i64).emit(self)?;
             }
         }
 
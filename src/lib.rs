@@ -29,10 +29,13 @@ pub mod call_frames;
 pub mod disassembler;
 pub mod ebpf;
 pub mod elf;
+mod elf_writer;
 pub mod error;
 pub mod fuzz;
 pub mod insn_builder;
 mod jit;
+#[cfg(feature = "serde")]
+pub mod manifest;
 pub mod memory_region;
 pub mod static_analysis;
 pub mod syscalls;
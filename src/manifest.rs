@@ -0,0 +1,175 @@
+#![allow(clippy::integer_arithmetic)]
+//! Loads raw bytecode together with a JSON manifest describing it
+//!
+//! The manifest names the entrypoint, the functions, the syscalls the program may call and the
+//! memory layout. A manifest looks like this:
+//!
+//! ```json
+//! {
+//!     "entrypoint": "main",
+//!     "functions": { "main": 0, "helper": 4 },
+//!     "syscalls": ["log"],
+//!     "memory": { "heap_size": 4096, "input_size": 64 }
+//! }
+//! ```
+
+use crate::{
+    aligned_memory::AlignedMemory,
+    ebpf,
+    elf::{register_bpf_function, Executable},
+    error::{EbpfError, UserDefinedError},
+    memory_region::MemoryRegion,
+    vm::{Config, EbpfVm, InstructionMeter, SyscallRegistry, Verifier},
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    pin::Pin,
+};
+use thiserror::Error;
+
+/// Error definitions
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum ManifestError {
+    /// Failed to parse the manifest
+    #[error("failed to parse manifest: {0}")]
+    InvalidManifest(String),
+    /// The entrypoint is not one of the declared functions
+    #[error("entrypoint {0} is not a declared function")]
+    EntrypointNotFound(String),
+    /// A declared syscall is missing from the syscall registry
+    #[error("declared syscall {0} is not registered")]
+    UnregisteredSyscall(String),
+    /// The program calls a syscall which was not declared
+    #[error("undeclared syscall {0:#x} (insn #{1})")]
+    UndeclaredSyscall(u32, usize),
+}
+
+/// Memory the program expects to be provided
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct MemoryLayout {
+    /// Size of the heap region in bytes
+    #[serde(default)]
+    pub heap_size: usize,
+    /// Size of the input region in bytes
+    #[serde(default)]
+    pub input_size: usize,
+}
+
+/// Describes a program distributed as raw bytecode
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Manifest {
+    /// Name of the function where execution starts
+    pub entrypoint: String,
+    /// Instruction offsets of the functions by name
+    #[serde(default)]
+    pub functions: BTreeMap<String, usize>,
+    /// Names of the syscalls the program may call
+    #[serde(default)]
+    pub syscalls: Vec<String>,
+    /// Memory the program expects to be provided
+    #[serde(default)]
+    pub memory: MemoryLayout,
+}
+
+impl Manifest {
+    /// Parses a manifest from JSON
+    pub fn from_json(json: &str) -> Result<Self, ManifestError> {
+        serde_json::from_str(json).map_err(|err| ManifestError::InvalidManifest(err.to_string()))
+    }
+}
+
+/// A program loaded according to its manifest, along with the memory it expects
+pub struct ManifestProgram<E: UserDefinedError, I: InstructionMeter> {
+    /// The loaded executable
+    pub executable: Pin<Box<Executable<E, I>>>,
+    /// Names of the syscalls declared by the manifest
+    pub syscalls: Vec<String>,
+    /// Heap sized as declared by the manifest
+    pub heap: AlignedMemory,
+    /// Input region sized as declared by the manifest
+    pub input: AlignedMemory,
+}
+
+impl<E: UserDefinedError, I: 'static + InstructionMeter> ManifestProgram<E, I> {
+    /// Loads bytecode and validates it against the manifest
+    ///
+    /// Every declared syscall must be registered and the program must only call declared ones.
+    pub fn load(
+        bytecode: &[u8],
+        manifest: &Manifest,
+        verifier: Option<&dyn Verifier>,
+        config: Config,
+        syscall_registry: SyscallRegistry,
+    ) -> Result<Self, EbpfError<E>> {
+        let mut declared_syscalls = HashSet::new();
+        for name in manifest.syscalls.iter() {
+            let hash = syscall_registry.hash_name(name.as_bytes());
+            if syscall_registry.lookup_syscall(hash).is_none() {
+                return Err(ManifestError::UnregisteredSyscall(name.clone()).into());
+            }
+            declared_syscalls.insert(hash);
+        }
+        let mut bpf_functions = BTreeMap::new();
+        for (name, pc) in manifest.functions.iter() {
+            register_bpf_function(&config, &mut bpf_functions, &syscall_registry, *pc, name)?;
+        }
+        let entrypoint_pc = manifest
+            .functions
+            .get(&manifest.entrypoint)
+            .ok_or_else(|| ManifestError::EntrypointNotFound(manifest.entrypoint.clone()))?;
+        register_bpf_function(
+            &config,
+            &mut bpf_functions,
+            &syscall_registry,
+            *entrypoint_pc,
+            "entrypoint",
+        )?;
+        let mut insn_ptr = 0;
+        while (insn_ptr + 1) * ebpf::INSN_SIZE <= bytecode.len() {
+            let insn = ebpf::get_insn(bytecode, insn_ptr);
+            if insn.opc == ebpf::LD_DW_IMM {
+                insn_ptr += 1;
+            } else if insn.opc == ebpf::CALL_IMM {
+                let hash = insn.imm as u32;
+                let is_syscall = if config.static_syscalls {
+                    insn.src == 0
+                } else {
+                    !bpf_functions.contains_key(&hash)
+                };
+                if is_syscall && !declared_syscalls.contains(&hash) {
+                    return Err(ManifestError::UndeclaredSyscall(
+                        hash,
+                        insn_ptr + ebpf::ELF_INSN_DUMP_OFFSET,
+                    )
+                    .into());
+                }
+            }
+            insn_ptr += 1;
+        }
+        let executable = Executable::from_text_bytes(
+            bytecode,
+            verifier,
+            config,
+            syscall_registry,
+            bpf_functions,
+        )?;
+        Ok(Self {
+            executable,
+            syscalls: manifest.syscalls.clone(),
+            heap: AlignedMemory::new_with_size(manifest.memory.heap_size, ebpf::HOST_ALIGN),
+            input: AlignedMemory::new_with_size(manifest.memory.input_size, ebpf::HOST_ALIGN),
+        })
+    }
+
+    /// Creates a VM with the heap and input region laid out as declared by the manifest
+    pub fn create_vm(&mut self) -> Result<EbpfVm<'_, E, I>, EbpfError<E>> {
+        let input_region =
+            MemoryRegion::new_writable(self.input.as_slice_mut(), ebpf::MM_INPUT_START);
+        EbpfVm::new(
+            &self.executable,
+            self.heap.as_slice_mut(),
+            vec![input_region],
+        )
+    }
+}
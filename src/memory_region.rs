@@ -1,11 +1,16 @@
 //! This module defines memory regions
 
 use crate::{
+    aligned_memory::AlignedMemory,
     ebpf,
     error::{EbpfError, UserDefinedError},
     vm::Config,
 };
-use std::fmt;
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fmt,
+};
 
 /* Explaination of the Gapped Memory
 
@@ -38,6 +43,13 @@ pub struct MemoryRegion {
     pub vm_gap_shift: u8,
     /// Is also writable (otherwise it is readonly)
     pub is_writable: bool,
+    /// Offsets wrap around modulo the length. Not supported by the JIT.
+    pub is_ring: bool,
+    /// Is also executable, never together with is_writable
+    pub is_executable: bool,
+    /// Stores go to a private copy, which MemoryMapping allocates on the first store.
+    /// host_addr keeps pointing to the readonly backing. Not supported by the JIT.
+    pub is_cow: bool,
 }
 impl MemoryRegion {
     pub(crate) const HOST_ADDR_OFFSET: i32 = 0;
@@ -64,6 +76,9 @@ impl MemoryRegion {
             len: slice.len() as u64,
             vm_gap_shift,
             is_writable,
+            is_ring: false,
+            is_executable: false,
+            is_cow: false,
         }
     }
 
@@ -87,11 +102,58 @@ impl MemoryRegion {
         Self::new(slice, vm_addr, 0, true)
     }
 
+    /// Creates a new readonly and executable MemoryRegion from a slice
+    pub fn new_executable(slice: &[u8], vm_addr: u64) -> Self {
+        let mut region = Self::new(slice, vm_addr, 0, false);
+        region.is_executable = true;
+        region
+    }
+
+    /// Creates a new copy-on-write MemoryRegion from a readonly slice
+    pub fn new_cow(backing: &[u8], vm_addr: u64) -> Self {
+        let mut region = Self::new(backing, vm_addr, 0, false);
+        region.is_cow = true;
+        region
+    }
+
     /// Creates a new writable gapped MemoryRegion from a mutable slice
     pub fn new_writable_gapped(slice: &mut [u8], vm_addr: u64, vm_gap_size: u64) -> Self {
         Self::new(slice, vm_addr, vm_gap_size, true)
     }
 
+    /// Creates a new writable MemoryRegion from a mutable slice, whose offsets wrap around
+    ///
+    /// An access at `vm_addr + slice.len()` targets `vm_addr` again. Accesses which would cross
+    /// the end of the slice are rejected.
+    pub fn new_writable_ring(slice: &mut [u8], vm_addr: u64) -> Self {
+        let mut region = Self::new(slice, vm_addr, 0, true);
+        region.is_ring = true;
+        region
+    }
+
+    /// Returns the exclusive end of the virtual address range, including the gaps
+    pub fn vm_end(&self) -> u64 {
+        let gaps = if self.len > 0 && self.vm_gap_shift < 63 {
+            self.len
+                .saturating_sub(1)
+                .checked_shr(self.vm_gap_shift as u32)
+                .and_then(|chunks| chunks.checked_shl(self.vm_gap_shift as u32))
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        self.vm_addr.saturating_add(self.len).saturating_add(gaps)
+    }
+
+    /// Returns if the permissions of this region allow the given type of access
+    pub fn allows(&self, access_type: AccessType) -> bool {
+        match access_type {
+            AccessType::Load => true,
+            AccessType::Store => self.is_writable || self.is_cow,
+            AccessType::Execute => self.is_executable,
+        }
+    }
+
     /// Convert a virtual machine address into a host address
     pub fn vm_to_host<E: UserDefinedError>(
         &self,
@@ -105,7 +167,10 @@ impl MemoryRegion {
             return Err(EbpfError::InvalidVirtualAddress(vm_addr));
         }
 
-        let begin_offset = vm_addr.saturating_sub(self.vm_addr);
+        let mut begin_offset = vm_addr.saturating_sub(self.vm_addr);
+        if self.is_ring {
+            begin_offset = begin_offset.checked_rem(self.len).unwrap_or(0);
+        }
         let is_in_gap = (begin_offset
             .checked_shr(self.vm_gap_shift as u32)
             .unwrap_or(0)
@@ -146,6 +211,58 @@ impl std::cmp::Ord for MemoryRegion {
     }
 }
 
+/// Assembles the additional memory regions passed to `EbpfVm::new()`
+#[derive(Debug, Default)]
+pub struct MemoryRegionsBuilder {
+    regions: Vec<MemoryRegion>,
+}
+impl MemoryRegionsBuilder {
+    /// Creates an empty builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a region at the virtual address it was created with
+    pub fn push(&mut self, region: MemoryRegion) -> &mut Self {
+        self.regions.push(region);
+        self
+    }
+
+    /// Places constant data in a readonly region and returns its virtual address
+    ///
+    /// The region is assigned the first free slot after the input region and after all
+    /// previously pushed regions, so push fixed regions first. The slot of the input region is
+    /// skipped even if it was not pushed, as programs expect their input there.
+    pub fn push_constants(&mut self, constants: &[u8]) -> u64 {
+        let first_slot = ebpf::MM_INPUT_START
+            .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+            .unwrap_or(0)
+            .saturating_add(1);
+        let slot = self
+            .regions
+            .iter()
+            .map(|region| {
+                region
+                    .vm_addr
+                    .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+                    .unwrap_or(0)
+                    .saturating_add(1)
+            })
+            .fold(first_slot, u64::max);
+        let vm_addr = slot
+            .checked_shl(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+            .unwrap_or(0);
+        self.regions
+            .push(MemoryRegion::new_readonly(constants, vm_addr));
+        vm_addr
+    }
+
+    /// Returns the regions
+    pub fn build(self) -> Vec<MemoryRegion> {
+        self.regions
+    }
+}
+
 /// Type of memory access
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum AccessType {
@@ -153,14 +270,89 @@ pub enum AccessType {
     Load,
     /// Write
     Store,
+    /// Instruction fetch
+    Execute,
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Unsigned integers which `MemoryMapping::load` and `MemoryMapping::store` can access
+///
+/// Sealed, as any bit pattern read from guest memory has to be a valid value.
+pub trait MemoryInteger: Copy + private::Sealed {
+    /// Converts from little endian to host endianness
+    fn from_le(value: Self) -> Self;
+    /// Converts from big endian to host endianness
+    fn from_be(value: Self) -> Self;
+    /// Converts from host endianness to little endian
+    fn to_le(self) -> Self;
+    /// Converts from host endianness to big endian
+    fn to_be(self) -> Self;
+}
+
+macro_rules! impl_memory_integer {
+    ($($T:ty),*) => {
+        $(impl private::Sealed for $T {}
+        impl MemoryInteger for $T {
+            fn from_le(value: Self) -> Self {
+                <$T>::from_le(value)
+            }
+            fn from_be(value: Self) -> Self {
+                <$T>::from_be(value)
+            }
+            fn to_le(self) -> Self {
+                <$T>::to_le(self)
+            }
+            fn to_be(self) -> Self {
+                <$T>::to_be(self)
+            }
+        })*
+    };
+}
+impl_memory_integer!(u8, u16, u32, u64);
+
+/// Name of the section a virtual address belongs to
+fn region_name(vm_addr: u64) -> &'static str {
+    match vm_addr & (!ebpf::MM_PROGRAM_START.saturating_sub(1)) {
+        ebpf::MM_PROGRAM_START => "program",
+        ebpf::MM_STACK_START => "stack",
+        ebpf::MM_HEAP_START => "heap",
+        ebpf::MM_INPUT_START => "input",
+        _ => "unknown",
+    }
 }
 
 /// Indirection to use instead of a slice to make handling easier
 pub struct MemoryMapping<'a> {
     /// Mapped memory regions
     regions: Box<[MemoryRegion]>,
+    /// Boxed so that the size stays the same for the JIT
+    state: Box<MappingState<'a>>,
+}
+
+/// Contents of the writable memory of a MemoryMapping, see `EbpfVm::snapshot_memory()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemorySnapshot {
+    /// Contents of the writable regions, by region index
+    regions: BTreeMap<usize, Vec<u8>>,
+    /// Private copies of the copy-on-write regions, by region index
+    cow_copies: BTreeMap<usize, Vec<u8>>,
+}
+
+/// Parts of a MemoryMapping which the JIT does not access
+struct MappingState<'a> {
     /// VM configuration
     config: &'a Config,
+    /// Private copies of the copy-on-write regions, by region index
+    cow_copies: RefCell<BTreeMap<usize, AlignedMemory>>,
+    /// (access type, vm_addr, len) of the accesses since start_access_trace, if tracing
+    access_trace: RefCell<Option<Vec<(AccessType, u64, u64)>>>,
+    /// Hash of the pure syscall which is running, if any
+    pure_syscall: Cell<Option<u32>>,
+    /// Whether the running pure syscall attempted a store
+    pure_syscall_violated: Cell<bool>,
 }
 impl<'a> MemoryMapping<'a> {
     /// Creates a new MemoryMapping structure from the given regions
@@ -169,7 +361,15 @@ impl<'a> MemoryMapping<'a> {
         config: &'a Config,
     ) -> Result<Self, EbpfError<E>> {
         regions.sort();
-        for (index, region) in regions.iter().enumerate() {
+        for pair in regions.windows(2) {
+            if pair[1].vm_addr < pair[0].vm_end() {
+                return Err(EbpfError::OverlappingMemoryRegions(
+                    pair[0].vm_addr,
+                    pair[1].vm_addr,
+                ));
+            }
+        }
+        for (index, region) in regions.iter_mut().enumerate() {
             if region
                 .vm_addr
                 .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
@@ -178,27 +378,61 @@ impl<'a> MemoryMapping<'a> {
             {
                 return Err(EbpfError::InvalidMemoryRegion(index));
             }
+            // W^X
+            if (region.is_writable || region.is_cow) && region.is_executable {
+                return Err(EbpfError::InvalidMemoryRegion(index));
+            }
+            if config.reject_context_writes && region.vm_addr == ebpf::MM_INPUT_START {
+                region.is_writable = false;
+                region.is_cow = false;
+            }
         }
         Ok(Self {
             regions: regions.into_boxed_slice(),
-            config,
+            state: Box::new(MappingState {
+                config,
+                cow_copies: RefCell::new(BTreeMap::new()),
+                access_trace: RefCell::new(None),
+                pure_syscall: Cell::new(None),
+                pure_syscall_violated: Cell::new(false),
+            }),
         })
     }
 
     /// Given a list of regions translate from virtual machine to host address
+    ///
+    /// The region is looked up in constant time, as its index is `vm_addr >> 32`, so the cost
+    /// does not depend on the number of regions.
+    ///
+    /// A zero-length access reads and writes nothing, so it only requires `vm_addr` to lie
+    /// within a region (including its end address), regardless of the region's permissions.
     pub fn map<E: UserDefinedError>(
         &self,
         access_type: AccessType,
         vm_addr: u64,
         len: u64,
     ) -> Result<u64, EbpfError<E>> {
+        if access_type == AccessType::Store && len > 0 {
+            if let Some(hash) = self.state.pure_syscall.get() {
+                self.state.pure_syscall_violated.set(true);
+                return Err(EbpfError::PureSyscallViolation(hash));
+            }
+        }
         let index = vm_addr
             .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
             .unwrap_or(0) as usize;
         if (1..self.regions.len()).contains(&index) {
             let region = &self.regions[index];
-            if access_type == AccessType::Load || region.is_writable {
+            if len == 0 || region.allows(access_type) {
                 if let Ok(host_addr) = region.vm_to_host::<E>(vm_addr, len as u64) {
+                    if len > 0 {
+                        if let Some(access_trace) = self.state.access_trace.borrow_mut().as_mut() {
+                            access_trace.push((access_type, vm_addr, len));
+                        }
+                    }
+                    if region.is_cow {
+                        return Ok(self.map_cow(index, access_type, host_addr, len));
+                    }
                     return Ok(host_addr);
                 }
             }
@@ -206,6 +440,180 @@ impl<'a> MemoryMapping<'a> {
         self.generate_access_violation(access_type, vm_addr, len)
     }
 
+    /// Loads a value in host endianness, which may be unaligned
+    pub fn load<T: MemoryInteger, E: UserDefinedError>(
+        &self,
+        vm_addr: u64,
+    ) -> Result<T, EbpfError<E>> {
+        let host_addr =
+            self.map::<E>(AccessType::Load, vm_addr, std::mem::size_of::<T>() as u64)?;
+        Ok(unsafe { std::ptr::read_unaligned(host_addr as *const T) })
+    }
+
+    /// Loads a little endian value
+    pub fn load_le<T: MemoryInteger, E: UserDefinedError>(
+        &self,
+        vm_addr: u64,
+    ) -> Result<T, EbpfError<E>> {
+        self.load::<T, E>(vm_addr).map(T::from_le)
+    }
+
+    /// Loads a big endian value
+    pub fn load_be<T: MemoryInteger, E: UserDefinedError>(
+        &self,
+        vm_addr: u64,
+    ) -> Result<T, EbpfError<E>> {
+        self.load::<T, E>(vm_addr).map(T::from_be)
+    }
+
+    /// Stores a value in host endianness, which may be unaligned
+    pub fn store<T: MemoryInteger, E: UserDefinedError>(
+        &self,
+        vm_addr: u64,
+        value: T,
+    ) -> Result<(), EbpfError<E>> {
+        let host_addr =
+            self.map::<E>(AccessType::Store, vm_addr, std::mem::size_of::<T>() as u64)?;
+        unsafe { std::ptr::write_unaligned(host_addr as *mut T, value) };
+        Ok(())
+    }
+
+    /// Stores a value as little endian
+    pub fn store_le<T: MemoryInteger, E: UserDefinedError>(
+        &self,
+        vm_addr: u64,
+        value: T,
+    ) -> Result<(), EbpfError<E>> {
+        self.store::<T, E>(vm_addr, value.to_le())
+    }
+
+    /// Stores a value as big endian
+    pub fn store_be<T: MemoryInteger, E: UserDefinedError>(
+        &self,
+        vm_addr: u64,
+        value: T,
+    ) -> Result<(), EbpfError<E>> {
+        self.store::<T, E>(vm_addr, value.to_be())
+    }
+
+    /// Sets the pure syscall which is running, see SyscallRegistry::set_syscall_pure
+    ///
+    /// Returns whether the previous one attempted a store.
+    pub(crate) fn set_pure_syscall(&self, hash: Option<u32>) -> bool {
+        self.state.pure_syscall.set(hash);
+        self.state.pure_syscall_violated.replace(false)
+    }
+
+    /// Starts recording the successful accesses of map, see Config::trace_syscall_accesses and
+    /// SyscallLog::Record
+    pub(crate) fn start_access_trace(&self) {
+        *self.state.access_trace.borrow_mut() = Some(Vec::new());
+    }
+
+    /// Stops recording and returns the recorded accesses
+    pub(crate) fn take_access_trace(&self) -> Vec<(AccessType, u64, u64)> {
+        self.state
+            .access_trace
+            .borrow_mut()
+            .take()
+            .unwrap_or_default()
+    }
+
+    /// Copies the contents of the writable regions and of the copy-on-write copies
+    pub(crate) fn snapshot(&self) -> MemorySnapshot {
+        let regions = self
+            .regions
+            .iter()
+            .enumerate()
+            .filter(|(_index, region)| region.is_writable)
+            .map(|(index, region)| {
+                let contents = unsafe {
+                    std::slice::from_raw_parts(region.host_addr as *const u8, region.len as usize)
+                };
+                (index, contents.to_vec())
+            })
+            .collect();
+        let cow_copies = self
+            .state
+            .cow_copies
+            .borrow()
+            .iter()
+            .map(|(index, copy)| (*index, copy.as_slice().to_vec()))
+            .collect();
+        MemorySnapshot {
+            regions,
+            cow_copies,
+        }
+    }
+
+    /// Writes the contents of a snapshot back and discards the copy-on-write copies made since
+    ///
+    /// Fails without changing anything if a region of the snapshot does not match this mapping.
+    pub(crate) fn restore<E: UserDefinedError>(
+        &self,
+        snapshot: &MemorySnapshot,
+    ) -> Result<(), EbpfError<E>> {
+        let fits = |index: usize, contents: &[u8], is_cow: bool| {
+            matches!(
+                self.regions.get(index),
+                Some(region) if region.len == contents.len() as u64
+                    && if is_cow { region.is_cow } else { region.is_writable }
+            )
+        };
+        for (index, contents) in snapshot.regions.iter() {
+            if !fits(*index, contents, false) {
+                return Err(EbpfError::InvalidMemoryRegion(*index));
+            }
+        }
+        for (index, contents) in snapshot.cow_copies.iter() {
+            if !fits(*index, contents, true) {
+                return Err(EbpfError::InvalidMemoryRegion(*index));
+            }
+        }
+        for (index, contents) in snapshot.regions.iter() {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    contents.as_ptr(),
+                    self.regions[*index].host_addr as *mut u8,
+                    contents.len(),
+                );
+            }
+        }
+        *self.state.cow_copies.borrow_mut() = snapshot
+            .cow_copies
+            .iter()
+            .map(|(index, contents)| {
+                (
+                    *index,
+                    AlignedMemory::new_with_data(contents, ebpf::HOST_ALIGN),
+                )
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Redirects an access to a copy-on-write region to its private copy, allocating it on the
+    /// first store
+    fn map_cow(&self, index: usize, access_type: AccessType, host_addr: u64, len: u64) -> u64 {
+        let region = &self.regions[index];
+        let mut cow_copies = self.state.cow_copies.borrow_mut();
+        let copy = if access_type == AccessType::Store && len > 0 {
+            Some(cow_copies.entry(index).or_insert_with(|| {
+                let backing = unsafe {
+                    std::slice::from_raw_parts(region.host_addr as *const u8, region.len as usize)
+                };
+                AlignedMemory::new_with_data(backing, ebpf::HOST_ALIGN)
+            }))
+        } else {
+            cow_copies.get_mut(&index)
+        };
+        match copy {
+            Some(copy) => (copy.as_slice().as_ptr() as u64)
+                .saturating_add(host_addr.saturating_sub(region.host_addr)),
+            None => host_addr,
+        }
+    }
+
     /// Helper for map to generate errors
     pub fn generate_access_violation<E: UserDefinedError>(
         &self,
@@ -213,12 +621,25 @@ impl<'a> MemoryMapping<'a> {
         vm_addr: u64,
         len: u64,
     ) -> Result<u64, EbpfError<E>> {
+        if self.state.config.reject_context_writes && access_type == AccessType::Store {
+            let index = ebpf::MM_INPUT_START
+                .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+                .unwrap_or(0) as usize;
+            if let Some(region) = self.regions.get(index) {
+                if region.vm_to_host::<E>(vm_addr, len).is_ok() {
+                    return Err(EbpfError::ContextWriteViolation(
+                        0, // Filled out later
+                    ));
+                }
+            }
+        }
         let stack_frame = (vm_addr as i64)
             .saturating_sub(ebpf::MM_STACK_START as i64)
-            .checked_div(self.config.stack_frame_size as i64)
+            .checked_div(self.state.config.stack_frame_size as i64)
             .unwrap_or(0);
-        if !self.config.dynamic_stack_frames
-            && (-1..(self.config.max_call_depth as i64).saturating_add(1)).contains(&stack_frame)
+        if !self.state.config.dynamic_stack_frames
+            && (-1..(self.state.config.max_call_depth as i64).saturating_add(1))
+                .contains(&stack_frame)
         {
             Err(EbpfError::StackAccessViolation(
                 0, // Filled out later
@@ -228,13 +649,21 @@ impl<'a> MemoryMapping<'a> {
                 stack_frame,
             ))
         } else {
-            let region_name = match vm_addr & (!ebpf::MM_PROGRAM_START.saturating_sub(1)) {
-                ebpf::MM_PROGRAM_START => "program",
-                ebpf::MM_STACK_START => "stack",
-                ebpf::MM_HEAP_START => "heap",
-                ebpf::MM_INPUT_START => "input",
-                _ => "unknown",
-            };
+            let region_name = region_name(vm_addr);
+            let index = vm_addr
+                .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+                .unwrap_or(0) as usize;
+            if let Some(region) = self.regions.get(index).filter(|_| index > 0) {
+                if region.allows(access_type) && region.vm_to_host::<E>(vm_addr, 1).is_ok() {
+                    return Err(EbpfError::RegionStraddleViolation(
+                        0, // Filled out later
+                        access_type,
+                        vm_addr,
+                        len,
+                        region_name,
+                    ));
+                }
+            }
             Err(EbpfError::AccessViolation(
                 0, // Filled out later
                 access_type,
@@ -245,6 +674,40 @@ impl<'a> MemoryMapping<'a> {
         }
     }
 
+    /// Returns the mapped memory regions, indexed by `vm_addr >> 32`
+    pub fn get_regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    /// Renders the regions as a table sorted by virtual address, with an exclusive end address
+    ///
+    /// Copy-on-write regions show `c` instead of `w` in the permissions.
+    pub fn render_map(&self) -> String {
+        let mut output = format!(
+            "{:<18} {:<18} {:>10} {:<4} {}\n",
+            "base", "end", "size", "perm", "name"
+        );
+        for region in self.regions.iter() {
+            output.push_str(&format!(
+                "{:#018x} {:#018x} {:>10} {}{}{}  {}\n",
+                region.vm_addr,
+                region.vm_end(),
+                region.len,
+                'r',
+                if region.is_cow {
+                    'c'
+                } else if region.is_writable {
+                    'w'
+                } else {
+                    '-'
+                },
+                if region.is_executable { 'x' } else { '-' },
+                region_name(region.vm_addr),
+            ));
+        }
+        output
+    }
+
     /// Resize the memory_region at the given index
     pub fn resize_region<E: UserDefinedError>(
         &mut self,
@@ -267,3 +730,466 @@ impl<'a> MemoryMapping<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::user_error::UserError;
+
+    #[test]
+    fn test_push_constants() {
+        let input = [0u8; 4];
+        let constants = [1u8, 2, 3];
+        // An empty builder does not hand out the slot of the input region
+        let mut builder = MemoryRegionsBuilder::new();
+        assert_eq!(builder.push_constants(&constants), 0x500000000);
+        let mut builder = MemoryRegionsBuilder::new();
+        builder.push(MemoryRegion::new_readonly(&input, ebpf::MM_INPUT_START));
+        assert_eq!(builder.push_constants(&constants), 0x500000000);
+        assert_eq!(builder.push_constants(&constants), 0x600000000);
+        let regions = builder.build();
+        assert_eq!(regions.len(), 3);
+        assert!(!regions[1].is_writable);
+        assert_eq!(regions[1].host_addr, constants.as_ptr() as u64);
+        assert_eq!(regions[1].len, 3);
+    }
+
+    #[test]
+    fn test_ring_region() {
+        let mut mem = [0u8; 8];
+        let host_addr = mem.as_ptr() as u64;
+        let region = MemoryRegion::new_writable_ring(&mut mem, ebpf::MM_INPUT_START);
+        for (offset, host_offset) in [(0, 0), (7, 7), (8, 0), (13, 5), (8 * 1000 + 3, 3)] {
+            assert_eq!(
+                region
+                    .vm_to_host::<UserError>(ebpf::MM_INPUT_START + offset, 1)
+                    .unwrap(),
+                host_addr + host_offset
+            );
+        }
+        assert!(region
+            .vm_to_host::<UserError>(ebpf::MM_INPUT_START + 12, 4)
+            .is_ok());
+        assert!(region
+            .vm_to_host::<UserError>(ebpf::MM_INPUT_START + 13, 4)
+            .is_err());
+        assert!(region
+            .vm_to_host::<UserError>(ebpf::MM_INPUT_START, 9)
+            .is_err());
+    }
+
+    #[test]
+    fn test_map_straddling_regions() {
+        let config = Config::default();
+        let mem1 = [0u8; 8];
+        let mem2 = [0u8; 8];
+        let memory_mapping = MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::default(),
+                MemoryRegion::new_readonly(&mem1, ebpf::MM_PROGRAM_START),
+                MemoryRegion::new_readonly(&mem2, ebpf::MM_STACK_START),
+            ],
+            &config,
+        )
+        .unwrap();
+        let err = memory_mapping
+            .map::<UserError>(AccessType::Load, 0x100000004, 8)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            EbpfError::RegionStraddleViolation(0, AccessType::Load, 0x100000004, 8, "program")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Access at address 0x100000004 of size 8 by instruction #0 straddles the end of the program section"
+        );
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Load, 0x100000008, 8),
+            Err(EbpfError::AccessViolation(..))
+        ));
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Store, 0x100000004, 8),
+            Err(EbpfError::AccessViolation(..))
+        ));
+    }
+
+    #[test]
+    fn test_map_execute() {
+        let config = Config::default();
+        let mut mem1 = [0u8; 8];
+        let mem2 = [0u8; 8];
+        let memory_mapping = MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::default(),
+                MemoryRegion::new_writable(&mut mem1, ebpf::MM_PROGRAM_START),
+                MemoryRegion::new_executable(&mem2, ebpf::MM_STACK_START),
+            ],
+            &config,
+        )
+        .unwrap();
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Execute, 0x100000000, 8),
+            Err(EbpfError::AccessViolation(..))
+        ));
+        for access_type in [AccessType::Load, AccessType::Store] {
+            assert!(memory_mapping
+                .map::<UserError>(access_type, 0x100000000, 8)
+                .is_ok());
+        }
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Store, 0x200000000, 8),
+            Err(EbpfError::AccessViolation(..))
+        ));
+        for access_type in [AccessType::Load, AccessType::Execute] {
+            assert!(memory_mapping
+                .map::<UserError>(access_type, 0x200000000, 8)
+                .is_ok());
+        }
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Execute, 0x200000004, 8),
+            Err(EbpfError::RegionStraddleViolation(..))
+        ));
+
+        let mut region = MemoryRegion::new_writable(&mut mem1, ebpf::MM_PROGRAM_START);
+        region.is_executable = true;
+        assert_eq!(
+            MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), region], &config).err(),
+            Some(EbpfError::InvalidMemoryRegion(1))
+        );
+    }
+
+    #[test]
+    fn test_map_cow() {
+        let config = Config::default();
+        let backing = [1u8, 2, 3, 4];
+        let memory_mapping = MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::default(),
+                MemoryRegion::new_cow(&backing, ebpf::MM_PROGRAM_START),
+            ],
+            &config,
+        )
+        .unwrap();
+        let load = |vm_addr: u64| unsafe {
+            *(memory_mapping
+                .map::<UserError>(AccessType::Load, vm_addr, 1)
+                .unwrap() as *const u8)
+        };
+
+        // Reads see the backing until the first store
+        assert_eq!(
+            memory_mapping
+                .map::<UserError>(AccessType::Load, 0x100000001, 1)
+                .unwrap(),
+            backing.as_ptr() as u64 + 1
+        );
+        assert_eq!(
+            memory_mapping
+                .map::<UserError>(AccessType::Store, 0x100000001, 0)
+                .unwrap(),
+            backing.as_ptr() as u64 + 1
+        );
+        let host_addr = memory_mapping
+            .map::<UserError>(AccessType::Store, 0x100000001, 2)
+            .unwrap();
+        assert_ne!(host_addr, backing.as_ptr() as u64 + 1);
+        unsafe { (host_addr as *mut u16).write_unaligned(0x0909) };
+        assert_eq!(
+            (0..4)
+                .map(|offset| load(0x100000000 + offset))
+                .collect::<Vec<_>>(),
+            vec![1, 9, 9, 4]
+        );
+        // Further stores go to the same copy
+        let host_addr = memory_mapping
+            .map::<UserError>(AccessType::Store, 0x100000003, 1)
+            .unwrap();
+        unsafe { *(host_addr as *mut u8) = 7 };
+        assert_eq!(load(0x100000003), 7);
+        assert_eq!(load(0x100000001), 9);
+        assert_eq!(backing, [1, 2, 3, 4]);
+        assert!(memory_mapping
+            .map::<UserError>(AccessType::Store, 0x100000003, 2)
+            .is_err());
+    }
+
+    #[test]
+    fn test_load_store() {
+        let config = Config::default();
+        let mut mem = [0x12u8, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x11];
+        let memory_mapping = MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::default(),
+                MemoryRegion::new_writable(&mut mem, ebpf::MM_PROGRAM_START),
+            ],
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            memory_mapping.load_be::<u16, UserError>(0x100000000),
+            Ok(0x1234)
+        );
+        assert_eq!(
+            memory_mapping.load_le::<u16, UserError>(0x100000000),
+            Ok(0x3412)
+        );
+        assert_eq!(
+            memory_mapping.load_be::<u32, UserError>(0x100000001),
+            Ok(0x3456789a)
+        );
+        assert_eq!(
+            memory_mapping.load_be::<u64, UserError>(0x100000001),
+            Ok(0x3456789abcdef011)
+        );
+        assert_eq!(
+            memory_mapping.load::<u64, UserError>(0x100000001),
+            Ok(u64::from_be(0x3456789abcdef011))
+        );
+        assert_eq!(memory_mapping.load::<u8, UserError>(0x100000008), Ok(0x11));
+        // Spanning the end of the region
+        assert!(matches!(
+            memory_mapping.load::<u16, UserError>(0x100000008),
+            Err(EbpfError::RegionStraddleViolation(..))
+        ));
+        assert!(matches!(
+            memory_mapping.load_be::<u64, UserError>(0x100000002),
+            Err(EbpfError::RegionStraddleViolation(..))
+        ));
+        assert!(matches!(
+            memory_mapping.store::<u32, UserError>(0x100000006, 0),
+            Err(EbpfError::RegionStraddleViolation(..))
+        ));
+
+        memory_mapping
+            .store_be::<u32, UserError>(0x100000003, 0xcafebabe)
+            .unwrap();
+        memory_mapping
+            .store_le::<u16, UserError>(0x100000000, 0xbeef)
+            .unwrap();
+        assert_eq!(mem, [0xef, 0xbe, 0x56, 0xca, 0xfe, 0xba, 0xbe, 0xf0, 0x11]);
+    }
+
+    #[test]
+    fn test_overlapping_regions() {
+        let config = Config::default();
+        let region = |vm_addr: u64, len: u64| MemoryRegion {
+            vm_addr,
+            len,
+            vm_gap_shift: 63,
+            ..MemoryRegion::default()
+        };
+        // Adjacent
+        assert!(MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::default(),
+                region(ebpf::MM_PROGRAM_START, 1 << 32),
+                region(ebpf::MM_STACK_START, 8),
+            ],
+            &config,
+        )
+        .is_ok());
+        // Overlapping by one byte
+        assert_eq!(
+            MemoryMapping::new::<UserError>(
+                vec![
+                    MemoryRegion::default(),
+                    region(ebpf::MM_STACK_START, 8),
+                    region(ebpf::MM_PROGRAM_START, (1 << 32) + 1),
+                ],
+                &config,
+            )
+            .err(),
+            Some(EbpfError::OverlappingMemoryRegions(
+                ebpf::MM_PROGRAM_START,
+                ebpf::MM_STACK_START
+            ))
+        );
+        // Fully contained, also with zero length
+        for len in [0, 8] {
+            assert_eq!(
+                MemoryMapping::new::<UserError>(
+                    vec![
+                        MemoryRegion::default(),
+                        region(ebpf::MM_PROGRAM_START, 3 << 32),
+                        region(ebpf::MM_STACK_START, len),
+                    ],
+                    &config,
+                )
+                .err(),
+                Some(EbpfError::OverlappingMemoryRegions(
+                    ebpf::MM_PROGRAM_START,
+                    ebpf::MM_STACK_START
+                ))
+            );
+            assert_eq!(
+                MemoryMapping::new::<UserError>(
+                    vec![
+                        MemoryRegion::default(),
+                        region(ebpf::MM_PROGRAM_START, 16),
+                        region(ebpf::MM_PROGRAM_START + 4, len),
+                    ],
+                    &config,
+                )
+                .err(),
+                Some(EbpfError::OverlappingMemoryRegions(
+                    ebpf::MM_PROGRAM_START,
+                    ebpf::MM_PROGRAM_START + 4
+                ))
+            );
+        }
+        // The gaps of a gapped region count
+        let stack = [0u8; 8];
+        let mut gapped = MemoryRegion::new_for_testing(&stack, ebpf::MM_PROGRAM_START, 2, false);
+        gapped.len = (1 << 32) - 1;
+        assert_eq!(gapped.vm_end(), ebpf::MM_PROGRAM_START + (2 << 32) - 3);
+        assert!(MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::default(),
+                gapped,
+                region(ebpf::MM_STACK_START, 8)
+            ],
+            &config,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_map_many_regions() {
+        let config = Config::default();
+        let mem = [0u8; 1024];
+        let regions = std::iter::once(MemoryRegion::default())
+            .chain((1..1024u64).map(|index| {
+                MemoryRegion::new_readonly(
+                    &mem[index as usize..],
+                    index << ebpf::VIRTUAL_ADDRESS_BITS,
+                )
+            }))
+            .collect();
+        let memory_mapping = MemoryMapping::new::<UserError>(regions, &config).unwrap();
+        for index in 1..1024u64 {
+            assert_eq!(
+                memory_mapping
+                    .map::<UserError>(AccessType::Load, index << ebpf::VIRTUAL_ADDRESS_BITS, 1)
+                    .unwrap(),
+                mem.as_ptr() as u64 + index
+            );
+        }
+        assert!(memory_mapping
+            .map::<UserError>(AccessType::Load, 1024 << ebpf::VIRTUAL_ADDRESS_BITS, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_render_map() {
+        let config = Config::default();
+        let text = [0u8; 16];
+        let mut stack = [0u8; 16];
+        let mut heap = [0u8; 32];
+        let input = [0u8; 3];
+        let cow = [0u8; 8];
+        // Passed out of order on purpose
+        let memory_mapping = MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::new_readonly(&input, ebpf::MM_INPUT_START),
+                MemoryRegion::new_cow(
+                    &cow,
+                    ebpf::MM_INPUT_START + (1 << ebpf::VIRTUAL_ADDRESS_BITS),
+                ),
+                MemoryRegion::default(),
+                MemoryRegion::new_writable(&mut heap, ebpf::MM_HEAP_START),
+                MemoryRegion::new_executable(&text, ebpf::MM_PROGRAM_START),
+                // Two frames of 8 bytes with a gap of 8 bytes in between
+                MemoryRegion::new_writable_gapped(&mut stack, ebpf::MM_STACK_START, 8),
+            ],
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            memory_mapping.render_map(),
+            "\
+base               end                      size perm name
+0x0000000000000000 0x0000000000000000          0 r--  unknown
+0x0000000100000000 0x0000000100000010         16 r-x  program
+0x0000000200000000 0x0000000200000018         16 rw-  stack
+0x0000000300000000 0x0000000300000020         32 rw-  heap
+0x0000000400000000 0x0000000400000003          3 r--  input
+0x0000000500000000 0x0000000500000008          8 rc-  unknown
+"
+        );
+    }
+
+    #[test]
+    fn test_map_zero_length() {
+        let config = Config::default();
+        let mem = [0u8; 8];
+        let memory_mapping = MemoryMapping::new::<UserError>(
+            vec![
+                MemoryRegion::default(),
+                MemoryRegion::new_readonly(&mem, ebpf::MM_PROGRAM_START),
+            ],
+            &config,
+        )
+        .unwrap();
+        let host_addr = mem.as_ptr() as u64;
+
+        // Valid address, also for stores into a readonly region
+        assert_eq!(
+            memory_mapping
+                .map::<UserError>(AccessType::Load, 0x100000004, 0)
+                .unwrap(),
+            host_addr.saturating_add(4)
+        );
+        assert_eq!(
+            memory_mapping
+                .map::<UserError>(AccessType::Store, 0x100000004, 0)
+                .unwrap(),
+            host_addr.saturating_add(4)
+        );
+
+        // Region boundaries
+        assert_eq!(
+            memory_mapping
+                .map::<UserError>(AccessType::Load, ebpf::MM_PROGRAM_START, 0)
+                .unwrap(),
+            host_addr
+        );
+        assert_eq!(
+            memory_mapping
+                .map::<UserError>(AccessType::Load, 0x100000008, 0)
+                .unwrap(),
+            host_addr.saturating_add(8)
+        );
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Load, 0x100000009, 0),
+            Err(EbpfError::AccessViolation(
+                _,
+                AccessType::Load,
+                0x100000009,
+                0,
+                "program"
+            ))
+        ));
+
+        // Unmapped addresses
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Load, 0x10, 0),
+            Err(EbpfError::AccessViolation(
+                _,
+                AccessType::Load,
+                0x10,
+                0,
+                "unknown"
+            ))
+        ));
+        assert!(matches!(
+            memory_mapping.map::<UserError>(AccessType::Store, ebpf::MM_INPUT_START, 0),
+            Err(EbpfError::AccessViolation(
+                _,
+                AccessType::Store,
+                _,
+                0,
+                "input"
+            ))
+        ));
+    }
+}
@@ -1,7 +1,10 @@
 #![allow(clippy::integer_arithmetic)]
 //! Static Byte Code Analysis
 
-use crate::disassembler::disassemble_instruction;
+use crate::disassembler::{
+    decode_instruction, decode_instruction_with_labels, disassemble_instruction,
+    DisassembledInstruction,
+};
 use crate::{
     ebpf,
     elf::{self, Executable},
@@ -104,6 +107,21 @@ pub struct DfgEdge {
     pub resource: DataResource,
 }
 
+/// A violation of the entry/exit structure, see `Analysis::check_structure`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructuralIssue {
+    /// The entrypoint (pc) does not start a function
+    EntrypointNotAFunction(usize),
+    /// The function starting at pc contains no exit instruction
+    MissingExit(usize),
+    /// The jump at pc leaves its function towards the target pc
+    JumpOutOfFunction(usize, usize),
+    /// The last instruction (pc) of a function falls through into the next function
+    FallthroughIntoFunction(usize),
+    /// The last instruction (pc) of the program falls through past the end of the text section
+    FallthroughOffEnd(usize),
+}
+
 impl Default for CfgNode {
     fn default() -> Self {
         Self {
@@ -410,7 +428,7 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
     }
 
     /// Generates labels for assembler code
-    pub fn disassemble_label<W: std::io::Write>(
+    pub fn disassemble_label<W: std::io::Write + ?Sized>(
         &self,
         output: &mut W,
         suppress_extra_newlines: bool,
@@ -436,20 +454,229 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
     }
 
     /// Generates assembler code for the analyzed executable
-    pub fn disassemble<W: std::io::Write>(&self, output: &mut W) -> std::io::Result<()> {
+    ///
+    /// Jump and call targets are labeled, with `lbb_<pc>` or the function name, and the operands
+    /// of jumps and calls refer to these labels instead of relative offsets.
+    pub fn disassemble<W: std::io::Write + ?Sized>(&self, output: &mut W) -> std::io::Result<()> {
+        self.disassemble_with_options(output, false, false)
+    }
+
+    /// Generates assembler code prefixed with the raw bytes of each instruction
+    ///
+    /// An `lddw` spans two slots and shows all 16 bytes.
+    pub fn disassemble_with_raw_bytes<W: std::io::Write + ?Sized>(
+        &self,
+        output: &mut W,
+    ) -> std::io::Result<()> {
+        self.disassemble_with_options(output, true, false)
+    }
+
+    /// Generates assembler code with an `lbl_<pc>:` label at every jump and call target
+    ///
+    /// The operands of jumps and calls refer to these labels, e.g. `ja lbl_42`. Functions are
+    /// still introduced by their names, but basic blocks are not labeled otherwise.
+    pub fn disassemble_with_jump_labels<W: std::io::Write + ?Sized>(
+        &self,
+        output: &mut W,
+    ) -> std::io::Result<()> {
+        self.disassemble_with_options(output, false, true)
+    }
+
+    /// Pcs which are the target of a jump or of a call to a function
+    fn jump_targets(&self) -> BTreeSet<usize> {
+        self.instructions
+            .iter()
+            .filter_map(|insn| match insn.opc {
+                ebpf::CALL_IMM => self.executable.lookup_bpf_function(insn.imm as u32),
+                ebpf::CALL_REG | ebpf::EXIT => None,
+                _ if insn.opc & 0x07 == ebpf::BPF_JMP => {
+                    Some((insn.ptr as isize + insn.off as isize + 1) as usize)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn disassemble_with_options<W: std::io::Write + ?Sized>(
+        &self,
+        output: &mut W,
+        show_raw_bytes: bool,
+        jump_labels: bool,
+    ) -> std::io::Result<()> {
+        let (_program_vm_addr, program) = self.executable.get_text_bytes();
+        let jump_targets = if jump_labels {
+            self.jump_targets()
+        } else {
+            BTreeSet::new()
+        };
         let mut last_basic_block = usize::MAX;
         for insn in self.instructions.iter() {
-            self.disassemble_label(
-                output,
-                Some(insn) == self.instructions.first(),
-                insn.ptr,
-                &mut last_basic_block,
-            )?;
-            writeln!(output, "    {}", disassemble_instruction(insn, self))?;
+            if jump_labels {
+                if self.functions.contains_key(&insn.ptr) {
+                    if Some(insn) != self.instructions.first() {
+                        writeln!(output)?;
+                    }
+                    writeln!(output, "{}:", self.cfg_nodes[&insn.ptr].label)?;
+                }
+                if jump_targets.contains(&insn.ptr) {
+                    writeln!(output, "lbl_{}:", insn.ptr)?;
+                }
+            } else {
+                self.disassemble_label(
+                    output,
+                    Some(insn) == self.instructions.first(),
+                    insn.ptr,
+                    &mut last_basic_block,
+                )?;
+            }
+            let text = decode_instruction_with_labels(insn, self, jump_labels).to_string();
+            if show_raw_bytes {
+                let slots = if insn.opc == ebpf::LD_DW_IMM { 2 } else { 1 };
+                let raw_bytes = program
+                    [insn.ptr * ebpf::INSN_SIZE..(insn.ptr + slots) * ebpf::INSN_SIZE]
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                writeln!(output, "    {:<47}    {}", raw_bytes, text)?;
+            } else {
+                writeln!(output, "    {}", text)?;
+            }
         }
         Ok(())
     }
 
+    /// Decodes the instructions of the analyzed executable, in the order of `disassemble()`
+    pub fn disassembled_instructions(&self) -> impl Iterator<Item = DisassembledInstruction> + '_ {
+        self.instructions
+            .iter()
+            .map(move |insn| decode_instruction(insn, self))
+    }
+
+    /// Decodes the instruction at `pc` with its mnemonic and operands, like `disassemble()`
+    ///
+    /// Returns None if `pc` is out of range or the second slot of a `lddw`.
+    pub fn instruction_at(&self, pc: usize) -> Option<DisassembledInstruction> {
+        self.instructions
+            .binary_search_by_key(&pc, |insn| insn.ptr)
+            .ok()
+            .map(|index| decode_instruction(&self.instructions[index], self))
+    }
+
+    /// Disassembles only the instructions which occupy the slots `start_pc..end_pc`
+    ///
+    /// An `lddw` with only one of its two slots in the range is included in full. Labels are not
+    /// emitted, but jump and call operands refer to them like in `disassemble()`.
+    pub fn disassemble_range(&self, start_pc: usize, end_pc: usize) -> Vec<String> {
+        let first = self.instructions.partition_point(|insn| {
+            let slots = if insn.opc == ebpf::LD_DW_IMM { 2 } else { 1 };
+            insn.ptr + slots <= start_pc
+        });
+        self.instructions[first..]
+            .iter()
+            .take_while(|insn| insn.ptr < end_pc)
+            .map(|insn| disassemble_instruction(insn, self))
+            .collect()
+    }
+
+    /// Lists instructions which never have an effect, e.g. `mov r1, r1` or `add r1, 0`
+    ///
+    /// These are reported as (pc, reason) warnings and do not make the program invalid.
+    pub fn redundant_instructions(&self) -> Vec<(usize, &'static str)> {
+        self.instructions
+            .iter()
+            .filter_map(|insn| {
+                let reason = match insn.opc {
+                    ebpf::MOV64_REG if insn.dst == insn.src => "moves a register to itself",
+                    ebpf::ADD64_IMM
+                    | ebpf::SUB64_IMM
+                    | ebpf::OR64_IMM
+                    | ebpf::XOR64_IMM
+                    | ebpf::LSH64_IMM
+                    | ebpf::RSH64_IMM
+                    | ebpf::ARSH64_IMM
+                        if insn.imm == 0 =>
+                    {
+                        "operand is the identity element"
+                    }
+                    ebpf::MUL64_IMM | ebpf::DIV64_IMM if insn.imm == 1 => {
+                        "operand is the identity element"
+                    }
+                    ebpf::AND64_IMM if insn.imm == -1 => "operand is the identity element",
+                    _ if insn.opc & 0x07 == ebpf::BPF_JMP
+                        && insn.opc != ebpf::CALL_IMM
+                        && insn.opc != ebpf::CALL_REG
+                        && insn.opc != ebpf::EXIT
+                        && insn.off == 0 =>
+                    {
+                        "jumps to the next instruction"
+                    }
+                    _ => return None,
+                };
+                Some((insn.ptr, reason))
+            })
+            .collect()
+    }
+
+    /// Checks the entry/exit structure of the program and reports all violations at once
+    ///
+    /// The entrypoint has to start a function, every function has to contain an exit, jumps may
+    /// not leave their function and the last instruction of a function may not fall through.
+    pub fn check_structure(&self) -> Result<(), Vec<StructuralIssue>> {
+        let mut issues = Vec::new();
+        if !self.functions.contains_key(&self.entrypoint) {
+            issues.push(StructuralIssue::EntrypointNotAFunction(self.entrypoint));
+        }
+        // A trailing lddw is the last instruction and occupies both of the final slots
+        let end_of_program = self.instructions.last().map_or(0, |insn| {
+            insn.ptr + if insn.opc == ebpf::LD_DW_IMM { 2 } else { 1 }
+        });
+        let mut function_starts = self.functions.keys().peekable();
+        while let Some(function_start) = function_starts.next() {
+            let function_end = function_starts
+                .peek()
+                .map_or(end_of_program, |next_function| **next_function);
+            let function_range = *function_start..function_end;
+            let mut has_exit = false;
+            let mut last_insn = None;
+            for insn in self
+                .instructions
+                .iter()
+                .filter(|insn| function_range.contains(&insn.ptr))
+            {
+                match insn.opc {
+                    ebpf::EXIT => has_exit = true,
+                    ebpf::CALL_IMM | ebpf::CALL_REG => {}
+                    _ if insn.opc & 0x07 == ebpf::BPF_JMP => {
+                        let target = (insn.ptr as isize + 1 + insn.off as isize) as usize;
+                        if !function_range.contains(&target) {
+                            issues.push(StructuralIssue::JumpOutOfFunction(insn.ptr, target));
+                        }
+                    }
+                    _ => {}
+                }
+                last_insn = Some(insn);
+            }
+            if !has_exit {
+                issues.push(StructuralIssue::MissingExit(*function_start));
+            }
+            if let Some(insn) =
+                last_insn.filter(|insn| insn.opc != ebpf::EXIT && insn.opc != ebpf::JA)
+            {
+                issues.push(if function_end == end_of_program {
+                    StructuralIssue::FallthroughOffEnd(insn.ptr)
+                } else {
+                    StructuralIssue::FallthroughIntoFunction(insn.ptr)
+                });
+            }
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
     /// Iterates over the cfg_nodes while providing the PC range of the function they belong to.
     pub fn iter_cfg_by_function(
         &self,
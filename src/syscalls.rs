@@ -21,13 +21,23 @@
 //! respect this convention.
 
 use crate::{
-    error::EbpfError,
+    error::{EbpfError, UserDefinedError},
     memory_region::{AccessType, MemoryMapping},
     question_mark,
     user_error::UserError,
-    vm::SyscallObject,
+    vm::{InstructionMeter, SyscallObject},
+};
+#[cfg(feature = "sha256")]
+use sha2::{Digest, Sha256};
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    io::Write,
+    rc::Rc,
+    slice::from_raw_parts,
+    str::from_utf8,
+    u64,
 };
-use std::{slice::from_raw_parts, str::from_utf8, u64};
 
 /// Test syscall context
 pub type BpfSyscallContext = u64;
@@ -35,13 +45,85 @@ pub type BpfSyscallContext = u64;
 /// Return type of syscalls
 pub type Result = std::result::Result<u64, EbpfError<UserError>>;
 
+/// Destination of `BpfTracePrintf`, `BpfSyscallString` and `BpfSyscallU64` (None = stdout)
+pub type BpfPrintSink = Option<Rc<RefCell<dyn Write>>>;
+
+fn print_line(sink: &BpfPrintSink, args: fmt::Arguments) {
+    match sink {
+        Some(sink) => {
+            let _ = writeln!(sink.borrow_mut(), "{}", args);
+        }
+        None => println!("{}", args),
+    }
+}
+
+/// Stores `data` into a guest buffer for syscalls producing variable length output.
+///
+/// On entry the u64 at `out_len_addr` holds the capacity of the buffer at `out_addr`. At most that
+/// many bytes of `data` are copied, then the full length of `data` is written back to
+/// `out_len_addr`, so that a guest can detect truncation and retry with a larger buffer. Both
+/// locations are mapped before anything is written. Returns the number of bytes copied.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::write_result_bytes;
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::Config;
+/// use solana_rbpf::user_error::UserError;
+///
+/// let mut buffer = [0u8; 8];
+/// let mut len = [0u8; 8];
+/// let va_buffer = 0x100000000;
+/// let va_len = 0x200000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_writable(&mut buffer, va_buffer), MemoryRegion::new_writable(&mut len, va_len)], &config).unwrap();
+/// // Fits into the buffer
+/// len = 8u64.to_le_bytes();
+/// assert_eq!(write_result_bytes::<UserError>(&memory_mapping, va_buffer, va_len, b"abc").unwrap(), 3);
+/// assert_eq!(&buffer[..4], b"abc\0");
+/// assert_eq!(u64::from_le_bytes(len), 3);
+/// // Truncated to the capacity of the buffer
+/// len = 2u64.to_le_bytes();
+/// assert_eq!(write_result_bytes::<UserError>(&memory_mapping, va_buffer, va_len, b"xyz").unwrap(), 2);
+/// assert_eq!(&buffer[..4], b"xyc\0");
+/// assert_eq!(u64::from_le_bytes(len), 3);
+/// // A capacity beyond the end of the region fails without writing anything
+/// len = 16u64.to_le_bytes();
+/// assert!(write_result_bytes::<UserError>(&memory_mapping, va_buffer, va_len, &[1; 12]).is_err());
+/// assert_eq!(&buffer[..4], b"xyc\0");
+/// assert_eq!(u64::from_le_bytes(len), 16);
+/// // The length must be mapped as well
+/// assert!(write_result_bytes::<UserError>(&memory_mapping, va_buffer, va_len + 4, b"abc").is_err());
+/// assert_eq!(&buffer[..4], b"xyc\0");
+/// ```
+pub fn write_result_bytes<E: UserDefinedError>(
+    memory_mapping: &MemoryMapping,
+    out_addr: u64,
+    out_len_addr: u64,
+    data: &[u8],
+) -> std::result::Result<u64, EbpfError<E>> {
+    let len_host_addr = memory_mapping.map::<E>(AccessType::Store, out_len_addr, 8)? as *mut u64;
+    let capacity = unsafe { len_host_addr.read_unaligned() };
+    let copied = capacity.min(data.len() as u64);
+    if copied > 0 {
+        let host_addr = memory_mapping.map::<E>(AccessType::Store, out_addr, copied)?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), host_addr as *mut u8, copied as usize);
+        }
+    }
+    unsafe { len_host_addr.write_unaligned(data.len() as u64) };
+    Ok(copied)
+}
+
 // bpf_trace_printk()
 
 /// Index of syscall `bpf_trace_printk()`, equivalent to `bpf_trace_printf`, in Linux kernel, see
 /// <https://git.kernel.org/cgit/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h>.
 pub const BPF_TRACE_PRINTK_IDX: u32 = 6;
 
-/// Prints its **last three** arguments to standard output. The **first two** arguments are
+/// Prints its **last three** arguments to its sink or standard output. The **first two** arguments are
 /// **unused**. Returns the number of bytes written.
 ///
 /// By ignoring the first two arguments, it creates a syscall that will have a behavior similar to
@@ -58,11 +140,27 @@ pub const BPF_TRACE_PRINTK_IDX: u32 = 6;
 /// let mut result: Result = Ok(0);
 /// let config = Config::default();
 /// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
-/// BpfTracePrintf::call(&mut BpfTracePrintf {}, 0, 0, 1, 15, 32, &memory_mapping, &mut result);
+/// BpfTracePrintf::call(&mut BpfTracePrintf { sink: None }, 0, 0, 1, 15, 32, &memory_mapping, &mut result);
 /// assert_eq!(result.unwrap() as usize, "BpfTracePrintf: 0x1, 0xf, 0x20\n".len());
 /// ```
 ///
-/// This will print `BpfTracePrintf: 0x1, 0xf, 0x20`.
+/// This will print `BpfTracePrintf: 0x1, 0xf, 0x20`. The output can be redirected into a sink:
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfTracePrintf, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// let output = Rc::new(RefCell::new(Vec::new()));
+/// let mut result: Result = Ok(0);
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// BpfTracePrintf::call(&mut BpfTracePrintf { sink: Some(output.clone()) }, 0, 0, 1, 15, 32, &memory_mapping, &mut result);
+/// assert_eq!(output.borrow().as_slice(), b"BpfTracePrintf: 0x1, 0xf, 0x20\n");
+/// assert_eq!(result.unwrap() as usize, output.borrow().len());
+/// ```
 ///
 /// The eBPF code needed to perform the call in this example would be nearly identical to the code
 /// obtained by compiling the following code from C to eBPF with clang:
@@ -82,11 +180,19 @@ pub const BPF_TRACE_PRINTK_IDX: u32 = 6;
 ///
 /// This would equally print the three numbers in `/sys/kernel/debug/tracing` file each time the
 /// program is run.
-pub struct BpfTracePrintf {}
+pub struct BpfTracePrintf {
+    /// Destination of the output
+    pub sink: BpfPrintSink,
+}
 impl BpfTracePrintf {
     /// new
     pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
-        Box::new(Self {})
+        Box::new(Self { sink: None })
+    }
+
+    /// new, writing to the given sink
+    pub fn init_with_sink<C, E>(sink: BpfPrintSink) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { sink })
     }
 }
 impl SyscallObject<UserError> for BpfTracePrintf {
@@ -100,7 +206,10 @@ impl SyscallObject<UserError> for BpfTracePrintf {
         _memory_mapping: &MemoryMapping,
         result: &mut Result,
     ) {
-        println!("BpfTracePrintf: {:#x}, {:#x}, {:#x}", arg3, arg4, arg5);
+        print_line(
+            &self.sink,
+            format_args!("BpfTracePrintf: {:#x}, {:#x}, {:#x}", arg3, arg4, arg5),
+        );
         let size_arg = |x| {
             if x == 0 {
                 1
@@ -216,17 +325,196 @@ impl SyscallObject<UserError> for BpfMemFrob {
     }
 }
 
+/// Same as `memcpy` in C, copies `n` bytes from `src` to `dst` and returns 0. Overlapping ranges are
+/// rejected with `EbpfError::CopyOverlapping`, use `BpfMemMove` for those.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfMemCpy, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::error::EbpfError;
+/// use solana_rbpf::user_error::UserError;
+///
+/// let src = [1u8, 2, 3, 4];
+/// let mut dst = [0u8; 8];
+/// let va_src = 0x100000000;
+/// let va_dst = 0x200000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(&src, va_src), MemoryRegion::new_writable(&mut dst, va_dst)], &config).unwrap();
+/// let mut result: Result = Ok(1);
+/// BpfMemCpy::call(&mut BpfMemCpy {}, va_dst, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(1);
+/// BpfMemCpy::call(&mut BpfMemCpy {}, va_dst + 2, va_src, 4, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(1);
+/// BpfMemCpy::call(&mut BpfMemCpy {}, va_dst, va_dst + 2, 4, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result, Err(EbpfError::CopyOverlapping(va_dst, va_dst + 2, 4)));
+/// let mut result: Result = Ok(1);
+/// BpfMemCpy::call(&mut BpfMemCpy {}, va_dst + 6, va_src, 4, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// assert_eq!(dst, [0, 0, 1, 2, 3, 4, 0, 0]);
+/// ```
+pub struct BpfMemCpy {}
+impl BpfMemCpy {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfMemCpy {
+    fn call(
+        &mut self,
+        dst_va: u64,
+        src_va: u64,
+        n: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        if n == 0 {
+            *result = Result::Ok(0);
+            return;
+        }
+        let dst = question_mark!(memory_mapping.map(AccessType::Store, dst_va, n), result);
+        let src = question_mark!(memory_mapping.map(AccessType::Load, src_va, n), result);
+        if dst < src.saturating_add(n) && src < dst.saturating_add(n) {
+            *result = Err(EbpfError::CopyOverlapping(dst_va, src_va, n));
+            return;
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, n as usize);
+        }
+        *result = Result::Ok(0);
+    }
+}
+
+/// Same as `memmove` in C, copies `n` bytes from `src` to `dst`, which may overlap, and returns 0.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfMemMove, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let mut val = [1u8, 2, 3, 4, 5, 6];
+/// let va_val = 0x100000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_writable(&mut val, va_val)], &config).unwrap();
+/// let mut result: Result = Ok(1);
+/// BpfMemMove::call(&mut BpfMemMove {}, va_val + 2, va_val, 4, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(1);
+/// BpfMemMove::call(&mut BpfMemMove {}, va_val, va_val, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// assert_eq!(val, [1, 2, 1, 2, 3, 4]);
+/// ```
+pub struct BpfMemMove {}
+impl BpfMemMove {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfMemMove {
+    fn call(
+        &mut self,
+        dst_va: u64,
+        src_va: u64,
+        n: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        if n == 0 {
+            *result = Result::Ok(0);
+            return;
+        }
+        let dst = question_mark!(memory_mapping.map(AccessType::Store, dst_va, n), result);
+        let src = question_mark!(memory_mapping.map(AccessType::Load, src_va, n), result);
+        unsafe {
+            std::ptr::copy(src as *const u8, dst as *mut u8, n as usize);
+        }
+        *result = Result::Ok(0);
+    }
+}
+
+/// Same as `memset` in C, fills `n` bytes at `dst` with the lowest byte of `byte` and returns 0.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfMemSet, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let mut val = [0u8; 6];
+/// let va_val = 0x100000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_writable(&mut val, va_val)], &config).unwrap();
+/// let mut result: Result = Ok(1);
+/// BpfMemSet::call(&mut BpfMemSet {}, va_val + 1, 0x1ab, 4, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(1);
+/// BpfMemSet::call(&mut BpfMemSet {}, va_val + 6, 0xff, 1, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// assert_eq!(val, [0, 0xab, 0xab, 0xab, 0xab, 0]);
+/// ```
+pub struct BpfMemSet {}
+impl BpfMemSet {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfMemSet {
+    fn call(
+        &mut self,
+        dst_va: u64,
+        byte: u64,
+        n: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        if n == 0 {
+            *result = Result::Ok(0);
+            return;
+        }
+        let dst = question_mark!(memory_mapping.map(AccessType::Store, dst_va, n), result);
+        unsafe {
+            std::ptr::write_bytes(dst as *mut u8, byte as u8, n as usize);
+        }
+        *result = Result::Ok(0);
+    }
+}
+
 /// C-like `strcmp`, return 0 if the strings are equal, and a non-null value otherwise.
 ///
+/// Every byte is mapped before it is read, so a string which is not NULL-terminated within its
+/// region results in an error.
+///
 /// # Examples
 ///
 /// ```
 /// use solana_rbpf::syscalls::{BpfStrCmp, Result};
 /// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
 /// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::error::EbpfError;
 ///
-/// let foo = "This is a string.";
-/// let bar = "This is another sting.";
+/// let foo = "This is a string.\0";
+/// let bar = "This is another sting.\0";
 /// let va_foo = 0x100000000;
 /// let va_bar = 0x200000000;
 /// use solana_rbpf::user_error::UserError;
@@ -240,6 +528,22 @@ impl SyscallObject<UserError> for BpfMemFrob {
 /// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(foo.as_bytes(), va_foo), MemoryRegion::new_readonly(bar.as_bytes(), va_bar)], &config).unwrap();
 /// BpfStrCmp::call(&mut BpfStrCmp {}, va_foo, va_bar, 0, 0, 0, &memory_mapping, &mut result);
 /// assert!(result.unwrap() != 0);
+///
+/// // Strings at the very end of their regions without a trailing NULL
+/// let abc = b"abc";
+/// let abd = b"abd";
+/// let va_abc = 0x300000000;
+/// let va_abd = 0x400000000;
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(foo.as_bytes(), va_foo), MemoryRegion::new_readonly(bar.as_bytes(), va_bar), MemoryRegion::new_readonly(abc, va_abc), MemoryRegion::new_readonly(abd, va_abd)], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfStrCmp::call(&mut BpfStrCmp {}, va_abc, va_abd, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 1);
+/// let mut result: Result = Ok(0);
+/// BpfStrCmp::call(&mut BpfStrCmp {}, va_abc, va_abc, 0, 0, 0, &memory_mapping, &mut result);
+/// assert!(matches!(result, Err(EbpfError::AccessViolation(_, _, vm_addr, 1, _)) if vm_addr == va_abc + 3));
+/// let mut result: Result = Ok(0);
+/// BpfStrCmp::call(&mut BpfStrCmp {}, va_foo, va_abc, 0, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.unwrap() != 0);
 /// ```
 pub struct BpfStrCmp {}
 impl BpfStrCmp {
@@ -264,34 +568,116 @@ impl SyscallObject<UserError> for BpfStrCmp {
             *result = Result::Ok(u64::MAX);
             return;
         }
-        let mut a = question_mark!(memory_mapping.map(AccessType::Load, arg1, 1), result);
-        let mut b = question_mark!(memory_mapping.map(AccessType::Load, arg2, 1), result);
-        unsafe {
-            let mut a_val = *(a as *const u8);
-            let mut b_val = *(b as *const u8);
-            while a_val == b_val && a_val != 0 && b_val != 0 {
-                a += 1;
-                b += 1;
-                a_val = *(a as *const u8);
-                b_val = *(b as *const u8);
+        let mut offset = 0u64;
+        loop {
+            let a = question_mark!(
+                memory_mapping.map(AccessType::Load, arg1.saturating_add(offset), 1),
+                result
+            );
+            let b = question_mark!(
+                memory_mapping.map(AccessType::Load, arg2.saturating_add(offset), 1),
+                result
+            );
+            let (a_val, b_val) = unsafe { (*(a as *const u8), *(b as *const u8)) };
+            if a_val != b_val || a_val == 0 {
+                *result = if a_val >= b_val {
+                    Result::Ok((a_val - b_val) as u64)
+                } else {
+                    Result::Ok((b_val - a_val) as u64)
+                };
+                return;
+            }
+            offset = offset.saturating_add(1);
+        }
+    }
+}
+
+/// C-like `strlen`, returns the length of the NULL-terminated string at `vm_addr`.
+///
+/// Every byte is mapped before it is read. If there is no NULL among the first `max_len` bytes the
+/// result is `EbpfError::InvalidVirtualAddress` of the address after them.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfStrLen, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::error::EbpfError;
+/// use solana_rbpf::user_error::UserError;
+///
+/// let empty = b"\0";
+/// let boundary = b"boundary\0";
+/// let unterminated = b"unterminated";
+/// let va_empty = 0x100000000;
+/// let va_boundary = 0x200000000;
+/// let va_unterminated = 0x300000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(empty, va_empty), MemoryRegion::new_readonly(boundary, va_boundary), MemoryRegion::new_readonly(unterminated, va_unterminated)], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfStrLen::call(&mut BpfStrLen {}, va_empty, 16, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(0);
+/// BpfStrLen::call(&mut BpfStrLen {}, va_boundary, 16, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 8);
+/// let mut result: Result = Ok(0);
+/// BpfStrLen::call(&mut BpfStrLen {}, va_boundary, 8, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result, Err(EbpfError::InvalidVirtualAddress(va_boundary + 8)));
+/// let mut result: Result = Ok(0);
+/// BpfStrLen::call(&mut BpfStrLen {}, va_unterminated, 16, 0, 0, 0, &memory_mapping, &mut result);
+/// assert!(matches!(result, Err(EbpfError::AccessViolation(..))));
+/// ```
+pub struct BpfStrLen {}
+impl BpfStrLen {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfStrLen {
+    fn call(
+        &mut self,
+        vm_addr: u64,
+        max_len: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        for len in 0..max_len {
+            let host_addr = question_mark!(
+                memory_mapping.map(AccessType::Load, vm_addr.saturating_add(len), 1),
+                result
+            );
+            if unsafe { *(host_addr as *const u8) } == 0 {
+                *result = Result::Ok(len);
+                return;
             }
-            *result = if a_val >= b_val {
-                Result::Ok((a_val - b_val) as u64)
-            } else {
-                Result::Ok((b_val - a_val) as u64)
-            };
         }
+        *result = Err(EbpfError::InvalidVirtualAddress(
+            vm_addr.saturating_add(max_len),
+        ));
     }
 }
 
 // Some additional syscalls
 
 /// Prints a NULL-terminated UTF-8 string.
-pub struct BpfSyscallString {}
+pub struct BpfSyscallString {
+    /// Destination of the output
+    pub sink: BpfPrintSink,
+}
 impl BpfSyscallString {
     /// new
     pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
-        Box::new(Self {})
+        Box::new(Self { sink: None })
+    }
+
+    /// new, writing to the given sink
+    pub fn init_with_sink<C, E>(sink: BpfPrintSink) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { sink })
     }
 }
 impl SyscallObject<UserError> for BpfSyscallString {
@@ -316,18 +702,26 @@ impl SyscallObject<UserError> for BpfSyscallString {
             }
             let message = from_utf8(from_raw_parts(host_addr as *const u8, len as usize))
                 .unwrap_or("Invalid UTF-8 String");
-            println!("log: {}", message);
+            print_line(&self.sink, format_args!("log: {}", message));
         }
         *result = Result::Ok(0);
     }
 }
 
 /// Prints the five arguments formated as u64 in decimal.
-pub struct BpfSyscallU64 {}
+pub struct BpfSyscallU64 {
+    /// Destination of the output
+    pub sink: BpfPrintSink,
+}
 impl BpfSyscallU64 {
     /// new
     pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
-        Box::new(Self {})
+        Box::new(Self { sink: None })
+    }
+
+    /// new, writing to the given sink
+    pub fn init_with_sink<C, E>(sink: BpfPrintSink) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { sink })
     }
 }
 impl SyscallObject<UserError> for BpfSyscallU64 {
@@ -341,9 +735,12 @@ impl SyscallObject<UserError> for BpfSyscallU64 {
         memory_mapping: &MemoryMapping,
         result: &mut Result,
     ) {
-        println!(
-            "dump_64: {:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:?}",
-            arg1, arg2, arg3, arg4, arg5, memory_mapping as *const _
+        print_line(
+            &self.sink,
+            format_args!(
+                "dump_64: {:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:?}",
+                arg1, arg2, arg3, arg4, arg5, memory_mapping as *const _
+            ),
         );
         *result = Result::Ok(0);
     }
@@ -380,3 +777,1147 @@ impl SyscallObject<UserError> for SyscallWithContext {
         *result = Result::Ok(0);
     }
 }
+
+/// Context of `BpfInstructionsExecuted`
+#[derive(Clone)]
+pub struct BpfInstructionCountContext {
+    /// Instruction meter shared with the VM
+    pub instruction_meter: Rc<RefCell<dyn InstructionMeter>>,
+    /// Remaining instructions at the start of the current run, see `EbpfVm::get_run_start_handle`
+    pub run_start_remaining: Rc<Cell<u64>>,
+}
+
+/// Returns the number of instructions executed in the current run, including the call.
+///
+/// The count restarts with every run of the VM whose run start handle is in the context. It only
+/// advances if the instruction meter is enabled.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfInstructionsExecuted, BpfInstructionCountContext, Result};
+/// use solana_rbpf::memory_region::MemoryMapping;
+/// use solana_rbpf::vm::{Config, InstructionMeter, SyscallObject, TestInstructionMeter};
+/// use solana_rbpf::user_error::UserError;
+/// use std::{cell::{Cell, RefCell}, rc::Rc};
+///
+/// let instruction_meter = Rc::new(RefCell::new(TestInstructionMeter { remaining: 100 }));
+/// let mut syscall = BpfInstructionsExecuted {
+///     context: BpfInstructionCountContext {
+///         instruction_meter: instruction_meter.clone(),
+///         run_start_remaining: Rc::new(Cell::new(90)),
+///     },
+/// };
+/// instruction_meter.borrow_mut().consume(42);
+///
+/// let mut result: Result = Ok(0);
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// BpfInstructionsExecuted::call(&mut syscall, 0, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 32);
+/// ```
+pub struct BpfInstructionsExecuted {
+    /// Instruction meter and run start
+    pub context: BpfInstructionCountContext,
+}
+impl BpfInstructionsExecuted {
+    /// new
+    pub fn init<C, E>(context: BpfInstructionCountContext) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { context })
+    }
+}
+impl SyscallObject<UserError> for BpfInstructionsExecuted {
+    fn call(
+        &mut self,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let remaining = self.context.instruction_meter.borrow().get_remaining();
+        *result = Result::Ok(
+            self.context
+                .run_start_remaining
+                .get()
+                .saturating_sub(remaining),
+        );
+    }
+}
+
+/// Returns the number of instructions executed in the current run, same as
+/// `BpfInstructionsExecuted`.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfGetInstructionCount, BpfInstructionCountContext, Result};
+/// use solana_rbpf::memory_region::MemoryMapping;
+/// use solana_rbpf::vm::{Config, InstructionMeter, SyscallObject, TestInstructionMeter};
+/// use solana_rbpf::user_error::UserError;
+/// use std::{cell::{Cell, RefCell}, rc::Rc};
+///
+/// let instruction_meter = Rc::new(RefCell::new(TestInstructionMeter { remaining: 100 }));
+/// let mut syscall = BpfGetInstructionCount {
+///     context: BpfInstructionCountContext {
+///         instruction_meter: instruction_meter.clone(),
+///         run_start_remaining: Rc::new(Cell::new(100)),
+///     },
+/// };
+/// instruction_meter.borrow_mut().consume(7);
+///
+/// let mut result: Result = Ok(0);
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// BpfGetInstructionCount::call(&mut syscall, 0, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 7);
+/// ```
+pub type BpfGetInstructionCount = BpfInstructionsExecuted;
+
+/// Returns the instruction index of the `call` which invoked it.
+///
+/// The context is the handle returned by `EbpfVm::get_current_pc_handle`.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfCurrentPc, Result};
+/// use solana_rbpf::memory_region::MemoryMapping;
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+/// use std::{cell::Cell, rc::Rc};
+///
+/// let current_pc = Rc::new(Cell::new(0));
+/// let mut syscall = BpfCurrentPc { current_pc: current_pc.clone() };
+/// current_pc.set(42);
+///
+/// let mut result: Result = Ok(0);
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// BpfCurrentPc::call(&mut syscall, 0, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 42);
+/// ```
+pub struct BpfCurrentPc {
+    /// Pc of the calling instruction, set by the VM
+    pub current_pc: Rc<Cell<u64>>,
+}
+impl BpfCurrentPc {
+    /// new
+    pub fn init<C, E>(current_pc: Rc<Cell<u64>>) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { current_pc })
+    }
+}
+impl SyscallObject<UserError> for BpfCurrentPc {
+    fn call(
+        &mut self,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok(self.current_pc.get());
+    }
+}
+
+/// Looks up `key` in a readonly table of `table_len` little-endian `u64` entries.
+///
+/// Returns `u64::MAX` if `key` is out of range. The whole table is bounds checked.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfTableLookup, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let table = [10u64, 20, 30];
+/// let table_bytes: Vec<u8> = table.iter().flat_map(|entry| entry.to_le_bytes()).collect();
+/// let va_table = 0x100000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(&table_bytes, va_table)], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfTableLookup::call(&mut BpfTableLookup {}, va_table, 3, 2, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 30);
+/// let mut result: Result = Ok(0);
+/// BpfTableLookup::call(&mut BpfTableLookup {}, va_table, 3, 3, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), u64::MAX);
+/// let mut result: Result = Ok(0);
+/// BpfTableLookup::call(&mut BpfTableLookup {}, va_table, 4, 0, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// ```
+pub struct BpfTableLookup {}
+impl BpfTableLookup {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfTableLookup {
+    fn call(
+        &mut self,
+        table_va: u64,
+        table_len: u64,
+        key: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let host_addr = question_mark!(
+            memory_mapping.map(
+                AccessType::Load,
+                table_va,
+                table_len.saturating_mul(std::mem::size_of::<u64>() as u64)
+            ),
+            result
+        );
+        if key >= table_len {
+            *result = Result::Ok(u64::MAX);
+            return;
+        }
+        let entry =
+            unsafe { std::ptr::read_unaligned((host_addr as *const u64).add(key as usize)) };
+        *result = Result::Ok(u64::from_le(entry));
+    }
+}
+
+/// Scratch value shared by `BpfScratchSet` and `BpfScratchGet`
+pub type BpfScratchContext = Rc<RefCell<u64>>;
+
+/// Stores its first argument in the scratch value, which the host can inspect after the run.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfScratchGet, BpfScratchSet, Result};
+/// use solana_rbpf::memory_region::MemoryMapping;
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// let scratch = Rc::new(RefCell::new(0));
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfScratchSet::call(&mut BpfScratchSet { scratch: scratch.clone() }, 42, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(*scratch.borrow(), 42);
+/// let mut result: Result = Ok(0);
+/// BpfScratchGet::call(&mut BpfScratchGet { scratch }, 0, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 42);
+/// ```
+pub struct BpfScratchSet {
+    /// Scratch value shared with `BpfScratchGet`
+    pub scratch: BpfScratchContext,
+}
+impl BpfScratchSet {
+    /// new
+    pub fn init<C, E>(scratch: BpfScratchContext) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { scratch })
+    }
+}
+impl SyscallObject<UserError> for BpfScratchSet {
+    fn call(
+        &mut self,
+        value: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *self.scratch.borrow_mut() = value;
+        *result = Result::Ok(0);
+    }
+}
+
+/// Returns the scratch value last stored by `BpfScratchSet`.
+pub struct BpfScratchGet {
+    /// Scratch value shared with `BpfScratchSet`
+    pub scratch: BpfScratchContext,
+}
+impl BpfScratchGet {
+    /// new
+    pub fn init<C, E>(scratch: BpfScratchContext) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { scratch })
+    }
+}
+impl SyscallObject<UserError> for BpfScratchGet {
+    fn call(
+        &mut self,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok(*self.scratch.borrow());
+    }
+}
+
+/// Copies `min(dst_cap, src_len)` bytes from `src_va` to `dst_va` and returns the number copied.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfCopyClamped, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let src = [1u8, 2, 3, 4, 5, 6];
+/// let mut dst = [0u8; 4];
+/// let va_src = 0x100000000;
+/// let va_dst = 0x200000000;
+///
+/// let mut result: Result = Ok(0);
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(&src, va_src), MemoryRegion::new_writable(&mut dst, va_dst)], &config).unwrap();
+/// BpfCopyClamped::call(&mut BpfCopyClamped {}, va_dst, 4, va_src, 6, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 4);
+/// assert_eq!(dst, [1, 2, 3, 4]);
+/// ```
+pub struct BpfCopyClamped {}
+impl BpfCopyClamped {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfCopyClamped {
+    fn call(
+        &mut self,
+        dst_va: u64,
+        dst_cap: u64,
+        src_va: u64,
+        src_len: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let len = dst_cap.min(src_len);
+        let dst = question_mark!(memory_mapping.map(AccessType::Store, dst_va, len), result);
+        let src = question_mark!(memory_mapping.map(AccessType::Load, src_va, len), result);
+        unsafe {
+            std::ptr::copy(src as *const u8, dst as *mut u8, len as usize);
+        }
+        *result = Result::Ok(len);
+    }
+}
+
+/// Returns 1 if the `len` bytes at `vm_addr` are valid UTF-8, and 0 otherwise.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfIsValidUtf8, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let valid = "Grüße".as_bytes();
+/// let invalid = [0x66, 0x6f, 0xff, 0x6f];
+/// let va_valid = 0x100000000;
+/// let va_invalid = 0x200000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(valid, va_valid), MemoryRegion::new_readonly(&invalid, va_invalid)], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfIsValidUtf8::call(&mut BpfIsValidUtf8 {}, va_valid, valid.len() as u64, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 1);
+/// let mut result: Result = Ok(0);
+/// BpfIsValidUtf8::call(&mut BpfIsValidUtf8 {}, va_invalid, invalid.len() as u64, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(0);
+/// BpfIsValidUtf8::call(&mut BpfIsValidUtf8 {}, va_invalid, 5, 0, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// ```
+pub struct BpfIsValidUtf8 {}
+impl BpfIsValidUtf8 {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfIsValidUtf8 {
+    fn call(
+        &mut self,
+        vm_addr: u64,
+        len: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let host_addr = question_mark!(memory_mapping.map(AccessType::Load, vm_addr, len), result);
+        let bytes = unsafe { from_raw_parts(host_addr as *const u8, len as usize) };
+        *result = Result::Ok(from_utf8(bytes).is_ok() as u64);
+    }
+}
+
+/// Clamps `value` into `[lo, hi]`, all treated as unsigned. If `lo > hi` the result is `hi`.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfClamp, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfClamp::call(&mut BpfClamp {}, 3, 10, 20, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 10);
+/// let mut result: Result = Ok(0);
+/// BpfClamp::call(&mut BpfClamp {}, 15, 10, 20, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 15);
+/// let mut result: Result = Ok(0);
+/// BpfClamp::call(&mut BpfClamp {}, u64::MAX, 10, 20, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 20);
+/// ```
+pub struct BpfClamp {}
+impl BpfClamp {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfClamp {
+    fn call(
+        &mut self,
+        value: u64,
+        lo: u64,
+        hi: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok(value.max(lo).min(hi));
+    }
+}
+
+/// Clamps `value` into `[lo, hi]`, all treated as signed. If `lo > hi` the result is `hi`.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfClampSigned, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfClampSigned::call(&mut BpfClampSigned {}, -30i64 as u64, -10i64 as u64, 20, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, -10);
+/// let mut result: Result = Ok(0);
+/// BpfClampSigned::call(&mut BpfClampSigned {}, -5i64 as u64, -10i64 as u64, 20, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, -5);
+/// let mut result: Result = Ok(0);
+/// BpfClampSigned::call(&mut BpfClampSigned {}, u64::MAX >> 1, -10i64 as u64, 20, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, 20);
+/// ```
+pub struct BpfClampSigned {}
+impl BpfClampSigned {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfClampSigned {
+    fn call(
+        &mut self,
+        value: u64,
+        lo: u64,
+        hi: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok((value as i64).max(lo as i64).min(hi as i64) as u64);
+    }
+}
+
+/// Returns the position of the most significant set bit of `value`, or u64::MAX if `value` is 0.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfIlog2, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// for shift in 0..64 {
+///     let mut result: Result = Ok(0);
+///     BpfIlog2::call(&mut BpfIlog2 {}, 1 << shift, 0, 0, 0, 0, &memory_mapping, &mut result);
+///     assert_eq!(result.unwrap(), shift);
+/// }
+/// let mut result: Result = Ok(0);
+/// BpfIlog2::call(&mut BpfIlog2 {}, 0x0fff, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 11);
+/// let mut result: Result = Ok(0);
+/// BpfIlog2::call(&mut BpfIlog2 {}, 0, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), u64::MAX);
+/// ```
+pub struct BpfIlog2 {}
+impl BpfIlog2 {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfIlog2 {
+    fn call(
+        &mut self,
+        value: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok(if value == 0 {
+            u64::MAX
+        } else {
+            63 - value.leading_zeros() as u64
+        });
+    }
+}
+
+/// Returns `a + b` treated as unsigned, saturating at `u64::MAX`.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfSaturatingAdd, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingAdd::call(&mut BpfSaturatingAdd {}, u64::MAX, 1, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), u64::MAX);
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingAdd::call(&mut BpfSaturatingAdd {}, 40, 2, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 42);
+/// ```
+pub struct BpfSaturatingAdd {}
+impl BpfSaturatingAdd {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfSaturatingAdd {
+    fn call(
+        &mut self,
+        a: u64,
+        b: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok(a.saturating_add(b));
+    }
+}
+
+/// Returns `a - b` treated as unsigned, saturating at 0.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfSaturatingSub, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingSub::call(&mut BpfSaturatingSub {}, 0, 1, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingSub::call(&mut BpfSaturatingSub {}, 44, 2, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 42);
+/// ```
+pub struct BpfSaturatingSub {}
+impl BpfSaturatingSub {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfSaturatingSub {
+    fn call(
+        &mut self,
+        a: u64,
+        b: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok(a.saturating_sub(b));
+    }
+}
+
+/// Returns `a + b` treated as signed, saturating at `i64::MIN` and `i64::MAX`.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfSaturatingAddSigned, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingAddSigned::call(&mut BpfSaturatingAddSigned {}, i64::MAX as u64, 1, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, i64::MAX);
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingAddSigned::call(&mut BpfSaturatingAddSigned {}, i64::MIN as u64, -1i64 as u64, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, i64::MIN);
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingAddSigned::call(&mut BpfSaturatingAddSigned {}, -44i64 as u64, 2, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, -42);
+/// ```
+pub struct BpfSaturatingAddSigned {}
+impl BpfSaturatingAddSigned {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfSaturatingAddSigned {
+    fn call(
+        &mut self,
+        a: u64,
+        b: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok((a as i64).saturating_add(b as i64) as u64);
+    }
+}
+
+/// Returns `a - b` treated as signed, saturating at `i64::MIN` and `i64::MAX`.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfSaturatingSubSigned, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingSubSigned::call(&mut BpfSaturatingSubSigned {}, i64::MIN as u64, 1, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, i64::MIN);
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingSubSigned::call(&mut BpfSaturatingSubSigned {}, i64::MAX as u64, -1i64 as u64, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, i64::MAX);
+/// let mut result: Result = Ok(0);
+/// BpfSaturatingSubSigned::call(&mut BpfSaturatingSubSigned {}, 0, 1, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap() as i64, -1);
+/// ```
+pub struct BpfSaturatingSubSigned {}
+impl BpfSaturatingSubSigned {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfSaturatingSubSigned {
+    fn call(
+        &mut self,
+        a: u64,
+        b: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Result::Ok((a as i64).saturating_sub(b as i64) as u64);
+    }
+}
+
+/// Parses the base-10 digits at the start of the `len` bytes at `vm_addr`, writes the value as u64
+/// to `out_va` and returns the number of digits consumed. Parsing stops at the first non-digit.
+/// Returns u64::MAX without writing the output if the value does not fit into a u64.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfParseU64, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let digits = b"12345";
+/// let trailing = b"42abc";
+/// let overflow = b"18446744073709551616";
+/// let mut out = [0u8; 8];
+/// let va_digits = 0x100000000;
+/// let va_trailing = 0x200000000;
+/// let va_overflow = 0x300000000;
+/// let va_out = 0x400000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(digits, va_digits), MemoryRegion::new_readonly(trailing, va_trailing), MemoryRegion::new_readonly(overflow, va_overflow), MemoryRegion::new_writable(&mut out, va_out)], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfParseU64::call(&mut BpfParseU64 {}, va_digits, 5, va_out, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 5);
+/// assert_eq!(u64::from_le_bytes(out), 12345);
+/// let mut result: Result = Ok(0);
+/// BpfParseU64::call(&mut BpfParseU64 {}, va_trailing, 5, va_out, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 2);
+/// assert_eq!(u64::from_le_bytes(out), 42);
+/// let mut result: Result = Ok(0);
+/// BpfParseU64::call(&mut BpfParseU64 {}, va_overflow, 20, va_out, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), u64::MAX);
+/// assert_eq!(u64::from_le_bytes(out), 42);
+/// let mut result: Result = Ok(0);
+/// BpfParseU64::call(&mut BpfParseU64 {}, va_digits, 5, va_out + 4, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// ```
+pub struct BpfParseU64 {}
+impl BpfParseU64 {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfParseU64 {
+    fn call(
+        &mut self,
+        vm_addr: u64,
+        len: u64,
+        out_va: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let host_addr = question_mark!(memory_mapping.map(AccessType::Load, vm_addr, len), result);
+        let out = question_mark!(memory_mapping.map(AccessType::Store, out_va, 8), result);
+        let bytes = unsafe { from_raw_parts(host_addr as *const u8, len as usize) };
+        let mut value = 0u64;
+        let mut consumed = 0;
+        for byte in bytes.iter().take_while(|byte| byte.is_ascii_digit()) {
+            value = match value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add((*byte - b'0') as u64))
+            {
+                Some(value) => value,
+                None => {
+                    *result = Result::Ok(u64::MAX);
+                    return;
+                }
+            };
+            consumed += 1;
+        }
+        unsafe {
+            std::ptr::write_unaligned(out as *mut u64, value);
+        }
+        *result = Result::Ok(consumed);
+    }
+}
+
+/// Returns the index of the first occurrence of the `needle_len` bytes at `needle_va` in the
+/// `haystack_len` bytes at `haystack_va`, or u64::MAX if there is none. An empty needle is found at
+/// index 0. Both buffers are bounds checked in full before searching.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfFind, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let haystack = b"key=value;";
+/// let needle = b"value";
+/// let absent = b"other";
+/// let va_haystack = 0x100000000;
+/// let va_needle = 0x200000000;
+/// let va_absent = 0x300000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(haystack, va_haystack), MemoryRegion::new_readonly(needle, va_needle), MemoryRegion::new_readonly(absent, va_absent)], &config).unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfFind::call(&mut BpfFind {}, va_haystack, 10, va_needle, 5, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 4);
+/// let mut result: Result = Ok(0);
+/// BpfFind::call(&mut BpfFind {}, va_haystack, 10, va_absent, 5, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), u64::MAX);
+/// let mut result: Result = Ok(0);
+/// BpfFind::call(&mut BpfFind {}, va_haystack, 10, va_needle, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(0);
+/// BpfFind::call(&mut BpfFind {}, va_haystack, 11, va_needle, 5, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// ```
+pub struct BpfFind {}
+impl BpfFind {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for BpfFind {
+    fn call(
+        &mut self,
+        haystack_va: u64,
+        haystack_len: u64,
+        needle_va: u64,
+        needle_len: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let haystack_addr = question_mark!(
+            memory_mapping.map(AccessType::Load, haystack_va, haystack_len),
+            result
+        );
+        let needle_addr = question_mark!(
+            memory_mapping.map(AccessType::Load, needle_va, needle_len),
+            result
+        );
+        if needle_len == 0 {
+            *result = Result::Ok(0);
+            return;
+        }
+        let haystack = unsafe { from_raw_parts(haystack_addr as *const u8, haystack_len as usize) };
+        let needle = unsafe { from_raw_parts(needle_addr as *const u8, needle_len as usize) };
+        *result = Result::Ok(
+            haystack
+                .windows(needle.len())
+                .position(|window| window == needle)
+                .map(|index| index as u64)
+                .unwrap_or(u64::MAX),
+        );
+    }
+}
+
+/// Maximum number of hashes which can be in progress at the same time in `BpfSha256States`
+#[cfg(feature = "sha256")]
+pub const BPF_SHA256_MAX_STATES: usize = 8;
+
+/// Table of in-progress hashes shared by `BpfSha256Init`, `BpfSha256Update` and `BpfSha256Final`
+#[cfg(feature = "sha256")]
+#[derive(Default)]
+pub struct BpfSha256States {
+    states: Vec<Option<Sha256>>,
+}
+#[cfg(feature = "sha256")]
+impl BpfSha256States {
+    /// Number of hashes in progress
+    pub fn len(&self) -> usize {
+        self.states.iter().filter(|state| state.is_some()).count()
+    }
+
+    /// Returns true if no hash is in progress
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get_mut(&mut self, handle: u64) -> Option<&mut Sha256> {
+        self.states
+            .get_mut(handle as usize)
+            .and_then(|state| state.as_mut())
+    }
+}
+
+/// Hash states shared by `BpfSha256Init`, `BpfSha256Update` and `BpfSha256Final`
+#[cfg(feature = "sha256")]
+pub type BpfSha256Context = Rc<RefCell<BpfSha256States>>;
+
+/// Starts a SHA-256 hash and returns its handle.
+///
+/// Returns `u64::MAX` if `BPF_SHA256_MAX_STATES` hashes are already in progress. Requires the
+/// `sha256` feature.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfSha256Final, BpfSha256Init, BpfSha256Update, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// let hello = b"hello ";
+/// let world = b"world";
+/// let mut digest = [0u8; 32];
+/// let va_hello = 0x100000000;
+/// let va_world = 0x200000000;
+/// let va_digest = 0x300000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(hello, va_hello), MemoryRegion::new_readonly(world, va_world), MemoryRegion::new_writable(&mut digest, va_digest)], &config).unwrap();
+/// let states = Rc::new(RefCell::new(Default::default()));
+/// let mut init = BpfSha256Init { states: states.clone() };
+/// let mut update = BpfSha256Update { states: states.clone() };
+/// let mut finalize = BpfSha256Final { states: states.clone() };
+/// let mut result: Result = Ok(0);
+/// BpfSha256Init::call(&mut init, 0, 0, 0, 0, 0, &memory_mapping, &mut result);
+/// let handle = result.unwrap();
+/// let mut result: Result = Ok(0);
+/// BpfSha256Update::call(&mut update, handle, va_hello, 6, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(0);
+/// BpfSha256Update::call(&mut update, handle, va_world, 6, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// let mut result: Result = Ok(0);
+/// BpfSha256Update::call(&mut update, handle, va_world, 5, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// let mut result: Result = Ok(0);
+/// BpfSha256Final::call(&mut finalize, handle, va_digest, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// assert_eq!(digest, [
+///     0xb9, 0x4d, 0x27, 0xb9, 0x93, 0x4d, 0x3e, 0x08, 0xa5, 0x2e, 0x52, 0xd7, 0xda, 0x7d, 0xab, 0xfa,
+///     0xc4, 0x84, 0xef, 0xe3, 0x7a, 0x53, 0x80, 0xee, 0x90, 0x88, 0xf7, 0xac, 0xe2, 0xef, 0xcd, 0xe9,
+/// ]);
+/// assert!(states.borrow().is_empty());
+/// let mut result: Result = Ok(0);
+/// BpfSha256Final::call(&mut finalize, handle, va_digest, 0, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), u64::MAX);
+/// ```
+#[cfg(feature = "sha256")]
+pub struct BpfSha256Init {
+    /// Hash states shared with `BpfSha256Update` and `BpfSha256Final`
+    pub states: BpfSha256Context,
+}
+#[cfg(feature = "sha256")]
+impl BpfSha256Init {
+    /// new
+    pub fn init<C, E>(states: BpfSha256Context) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { states })
+    }
+}
+#[cfg(feature = "sha256")]
+impl SyscallObject<UserError> for BpfSha256Init {
+    fn call(
+        &mut self,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let states = &mut self.states.borrow_mut().states;
+        let handle = match states.iter().position(|state| state.is_none()) {
+            Some(handle) => handle,
+            None if states.len() < BPF_SHA256_MAX_STATES => {
+                states.push(None);
+                states.len() - 1
+            }
+            None => {
+                *result = Result::Ok(u64::MAX);
+                return;
+            }
+        };
+        states[handle] = Some(Sha256::default());
+        *result = Result::Ok(handle as u64);
+    }
+}
+
+/// Adds `len` bytes at `src_va` to the hash with the given handle.
+///
+/// Returns `u64::MAX` if the handle is invalid and 0 otherwise.
+#[cfg(feature = "sha256")]
+pub struct BpfSha256Update {
+    /// Hash states shared with `BpfSha256Init` and `BpfSha256Final`
+    pub states: BpfSha256Context,
+}
+#[cfg(feature = "sha256")]
+impl BpfSha256Update {
+    /// new
+    pub fn init<C, E>(states: BpfSha256Context) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { states })
+    }
+}
+#[cfg(feature = "sha256")]
+impl SyscallObject<UserError> for BpfSha256Update {
+    fn call(
+        &mut self,
+        handle: u64,
+        src_va: u64,
+        len: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let host_addr = question_mark!(memory_mapping.map(AccessType::Load, src_va, len), result);
+        let mut states = self.states.borrow_mut();
+        let state = match states.get_mut(handle) {
+            Some(state) => state,
+            None => {
+                *result = Result::Ok(u64::MAX);
+                return;
+            }
+        };
+        state.update(unsafe { from_raw_parts(host_addr as *const u8, len as usize) });
+        *result = Result::Ok(0);
+    }
+}
+
+/// Writes the 32 byte digest of the hash with the given handle to `out_va` and releases the handle.
+///
+/// Returns `u64::MAX` if the handle is invalid and 0 otherwise.
+#[cfg(feature = "sha256")]
+pub struct BpfSha256Final {
+    /// Hash states shared with `BpfSha256Init` and `BpfSha256Update`
+    pub states: BpfSha256Context,
+}
+#[cfg(feature = "sha256")]
+impl BpfSha256Final {
+    /// new
+    pub fn init<C, E>(states: BpfSha256Context) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self { states })
+    }
+}
+#[cfg(feature = "sha256")]
+impl SyscallObject<UserError> for BpfSha256Final {
+    fn call(
+        &mut self,
+        handle: u64,
+        out_va: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let out = question_mark!(memory_mapping.map(AccessType::Store, out_va, 32), result);
+        let state = match self
+            .states
+            .borrow_mut()
+            .states
+            .get_mut(handle as usize)
+            .and_then(|state| state.take())
+        {
+            Some(state) => state,
+            None => {
+                *result = Result::Ok(u64::MAX);
+                return;
+            }
+        };
+        let digest = state.finalize();
+        unsafe {
+            std::ptr::copy_nonoverlapping(digest.as_ptr(), out as *mut u8, digest.len());
+        }
+        *result = Result::Ok(0);
+    }
+}
+
+/// Writes the 32 byte SHA-256 digest of the `input_len` bytes at `input_va` to `out_va`.
+///
+/// Returns 0, mapping failures of the input or the output are reported as errors. Requires the
+/// `sha256` feature.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::syscalls::{BpfSha256, Result};
+/// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
+/// use solana_rbpf::vm::{Config, SyscallObject};
+/// use solana_rbpf::user_error::UserError;
+///
+/// let abc = b"abc";
+/// let mut digest = [0u8; 40];
+/// let va_abc = 0x100000000;
+/// let va_digest = 0x200000000;
+///
+/// let config = Config::default();
+/// let memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(abc, va_abc), MemoryRegion::new_writable(&mut digest, va_digest)], &config).unwrap();
+/// let mut result: Result = Ok(1);
+/// BpfSha256::call(&mut BpfSha256 {}, va_abc, 0, va_digest, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// assert_eq!(digest[..32], [
+///     0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9, 0x24,
+///     0x27, 0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c, 0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52, 0xb8, 0x55,
+/// ]);
+/// let mut result: Result = Ok(1);
+/// BpfSha256::call(&mut BpfSha256 {}, va_abc, 3, va_digest + 8, 0, 0, &memory_mapping, &mut result);
+/// assert_eq!(result.unwrap(), 0);
+/// assert_eq!(digest[8..], [
+///     0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22, 0x23,
+///     0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad,
+/// ]);
+/// // The output straddles the end of its region
+/// let mut result: Result = Ok(1);
+/// BpfSha256::call(&mut BpfSha256 {}, va_abc, 3, va_digest + 16, 0, 0, &memory_mapping, &mut result);
+/// assert!(result.is_err());
+/// assert_eq!(digest[8..16], [0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea]);
+/// ```
+#[cfg(feature = "sha256")]
+pub struct BpfSha256 {}
+#[cfg(feature = "sha256")]
+impl BpfSha256 {
+    /// new
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+#[cfg(feature = "sha256")]
+impl SyscallObject<UserError> for BpfSha256 {
+    fn call(
+        &mut self,
+        input_va: u64,
+        input_len: u64,
+        out_va: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        let input = question_mark!(
+            memory_mapping.map(AccessType::Load, input_va, input_len),
+            result
+        );
+        let out = question_mark!(memory_mapping.map(AccessType::Store, out_va, 32), result);
+        let mut state = Sha256::default();
+        state.update(unsafe { from_raw_parts(input as *const u8, input_len as usize) });
+        let digest = state.finalize();
+        unsafe {
+            std::ptr::copy_nonoverlapping(digest.as_ptr(), out as *mut u8, digest.len());
+        }
+        *result = Result::Ok(0);
+    }
+}
@@ -22,7 +22,13 @@
 //!
 //! Contrary to the verifier of the Linux kernel, this one does not modify the bytecode at all.
 
-use crate::{ebpf, vm::Config};
+use crate::{
+    ebpf,
+    elf::Executable,
+    error::UserDefinedError,
+    vm::{Config, InstructionMeter},
+};
+use std::collections::{BTreeSet, HashSet};
 use thiserror::Error;
 
 /// Error definitions
@@ -76,6 +82,86 @@ pub enum VerifierError {
     /// Invalid register specified
     #[error("Invalid register specified at instruction {0}")]
     InvalidRegister(usize),
+    /// Too many distinct syscalls
+    #[error("more than {0} distinct syscalls are called (insn #{1})")]
+    TooManyDistinctSyscalls(usize, usize),
+    /// Frame pointer used in arithmetic other than adding / subtracting
+    #[error("illegal arithmetic on the frame pointer (insn #{0})")]
+    IllegalFramePointerArithmetic(usize),
+    /// Opcode is valid but not in Config::allowed_opcodes
+    #[error("opcode {0:#2x} is not allowed (insn #{1:?})")]
+    DisallowedOpCode(u8, usize),
+    /// Unconditional jump to itself
+    #[error("unconditional jump to itself (insn #{0})")]
+    UnconditionalSelfLoop(usize),
+    /// A function calls too many distinct functions
+    #[error("a function calls more than {0} distinct functions (insn #{1})")]
+    TooManyCallees(usize, usize),
+}
+
+impl VerifierError {
+    /// Names of the `Config` fields consulted by the check which reported this error
+    ///
+    /// Meant for finding out which setting changed the outcome of a verification.
+    pub fn config_options(&self) -> &'static [&'static str] {
+        match self {
+            VerifierError::UnknownOpCode(opc, _) => match *opc {
+                ebpf::LD_ABS_B
+                | ebpf::LD_ABS_H
+                | ebpf::LD_ABS_W
+                | ebpf::LD_ABS_DW
+                | ebpf::LD_IND_B
+                | ebpf::LD_IND_H
+                | ebpf::LD_IND_W
+                | ebpf::LD_IND_DW => &["disable_deprecated_load_instructions"],
+                ebpf::SDIV32_IMM | ebpf::SDIV32_REG | ebpf::SDIV64_IMM | ebpf::SDIV64_REG => {
+                    &["enable_sdiv"]
+                }
+                _ => &[],
+            },
+            VerifierError::InvalidDestinationRegister(_) => &["dynamic_stack_frames"],
+            VerifierError::InvalidRegister(_) => &["reject_callx_r10"],
+            VerifierError::TooManyDistinctSyscalls(_, _) => {
+                &["max_distinct_syscalls", "static_syscalls"]
+            }
+            VerifierError::IllegalFramePointerArithmetic(_) => &["reject_frame_pointer_arithmetic"],
+            VerifierError::DisallowedOpCode(_, _) => &["allowed_opcodes"],
+            VerifierError::UnconditionalSelfLoop(_) => &["reject_unconditional_self_loops"],
+            VerifierError::TooManyCallees(_, _) => &["max_callees_per_function"],
+            _ => &[],
+        }
+    }
+}
+
+/// eBPF verifier which returns an error if the program does not meet its requirements.
+///
+/// Some examples of things the verifier may reject the program for:
+///
+///   - Program does not terminate.
+///   - Unknown instructions.
+///   - Bad formed instruction.
+///   - Unknown eBPF syscall index.
+///
+/// Implemented for `DefaultVerifier`, `VerifierPipeline` and plain functions such as `check()`.
+pub trait Verifier {
+    /// Checks the text section `prog`
+    fn verify(&self, prog: &[u8], config: &Config) -> Result<(), VerifierError>;
+}
+
+impl<F: Fn(&[u8], &Config) -> Result<(), VerifierError>> Verifier for F {
+    fn verify(&self, prog: &[u8], config: &Config) -> Result<(), VerifierError> {
+        self(prog, config)
+    }
+}
+
+/// The built-in rules, see `check()`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultVerifier;
+
+impl Verifier for DefaultVerifier {
+    fn verify(&self, prog: &[u8], config: &Config) -> Result<(), VerifierError> {
+        check(prog, config)
+    }
 }
 
 fn adj_insn_ptr(insn_ptr: usize) -> usize {
@@ -164,6 +250,38 @@ fn check_registers(
     }
 }
 
+/// Frame pointer may only be copied or have an offset added / subtracted
+fn check_frame_pointer_arithmetic(insn: &ebpf::Insn, insn_ptr: usize) -> Result<(), VerifierError> {
+    let class = insn.opc & ebpf::BPF_CLS_MASK;
+    if class != ebpf::BPF_ALU && class != ebpf::BPF_ALU64 {
+        return Ok(());
+    }
+    let reads_frame_pointer = insn.opc & ebpf::BPF_X != 0
+        && insn.opc != ebpf::BE
+        && insn.src as usize == ebpf::FRAME_PTR_REG
+        && !matches!(
+            insn.opc,
+            ebpf::ADD64_REG | ebpf::SUB64_REG | ebpf::MOV64_REG
+        );
+    // Moves into r10 and constant offsets are left to the CannotWriteR10 check
+    let writes_frame_pointer = insn.dst as usize == ebpf::FRAME_PTR_REG
+        && !matches!(
+            insn.opc,
+            ebpf::MOV32_IMM
+                | ebpf::MOV32_REG
+                | ebpf::MOV64_IMM
+                | ebpf::MOV64_REG
+                | ebpf::ADD64_IMM
+                | ebpf::SUB64_IMM
+        );
+    if writes_frame_pointer || reads_frame_pointer {
+        return Err(VerifierError::IllegalFramePointerArithmetic(adj_insn_ptr(
+            insn_ptr,
+        )));
+    }
+    Ok(())
+}
+
 /// Check that the imm is a valid shift operand
 fn check_imm_shift(insn: &ebpf::Insn, insn_ptr: usize, imm_bits: u64) -> Result<(), VerifierError> {
     let shift_by = insn.imm as u64;
@@ -189,163 +307,352 @@ fn check_imm_register(
     Ok(())
 }
 
-/// Check the program against the verifier's rules
+fn is_jump(opc: u8) -> bool {
+    opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP
+        && opc != ebpf::CALL_IMM
+        && opc != ebpf::CALL_REG
+        && opc != ebpf::EXIT
+}
+
+/// Checks which only depend on the instruction itself: opcode, immediate and registers
 #[rustfmt::skip]
+fn check_instruction(
+    insn: &ebpf::Insn,
+    insn_ptr: usize,
+    config: &Config,
+    syscalls: &mut HashSet<u32>,
+) -> Result<(), VerifierError> {
+    let mut store = false;
+
+    if !config.is_opcode_allowed(insn.opc) {
+        return Err(VerifierError::DisallowedOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
+    }
+
+    match insn.opc {
+        ebpf::LD_ABS_B
+        | ebpf::LD_ABS_H
+        | ebpf::LD_ABS_W
+        | ebpf::LD_ABS_DW
+        | ebpf::LD_IND_B
+        | ebpf::LD_IND_H
+        | ebpf::LD_IND_W
+        | ebpf::LD_IND_DW if config.disable_deprecated_load_instructions => {
+            return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
+        },
+
+        // BPF_LD class
+        ebpf::LD_ABS_B   => {},
+        ebpf::LD_ABS_H   => {},
+        ebpf::LD_ABS_W   => {},
+        ebpf::LD_ABS_DW  => {},
+        ebpf::LD_IND_B   => {},
+        ebpf::LD_IND_H   => {},
+        ebpf::LD_IND_W   => {},
+        ebpf::LD_IND_DW  => {},
+
+        ebpf::LD_DW_IMM  => {},
+
+        // BPF_LDX class
+        ebpf::LD_B_REG   => {},
+        ebpf::LD_H_REG   => {},
+        ebpf::LD_W_REG   => {},
+        ebpf::LD_DW_REG  => {},
+
+        // BPF_ST class
+        ebpf::ST_B_IMM   => store = true,
+        ebpf::ST_H_IMM   => store = true,
+        ebpf::ST_W_IMM   => store = true,
+        ebpf::ST_DW_IMM  => store = true,
+
+        // BPF_STX class
+        ebpf::ST_B_REG   => store = true,
+        ebpf::ST_H_REG   => store = true,
+        ebpf::ST_W_REG   => store = true,
+        ebpf::ST_DW_REG  => store = true,
+
+        // BPF_ALU class
+        ebpf::ADD32_IMM  => {},
+        ebpf::ADD32_REG  => {},
+        ebpf::SUB32_IMM  => {},
+        ebpf::SUB32_REG  => {},
+        ebpf::MUL32_IMM  => {},
+        ebpf::MUL32_REG  => {},
+        ebpf::DIV32_IMM  => { check_imm_nonzero(insn, insn_ptr)?; },
+        ebpf::DIV32_REG  => {},
+        ebpf::SDIV32_IMM if config.enable_sdiv => { check_imm_nonzero(insn, insn_ptr)?; },
+        ebpf::SDIV32_REG if config.enable_sdiv => {},
+        ebpf::OR32_IMM   => {},
+        ebpf::OR32_REG   => {},
+        ebpf::AND32_IMM  => {},
+        ebpf::AND32_REG  => {},
+        ebpf::LSH32_IMM  => { check_imm_shift(insn, insn_ptr, 32)?; },
+        ebpf::LSH32_REG  => {},
+        ebpf::RSH32_IMM  => { check_imm_shift(insn, insn_ptr, 32)?; },
+        ebpf::RSH32_REG  => {},
+        ebpf::NEG32      => {},
+        ebpf::MOD32_IMM  => { check_imm_nonzero(insn, insn_ptr)?; },
+        ebpf::MOD32_REG  => {},
+        ebpf::XOR32_IMM  => {},
+        ebpf::XOR32_REG  => {},
+        ebpf::MOV32_IMM  => {},
+        ebpf::MOV32_REG  => {},
+        ebpf::ARSH32_IMM => { check_imm_shift(insn, insn_ptr, 32)?; },
+        ebpf::ARSH32_REG => {},
+        ebpf::LE         => { check_imm_endian(insn, insn_ptr)?; },
+        ebpf::BE         => { check_imm_endian(insn, insn_ptr)?; },
+
+        // BPF_ALU64 class
+        ebpf::ADD64_IMM  => {},
+        ebpf::ADD64_REG  => {},
+        ebpf::SUB64_IMM  => {},
+        ebpf::SUB64_REG  => {},
+        ebpf::MUL64_IMM  => {},
+        ebpf::MUL64_REG  => {},
+        ebpf::DIV64_IMM  => { check_imm_nonzero(insn, insn_ptr)?; },
+        ebpf::DIV64_REG  => {},
+        ebpf::SDIV64_IMM if config.enable_sdiv => { check_imm_nonzero(insn, insn_ptr)?; },
+        ebpf::SDIV64_REG if config.enable_sdiv => {},
+        ebpf::OR64_IMM   => {},
+        ebpf::OR64_REG   => {},
+        ebpf::AND64_IMM  => {},
+        ebpf::AND64_REG  => {},
+        ebpf::LSH64_IMM  => { check_imm_shift(insn, insn_ptr, 64)?; },
+        ebpf::LSH64_REG  => {},
+        ebpf::RSH64_IMM  => { check_imm_shift(insn, insn_ptr, 64)?; },
+        ebpf::RSH64_REG  => {},
+        ebpf::NEG64      => {},
+        ebpf::MOD64_IMM  => { check_imm_nonzero(insn, insn_ptr)?; },
+        ebpf::MOD64_REG  => {},
+        ebpf::XOR64_IMM  => {},
+        ebpf::XOR64_REG  => {},
+        ebpf::MOV64_IMM  => {},
+        ebpf::MOV64_REG  => {},
+        ebpf::ARSH64_IMM => { check_imm_shift(insn, insn_ptr, 64)?; },
+        ebpf::ARSH64_REG => {},
+
+        // BPF_JMP class
+        ebpf::JA         => {
+            if config.reject_unconditional_self_loops && insn.off == -1 {
+                return Err(VerifierError::UnconditionalSelfLoop(adj_insn_ptr(insn_ptr)));
+            }
+        },
+        ebpf::JEQ_IMM    => {},
+        ebpf::JEQ_REG    => {},
+        ebpf::JGT_IMM    => {},
+        ebpf::JGT_REG    => {},
+        ebpf::JGE_IMM    => {},
+        ebpf::JGE_REG    => {},
+        ebpf::JLT_IMM    => {},
+        ebpf::JLT_REG    => {},
+        ebpf::JLE_IMM    => {},
+        ebpf::JLE_REG    => {},
+        ebpf::JSET_IMM   => {},
+        ebpf::JSET_REG   => {},
+        ebpf::JNE_IMM    => {},
+        ebpf::JNE_REG    => {},
+        ebpf::JSGT_IMM   => {},
+        ebpf::JSGT_REG   => {},
+        ebpf::JSGE_IMM   => {},
+        ebpf::JSGE_REG   => {},
+        ebpf::JSLT_IMM   => {},
+        ebpf::JSLT_REG   => {},
+        ebpf::JSLE_IMM   => {},
+        ebpf::JSLE_REG   => {},
+        ebpf::CALL_IMM   => {
+            if let Some(max_distinct_syscalls) = config.max_distinct_syscalls {
+                if !config.static_syscalls || insn.src == 0 {
+                    syscalls.insert(insn.imm as u32);
+                }
+                if syscalls.len() > max_distinct_syscalls {
+                    return Err(VerifierError::TooManyDistinctSyscalls(max_distinct_syscalls, adj_insn_ptr(insn_ptr)));
+                }
+            }
+        },
+        ebpf::CALL_REG   => { check_imm_register(insn, insn_ptr, config)?; },
+        ebpf::EXIT       => {},
+
+        _                => {
+            return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
+        }
+    }
+
+    if config.reject_frame_pointer_arithmetic {
+        check_frame_pointer_arithmetic(insn, insn_ptr)?;
+    }
+    check_registers(insn, store, insn_ptr, config.dynamic_stack_frames)
+}
+
+/// Check the program against the verifier's rules
 pub fn check(prog: &[u8], config: &Config) -> Result<(), VerifierError> {
     check_prog_len(prog)?;
 
+    let mut syscalls = HashSet::new();
     let mut insn_ptr: usize = 0;
     while (insn_ptr + 1) * ebpf::INSN_SIZE <= prog.len() {
         let insn = ebpf::get_insn(prog, insn_ptr);
-        let mut store = false;
-
-        match insn.opc {
-            ebpf::LD_ABS_B
-            | ebpf::LD_ABS_H
-            | ebpf::LD_ABS_W
-            | ebpf::LD_ABS_DW
-            | ebpf::LD_IND_B
-            | ebpf::LD_IND_H
-            | ebpf::LD_IND_W
-            | ebpf::LD_IND_DW if config.disable_deprecated_load_instructions => {
-                return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
-            },
+        if insn.opc == ebpf::LD_DW_IMM {
+            check_load_dw(prog, insn_ptr)?;
+            insn_ptr += 1;
+        } else if is_jump(insn.opc) {
+            check_jmp_offset(prog, insn_ptr)?;
+        }
+        check_instruction(&insn, insn_ptr, config, &mut syscalls)?;
+        insn_ptr += 1;
+    }
 
-            // BPF_LD class
-            ebpf::LD_ABS_B   => {},
-            ebpf::LD_ABS_H   => {},
-            ebpf::LD_ABS_W   => {},
-            ebpf::LD_ABS_DW  => {},
-            ebpf::LD_IND_B   => {},
-            ebpf::LD_IND_H   => {},
-            ebpf::LD_IND_W   => {},
-            ebpf::LD_IND_DW  => {},
-
-            ebpf::LD_DW_IMM  => {
-                check_load_dw(prog, insn_ptr)?;
-                insn_ptr += 1;
-            },
+    // insn_ptr should now be equal to number of instructions.
+    if insn_ptr != prog.len() / ebpf::INSN_SIZE {
+        return Err(VerifierError::JumpOutOfCode(
+            adj_insn_ptr(insn_ptr),
+            adj_insn_ptr(insn_ptr),
+        ));
+    }
 
-            // BPF_LDX class
-            ebpf::LD_B_REG   => {},
-            ebpf::LD_H_REG   => {},
-            ebpf::LD_W_REG   => {},
-            ebpf::LD_DW_REG  => {},
-
-            // BPF_ST class
-            ebpf::ST_B_IMM   => store = true,
-            ebpf::ST_H_IMM   => store = true,
-            ebpf::ST_W_IMM   => store = true,
-            ebpf::ST_DW_IMM  => store = true,
-
-            // BPF_STX class
-            ebpf::ST_B_REG   => store = true,
-            ebpf::ST_H_REG   => store = true,
-            ebpf::ST_W_REG   => store = true,
-            ebpf::ST_DW_REG  => store = true,
-
-            // BPF_ALU class
-            ebpf::ADD32_IMM  => {},
-            ebpf::ADD32_REG  => {},
-            ebpf::SUB32_IMM  => {},
-            ebpf::SUB32_REG  => {},
-            ebpf::MUL32_IMM  => {},
-            ebpf::MUL32_REG  => {},
-            ebpf::DIV32_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::DIV32_REG  => {},
-            ebpf::SDIV32_IMM if config.enable_sdiv => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::SDIV32_REG if config.enable_sdiv => {},
-            ebpf::OR32_IMM   => {},
-            ebpf::OR32_REG   => {},
-            ebpf::AND32_IMM  => {},
-            ebpf::AND32_REG  => {},
-            ebpf::LSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
-            ebpf::LSH32_REG  => {},
-            ebpf::RSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
-            ebpf::RSH32_REG  => {},
-            ebpf::NEG32      => {},
-            ebpf::MOD32_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::MOD32_REG  => {},
-            ebpf::XOR32_IMM  => {},
-            ebpf::XOR32_REG  => {},
-            ebpf::MOV32_IMM  => {},
-            ebpf::MOV32_REG  => {},
-            ebpf::ARSH32_IMM => { check_imm_shift(&insn, insn_ptr, 32)?; },
-            ebpf::ARSH32_REG => {},
-            ebpf::LE         => { check_imm_endian(&insn, insn_ptr)?; },
-            ebpf::BE         => { check_imm_endian(&insn, insn_ptr)?; },
-
-            // BPF_ALU64 class
-            ebpf::ADD64_IMM  => {},
-            ebpf::ADD64_REG  => {},
-            ebpf::SUB64_IMM  => {},
-            ebpf::SUB64_REG  => {},
-            ebpf::MUL64_IMM  => {},
-            ebpf::MUL64_REG  => {},
-            ebpf::DIV64_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::DIV64_REG  => {},
-            ebpf::SDIV64_IMM if config.enable_sdiv => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::SDIV64_REG if config.enable_sdiv => {},
-            ebpf::OR64_IMM   => {},
-            ebpf::OR64_REG   => {},
-            ebpf::AND64_IMM  => {},
-            ebpf::AND64_REG  => {},
-            ebpf::LSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
-            ebpf::LSH64_REG  => {},
-            ebpf::RSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
-            ebpf::RSH64_REG  => {},
-            ebpf::NEG64      => {},
-            ebpf::MOD64_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::MOD64_REG  => {},
-            ebpf::XOR64_IMM  => {},
-            ebpf::XOR64_REG  => {},
-            ebpf::MOV64_IMM  => {},
-            ebpf::MOV64_REG  => {},
-            ebpf::ARSH64_IMM => { check_imm_shift(&insn, insn_ptr, 64)?; },
-            ebpf::ARSH64_REG => {},
-
-            // BPF_JMP class
-            ebpf::JA         => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JEQ_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JEQ_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGT_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGT_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLT_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLT_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSET_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSET_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JNE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JNE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGT_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGT_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGE_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGE_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLT_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLT_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLE_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLE_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::CALL_IMM   => {},
-            ebpf::CALL_REG   => { check_imm_register(&insn, insn_ptr, config)?; },
-            ebpf::EXIT       => {},
-
-            _                => {
-                return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
+    Ok(())
+}
+
+/// Check that no function calls more than `Config::max_callees_per_function` distinct functions
+///
+/// Needs the function symbols of the executable, so the constructors of `Executable` run it after
+/// the verifier.
+pub fn check_call_graph<E: UserDefinedError, I: InstructionMeter>(
+    executable: &Executable<E, I>,
+) -> Result<(), VerifierError> {
+    let config = executable.get_config();
+    let max_callees = match config.max_callees_per_function {
+        Some(max_callees) => max_callees,
+        None => return Ok(()),
+    };
+    let (_program_vm_addr, prog) = executable.get_text_bytes();
+    let functions = executable.get_function_symbols();
+    let mut callees = BTreeSet::new();
+    let mut insn_ptr: usize = 0;
+    while (insn_ptr + 1) * ebpf::INSN_SIZE <= prog.len() {
+        if functions.contains_key(&insn_ptr) {
+            callees.clear();
+        }
+        let insn = ebpf::get_insn(prog, insn_ptr);
+        if insn.opc == ebpf::CALL_IMM && !(config.static_syscalls && insn.src == 0) {
+            if let Some(target_pc) = executable.lookup_bpf_function(insn.imm as u32) {
+                callees.insert(target_pc);
+                if callees.len() > max_callees {
+                    return Err(VerifierError::TooManyCallees(
+                        max_callees,
+                        adj_insn_ptr(insn_ptr),
+                    ));
+                }
             }
         }
+        insn_ptr += if insn.opc == ebpf::LD_DW_IMM { 2 } else { 1 };
+    }
+    Ok(())
+}
 
-        check_registers(&insn, store, insn_ptr, config.dynamic_stack_frames)?;
+/// Verifies instructions one at a time as they are appended to a program
+///
+/// Checks which only depend on a single instruction run in `push()`, so an invalid instruction
+/// is reported as soon as it is appended. Checks which need the whole program, like jump
+/// targets, run in `finalize()`.
+#[derive(Debug)]
+pub struct IncrementalVerifier {
+    config: Config,
+    prog: Vec<u8>,
+    syscalls: HashSet<u32>,
+    pending_lddw: bool,
+}
 
-        insn_ptr += 1;
+impl IncrementalVerifier {
+    /// Creates a verifier for an empty program
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            prog: Vec::new(),
+            syscalls: HashSet::new(),
+            pending_lddw: false,
+        }
     }
 
-    // insn_ptr should now be equal to number of instructions.
-    if insn_ptr != prog.len() / ebpf::INSN_SIZE {
-        return Err(VerifierError::JumpOutOfCode(adj_insn_ptr(insn_ptr), adj_insn_ptr(insn_ptr)));
+    /// Appends and checks an instruction
+    ///
+    /// After an error the program should be discarded.
+    pub fn push(&mut self, insn: &[u8; ebpf::INSN_SIZE]) -> Result<(), VerifierError> {
+        let insn_ptr = self.prog.len() / ebpf::INSN_SIZE;
+        self.prog.extend_from_slice(insn);
+        let insn = ebpf::get_insn(&self.prog, insn_ptr);
+        if self.pending_lddw {
+            self.pending_lddw = false;
+            if insn.opc != 0 {
+                return Err(VerifierError::IncompleteLDDW(adj_insn_ptr(insn_ptr - 1)));
+            }
+            // Same as check(), which reports the registers of an lddw at its second slot
+            let first_half = ebpf::get_insn(&self.prog, insn_ptr - 1);
+            return check_instruction(&first_half, insn_ptr, &self.config, &mut self.syscalls);
+        }
+        if insn.opc == ebpf::LD_DW_IMM {
+            self.pending_lddw = true;
+            return Ok(());
+        }
+        check_instruction(&insn, insn_ptr, &self.config, &mut self.syscalls)
     }
 
-    Ok(())
+    /// Runs the whole program checks and returns the program
+    pub fn finalize(self) -> Result<Vec<u8>, VerifierError> {
+        check_prog_len(&self.prog)?;
+        if self.pending_lddw {
+            return Err(VerifierError::LDDWCannotBeLast);
+        }
+        let mut insn_ptr: usize = 0;
+        while (insn_ptr + 1) * ebpf::INSN_SIZE <= self.prog.len() {
+            let insn = ebpf::get_insn(&self.prog, insn_ptr);
+            if insn.opc == ebpf::LD_DW_IMM {
+                insn_ptr += 1;
+            } else if is_jump(insn.opc) {
+                check_jmp_offset(&self.prog, insn_ptr)?;
+            }
+            insn_ptr += 1;
+        }
+        Ok(self.prog)
+    }
+}
+
+/// Runs several verifiers in sequence
+///
+/// `verify()` stops at the first error, `verify_all()` runs every verifier and collects all errors.
+#[derive(Default)]
+pub struct VerifierPipeline {
+    verifiers: Vec<Box<dyn Verifier>>,
+}
+
+impl VerifierPipeline {
+    /// Creates an empty pipeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a verifier to the end of the pipeline
+    pub fn push<V: Verifier + 'static>(&mut self, verifier: V) -> &mut Self {
+        self.verifiers.push(Box::new(verifier));
+        self
+    }
+
+    /// Runs the verifiers in order and returns the first error
+    pub fn verify(&self, prog: &[u8], config: &Config) -> Result<(), VerifierError> {
+        self.verifiers
+            .iter()
+            .try_for_each(|verifier| verifier.verify(prog, config))
+    }
+
+    /// Runs all verifiers in order and returns the errors they reported
+    pub fn verify_all(&self, prog: &[u8], config: &Config) -> Vec<VerifierError> {
+        self.verifiers
+            .iter()
+            .filter_map(|verifier| verifier.verify(prog, config).err())
+            .collect()
+    }
+}
+
+impl Verifier for VerifierPipeline {
+    fn verify(&self, prog: &[u8], config: &Config) -> Result<(), VerifierError> {
+        VerifierPipeline::verify(self, prog, config)
+    }
 }
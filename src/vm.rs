@@ -15,32 +15,32 @@ use crate::disassembler::disassemble_instruction;
 use crate::ebpf::STACK_PTR_REG;
 use crate::static_analysis::Analysis;
 use crate::{
+    assembler::assemble,
     call_frames::CallFrames,
     ebpf,
     elf::Executable,
     error::{EbpfError, UserDefinedError},
     jit::JitProgramArgument,
-    memory_region::{AccessType, MemoryMapping, MemoryRegion},
+    memory_region::{AccessType, MemoryMapping, MemoryRegion, MemorySnapshot},
     user_error::UserError,
-    verifier::VerifierError,
+    verifier::{check, check_call_graph},
 };
+use byteorder::{ByteOrder, LittleEndian};
+#[cfg(feature = "sha256")]
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{BTreeMap, HashMap},
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     fmt::Debug,
     mem,
+    ops::RangeInclusive,
     pin::Pin,
+    rc::Rc,
+    sync::Arc,
     u32,
 };
 
-/// eBPF verification function that returns an error if the program does not meet its requirements.
-///
-/// Some examples of things the verifier may reject the program for:
-///
-///   - Program does not terminate.
-///   - Unknown instructions.
-///   - Bad formed instruction.
-///   - Unknown eBPF syscall index.
-pub type Verifier = fn(prog: &[u8], config: &Config) -> Result<(), VerifierError>;
+pub use crate::verifier::Verifier;
 
 /// Return value of programs and syscalls
 pub type ProgramResult<E> = Result<u64, EbpfError<E>>;
@@ -92,9 +92,14 @@ pub struct Syscall {
     pub function: u64,
     /// Slot of context object
     pub context_object_slot: usize,
+    /// Values the syscall may return, anything else is reported as an error
+    pub return_range: Option<RangeInclusive<u64>>,
+    /// The syscall may not store to memory, see `SyscallRegistry::set_syscall_pure()`
+    pub is_pure: bool,
 }
 
 /// A virtual method table for dyn trait objects
+#[repr(C)]
 pub struct DynTraitVtable {
     /// Drops the dyn trait object
     pub drop: fn(*const u8),
@@ -109,6 +114,7 @@ pub struct DynTraitVtable {
 // Could be replaced by https://doc.rust-lang.org/std/raw/struct.TraitObject.html
 /// A dyn trait fat pointer for SyscallObject
 #[derive(Clone, Copy)]
+#[repr(C)]
 pub struct DynTraitFatPointer {
     /// Pointer to the actual object
     pub data: *mut u8,
@@ -116,16 +122,70 @@ pub struct DynTraitFatPointer {
     pub vtable: &'static DynTraitVtable,
 }
 
+/// Hashes the name of a syscall
+pub type SyscallNameHasher = Arc<dyn Fn(&str) -> u32 + Send + Sync>;
+
 /// Holds the syscall function pointers of an Executable
-#[derive(Debug, PartialEq, Default)]
+#[derive(Default)]
 pub struct SyscallRegistry {
     /// Function pointers by symbol
     entries: HashMap<u32, Syscall>,
     /// Context object slots by function pointer
     context_object_slots: HashMap<u64, usize>,
+    /// Hashes syscall names instead of ebpf::hash_symbol_name
+    name_hasher: Option<SyscallNameHasher>,
+    /// Syscall which is called with r0 when the program exits
+    exit_finalizer: Option<u32>,
+}
+
+impl Debug for SyscallRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyscallRegistry")
+            .field("entries", &self.entries)
+            .field("context_object_slots", &self.context_object_slots)
+            .field("name_hasher", &self.name_hasher.is_some())
+            .field("exit_finalizer", &self.exit_finalizer)
+            .finish()
+    }
+}
+
+impl PartialEq for SyscallRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        let name_hasher_ptr = |registry: &Self| {
+            registry
+                .name_hasher
+                .as_ref()
+                .map(|name_hasher| Arc::as_ptr(name_hasher) as *const u8)
+        };
+        self.entries == other.entries
+            && self.context_object_slots == other.context_object_slots
+            && name_hasher_ptr(self) == name_hasher_ptr(other)
+            && self.exit_finalizer == other.exit_finalizer
+    }
 }
 
 impl SyscallRegistry {
+    /// Creates an empty registry which hashes syscall names with `name_hasher`
+    ///
+    /// The assembler and the ELF loader use it too, so that syscall names resolve to the hashes
+    /// of the toolchain which produced the program.
+    pub fn with_name_hasher<F: Fn(&str) -> u32 + Send + Sync + 'static>(name_hasher: F) -> Self {
+        Self {
+            name_hasher: Some(Arc::new(name_hasher)),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the hash by which a syscall of the given name is called
+    ///
+    /// A custom name hasher is passed names which are not valid UTF-8 lossily converted.
+    pub fn hash_name(&self, name: &[u8]) -> u32 {
+        match &self.name_hasher {
+            Some(name_hasher) => name_hasher(&String::from_utf8_lossy(name)),
+            None => ebpf::hash_symbol_name(name),
+        }
+    }
+
     /// Register a syscall function by its symbol hash
     pub fn register_syscall_by_hash<'a, C, E: UserDefinedError, O: SyscallObject<E>>(
         &mut self,
@@ -144,6 +204,8 @@ impl SyscallRegistry {
                     init,
                     function,
                     context_object_slot,
+                    return_range: None,
+                    is_pure: false,
                 },
             )
             .is_some()
@@ -165,7 +227,69 @@ impl SyscallRegistry {
         init: SyscallInit<'a, C, E>,
         function: SyscallFunction<E, &mut O>,
     ) -> Result<(), EbpfError<E>> {
-        self.register_syscall_by_hash::<C, E, O>(ebpf::hash_symbol_name(name), init, function)
+        self.register_syscall_by_hash::<C, E, O>(self.hash_name(name), init, function)
+    }
+
+    /// Restrict the values a registered syscall may return
+    ///
+    /// The interpreter reports any other value as `EbpfError::SyscallReturnOutOfRange`.
+    pub fn set_syscall_return_range<E: UserDefinedError>(
+        &mut self,
+        hash: u32,
+        return_range: RangeInclusive<u64>,
+    ) -> Result<(), EbpfError<E>> {
+        let syscall = self
+            .entries
+            .get_mut(&hash)
+            .ok_or(EbpfError::SyscallNotRegistered(hash as usize))?;
+        syscall.return_range = Some(return_range);
+        Ok(())
+    }
+
+    /// Declare a registered syscall pure, it may read but not write the memory of the VM
+    ///
+    /// The interpreter aborts the run with `EbpfError::PureSyscallViolation` if the syscall maps
+    /// memory for a store.
+    pub fn set_syscall_pure<E: UserDefinedError>(&mut self, hash: u32) -> Result<(), EbpfError<E>> {
+        let syscall = self
+            .entries
+            .get_mut(&hash)
+            .ok_or(EbpfError::SyscallNotRegistered(hash as usize))?;
+        syscall.is_pure = true;
+        Ok(())
+    }
+
+    /// Declare a registered syscall the finalizer, which is called when the program exits
+    ///
+    /// The interpreter calls it with the final r0 as its only argument once the entrypoint
+    /// returns. An error of the finalizer becomes the result of the run, otherwise the result
+    /// stays r0.
+    pub fn set_exit_finalizer<E: UserDefinedError>(
+        &mut self,
+        hash: u32,
+    ) -> Result<(), EbpfError<E>> {
+        if !self.entries.contains_key(&hash) {
+            return Err(EbpfError::SyscallNotRegistered(hash as usize));
+        }
+        self.exit_finalizer = Some(hash);
+        Ok(())
+    }
+
+    /// Returns the hash of the syscall set by `set_exit_finalizer()`
+    pub fn get_exit_finalizer(&self) -> Option<u32> {
+        self.exit_finalizer
+    }
+
+    /// Returns true if any syscall is pure
+    pub fn has_pure_syscalls(&self) -> bool {
+        self.entries.values().any(|syscall| syscall.is_pure)
+    }
+
+    /// Returns true if any syscall has a return range
+    pub fn has_return_ranges(&self) -> bool {
+        self.entries
+            .values()
+            .any(|syscall| syscall.return_range.is_some())
     }
 
     /// Get a symbol's function pointer and context object slot
@@ -191,8 +315,55 @@ impl SyscallRegistry {
     }
 }
 
+/// Result of shifting by at least the operand width, see Config::shift_overflow_mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOverflowMode {
+    /// The shift amount is masked to the operand width, as done by the Linux kernel
+    Mask,
+    /// All bits are shifted out, so `lsh` and `rsh` yield 0 and `arsh` yields the sign
+    Saturate,
+}
+
+/// Groups of opcodes which share a cost in Config::set_instruction_class_costs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstructionClass {
+    /// Loads, including lddw and the legacy packet loads
+    Load,
+    /// Stores of registers and immediates
+    Store,
+    /// Arithmetic and logic other than division
+    Alu,
+    /// Division and modulo, signed and unsigned, 32 and 64 bit
+    Division,
+    /// Conditional and unconditional jumps
+    Jump,
+    /// Calls of syscalls and BPF functions
+    Call,
+    /// Exit from a BPF function or the program
+    Exit,
+}
+
+impl InstructionClass {
+    /// Returns the class of an opcode
+    pub fn of(opc: u8) -> Self {
+        match opc & ebpf::BPF_CLS_MASK {
+            ebpf::BPF_LD | ebpf::BPF_LDX => Self::Load,
+            ebpf::BPF_ST | ebpf::BPF_STX => Self::Store,
+            ebpf::BPF_JMP => match opc {
+                ebpf::CALL_IMM | ebpf::CALL_REG => Self::Call,
+                ebpf::EXIT => Self::Exit,
+                _ => Self::Jump,
+            },
+            _ => match opc & ebpf::BPF_ALU_OP_MASK {
+                ebpf::BPF_DIV | ebpf::BPF_MOD | ebpf::BPF_SDIV => Self::Division,
+                _ => Self::Alu,
+            },
+        }
+    }
+}
+
 /// VM configuration settings
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     /// Maximum call depth
     pub max_call_depth: usize,
@@ -235,6 +406,64 @@ pub struct Config {
     /// Allow sh_addr != sh_offset in elf sections. Used in SBFv2 to align
     /// section vaddrs to MM_PROGRAM_START.
     pub enable_elf_vaddr: bool,
+    /// Maximum number of distinct syscalls a program may call, checked by the verifier.
+    /// Without static syscalls every call immediate is counted.
+    pub max_distinct_syscalls: Option<usize>,
+    /// Maximum number of distinct functions a single function may call, checked after the
+    /// verifier when an executable is created (None = unlimited)
+    pub max_callees_per_function: Option<usize>,
+    /// Cost of each instruction by opcode for the instruction meter, opcodes which are not
+    /// listed cost 1 (None = 1 per instruction). The JIT charges the summed costs of the
+    /// instructions up to each pc, so the total cost of a program must fit into an i32.
+    pub instruction_costs: Option<HashMap<u8, u64>>,
+    /// Treat the input region (the context passed in r1) as readonly and report stores into it
+    pub reject_context_writes: bool,
+    /// Reject arithmetic on the frame pointer r10 other than add / sub / mov, checked by the verifier.
+    pub reject_frame_pointer_arithmetic: bool,
+    /// Seed for all randomized behavior (e.g. JIT diversification), None = seeded from entropy
+    ///
+    /// For testing and reproducing issues only, never set this in production: a known seed makes
+    /// the JIT code layout predictable and so defeats the diversification.
+    pub random_seed: Option<u64>,
+    /// Minimum number of instructions for which JIT compilation is recommended
+    pub jit_threshold_instructions: usize,
+    /// Record the highest offset stored to in each region. Not supported by the JIT.
+    pub track_store_high_water_marks: bool,
+    /// Bitmap of the opcodes the verifier accepts (None = all valid opcodes)
+    pub allowed_opcodes: Option<[u64; 4]>,
+    /// Report a nonzero r0 at exit as EbpfError::ProgramFailed instead of returning it
+    pub nonzero_exit_is_failure: bool,
+    /// Count the loads and stores hitting each region. Not supported by the JIT.
+    pub count_region_accesses: bool,
+    /// Reject unconditional jumps to themselves (`ja -1`), checked by the verifier
+    pub reject_unconditional_self_loops: bool,
+    /// Record the offsets of the input region (the context passed in r1) which are read.
+    /// Not supported by the JIT.
+    pub track_context_reads: bool,
+    /// Maximum sum of the lengths of all memory regions of a VM, including the readonly region
+    /// with the text section (None = unlimited)
+    pub max_footprint_bytes: Option<u64>,
+    /// Trap on loads of heap bytes which were not written by the program or a syscall during
+    /// the run. Not supported by the JIT.
+    pub trap_uninitialized_heap_reads: bool,
+    /// Maximum number of loads and stores of the program in a single run (None = unlimited).
+    /// Accesses by syscalls are not counted. Not supported by the JIT.
+    pub max_memory_accesses: Option<u64>,
+    /// Record the (from, to) pcs of every executed jump, for edge coverage. Conditional jumps
+    /// which fall through are recorded too. Not supported by the JIT.
+    pub track_branch_edges: bool,
+    /// Report an r0 at exit outside of these codes as EbpfError::InvalidExitCode (None = any code)
+    pub allowed_exit_codes: Option<HashSet<u64>>,
+    /// Record the guest memory which each syscall accesses through the memory mapping.
+    /// Not supported by the JIT.
+    pub trace_syscall_accesses: bool,
+    /// Hash the (pc, opcode) of every executed instruction and the result into a receipt, see
+    /// `EbpfVm::execution_receipt()`. Requires the `sha256` feature, otherwise `EbpfVm::new()`
+    /// fails with EbpfError::FeatureNotEnabled. Not supported by the JIT.
+    pub enable_execution_receipt: bool,
+    /// How shift instructions treat shift amounts of at least the operand width.
+    /// Only ShiftOverflowMode::Mask is supported by the JIT.
+    pub shift_overflow_mode: ShiftOverflowMode,
 }
 
 impl Config {
@@ -242,6 +471,66 @@ impl Config {
     pub fn stack_size(&self) -> usize {
         self.stack_frame_size * self.max_call_depth
     }
+
+    /// Sets the instruction costs by opcode, opcodes which are not listed cost 1
+    pub fn set_instruction_costs(&mut self, costs: &HashMap<u8, u64>) {
+        self.instruction_costs = Some(costs.clone());
+    }
+
+    /// Sets the instruction costs by class, classes which are not listed cost 1
+    pub fn set_instruction_class_costs(&mut self, costs: &HashMap<InstructionClass, u64>) {
+        self.instruction_costs = Some(
+            (0..=u8::MAX)
+                .filter_map(|opc| {
+                    costs
+                        .get(&InstructionClass::of(opc))
+                        .map(|cost| (opc, *cost))
+                })
+                .collect(),
+        );
+    }
+
+    /// Flattens the instruction costs into a table indexed by opcode for the instruction meter
+    pub(crate) fn instruction_cost_table(&self) -> Option<Box<[u64; 256]>> {
+        self.instruction_costs.as_ref().map(|costs| {
+            let mut instruction_costs = Box::new([1; 256]);
+            for (opc, cost) in costs.iter() {
+                instruction_costs[*opc as usize] = *cost;
+            }
+            instruction_costs
+        })
+    }
+
+    /// Restricts the verifier to the listed opcodes
+    pub fn set_allowed_opcodes(&mut self, opcodes: &[u8]) {
+        let mut allowed_opcodes = [0; 4];
+        for opc in opcodes.iter() {
+            allowed_opcodes[*opc as usize / 64] |= 1 << (*opc % 64);
+        }
+        self.allowed_opcodes = Some(allowed_opcodes);
+    }
+
+    /// Returns the registers at the entrypoint, which the interpreter and the JIT both start with
+    ///
+    /// r1 points to the input region, r10 to the top of the first stack frame and all others are 0.
+    pub fn initial_registers(&self) -> [u64; 11] {
+        let mut registers = [0; 11];
+        registers[1] = ebpf::MM_INPUT_START;
+        registers[ebpf::FRAME_PTR_REG] = if self.dynamic_stack_frames {
+            ebpf::MM_STACK_START + self.stack_size() as u64
+        } else {
+            ebpf::MM_STACK_START + self.stack_frame_size as u64
+        };
+        registers
+    }
+
+    /// Returns whether the verifier accepts the opcode, see Config::allowed_opcodes
+    pub fn is_opcode_allowed(&self, opc: u8) -> bool {
+        match self.allowed_opcodes {
+            Some(allowed_opcodes) => allowed_opcodes[opc as usize / 64] & (1 << (opc % 64)) != 0,
+            None => true,
+        }
+    }
 }
 
 impl Default for Config {
@@ -267,6 +556,27 @@ impl Default for Config {
             optimize_rodata: true,
             static_syscalls: true,
             enable_elf_vaddr: true,
+            max_distinct_syscalls: None,
+            max_callees_per_function: None,
+            instruction_costs: None,
+            reject_context_writes: false,
+            reject_frame_pointer_arithmetic: false,
+            random_seed: None,
+            jit_threshold_instructions: 0,
+            track_store_high_water_marks: false,
+            allowed_opcodes: None,
+            nonzero_exit_is_failure: false,
+            count_region_accesses: false,
+            reject_unconditional_self_loops: false,
+            track_context_reads: false,
+            max_footprint_bytes: None,
+            trap_uninitialized_heap_reads: false,
+            max_memory_accesses: None,
+            track_branch_edges: false,
+            allowed_exit_codes: None,
+            trace_syscall_accesses: false,
+            enable_execution_receipt: false,
+            shift_overflow_mode: ShiftOverflowMode::Mask,
         }
     }
 }
@@ -279,33 +589,227 @@ impl<E: UserDefinedError, I: 'static + InstructionMeter> Executable<E, I> {
     /// Creates a verified executable from an ELF file
     pub fn from_elf(
         elf_bytes: &[u8],
-        verifier: Option<Verifier>,
+        verifier: Option<&dyn Verifier>,
         config: Config,
         syscall_registry: SyscallRegistry,
     ) -> Result<Pin<Box<Self>>, EbpfError<E>> {
         let executable = Executable::load(config, elf_bytes, syscall_registry)?;
         if let Some(verifier) = verifier {
-            verifier(executable.get_text_bytes().1, executable.get_config())?;
+            verifier.verify(executable.get_text_bytes().1, executable.get_config())?;
+            check_call_graph(&executable)?;
+        }
+        Ok(Pin::new(Box::new(executable)))
+    }
+    /// Creates a verified executable from an ELF file, rewriting immediates before verification
+    ///
+    /// `rewriter` is called with the index of every instruction, the instruction and `parameters`
+    /// and returns the new immediate, if any. An `lddw` is passed with the immediate of both slots
+    /// merged and both halves are rewritten together. The immediates of calls refer to resolved
+    /// symbols and can not be rewritten. Loading fails if any rewrite is invalid.
+    pub fn from_elf_with_rewriter<F>(
+        elf_bytes: &[u8],
+        verifier: Option<&dyn Verifier>,
+        config: Config,
+        syscall_registry: SyscallRegistry,
+        parameters: &BTreeMap<String, i64>,
+        rewriter: F,
+    ) -> Result<Pin<Box<Self>>, EbpfError<E>>
+    where
+        F: FnMut(usize, &ebpf::Insn, &BTreeMap<String, i64>) -> Option<i64>,
+    {
+        let mut executable = Executable::load(config, elf_bytes, syscall_registry)?;
+        executable.rewrite_immediates(parameters, rewriter)?;
+        if let Some(verifier) = verifier {
+            verifier.verify(executable.get_text_bytes().1, executable.get_config())?;
+            check_call_graph(&executable)?;
         }
         Ok(Pin::new(Box::new(executable)))
     }
     /// Creates a verified executable from machine code
     pub fn from_text_bytes(
         text_bytes: &[u8],
-        verifier: Option<Verifier>,
+        verifier: Option<&dyn Verifier>,
         config: Config,
         syscall_registry: SyscallRegistry,
         bpf_functions: BTreeMap<u32, (usize, String)>,
     ) -> Result<Pin<Box<Self>>, EbpfError<E>> {
         if let Some(verifier) = verifier {
-            verifier(text_bytes, &config).map_err(EbpfError::VerifierError)?;
+            verifier
+                .verify(text_bytes, &config)
+                .map_err(EbpfError::VerifierError)?;
+        }
+        let executable =
+            Executable::new_from_text_bytes(config, text_bytes, syscall_registry, bpf_functions);
+        if verifier.is_some() {
+            check_call_graph(&executable)?;
+        }
+        Ok(Pin::new(Box::new(executable)))
+    }
+    /// Creates an executable from assembly source and verifies it with `verifier::check()`
+    ///
+    /// Assembler errors are reported as `EbpfError::AssemblerError` and verifier errors as
+    /// `EbpfError::VerifierError`.
+    pub fn from_text_asm(
+        src: &str,
+        config: Config,
+        syscall_registry: SyscallRegistry,
+    ) -> Result<Pin<Box<Self>>, EbpfError<E>> {
+        let executable = assemble::<E, I>(src, None, config, syscall_registry)
+            .map_err(EbpfError::AssemblerError)?;
+        check(executable.get_text_bytes().1, executable.get_config())?;
+        check_call_graph(&executable)?;
+        Ok(executable)
+    }
+}
+
+/// Registers and program counter of a paused debugging session, see `EbpfVm::debug_step()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugState {
+    /// Instruction which is executed next
+    pub pc: usize,
+    /// Register file
+    pub registers: [u64; 11],
+}
+
+/// Outcome of `EbpfVm::debug_step()` and `EbpfVm::debug_continue()`
+#[derive(Debug, PartialEq)]
+pub enum DebugEvent<E: UserDefinedError> {
+    /// Paused after executing a single instruction
+    Step(DebugState),
+    /// Paused before executing the instruction at a breakpoint
+    Breakpoint(DebugState),
+    /// The program exited or failed, which ends the debugging session
+    Exit(ProgramResult<E>),
+}
+
+/// Loop state of the interpreter, kept between the calls of a debugging session
+struct InterpreterState {
+    reg: [u64; 11],
+    /// None until the registers and the stack are initialized for the entrypoint
+    next_pc: Option<usize>,
+    initial_insn_count: u64,
+    remaining_insn_count: u64,
+    last_insn_count: u64,
+    /// Resuming does not stop at the breakpoint the session is paused at
+    resuming: bool,
+    at_breakpoint: bool,
+}
+
+/// Difference between the interpreter and the JIT, see `Executable::assert_deterministic()`
+#[derive(Debug, PartialEq, Eq)]
+pub enum Divergence<E: UserDefinedError> {
+    /// The VM could not be created or the JIT failed to compile the program
+    Setup(EbpfError<E>),
+    /// The results of the interpreter and the JIT differ
+    Result(ProgramResult<E>, ProgramResult<E>),
+    /// The instruction counts of the interpreter and the JIT differ
+    InstructionCount(u64, u64),
+    /// The input memory differs after execution, starting at the given offset
+    Memory(usize),
+    /// The heap differs after execution, starting at the given offset
+    Heap(usize),
+    /// The traces differ, starting at the given entry
+    Trace(usize),
+}
+
+/// Outcome of one run of `Executable::assert_deterministic()`
+type DeterminismRun<E> = (ProgramResult<E>, u64, Vec<u8>, Vec<u8>, Tracer);
+
+impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
+    /// Runs the program in the interpreter and the JIT and checks that both behave the same
+    ///
+    /// Each run gets its own copy of `mem` as input region, a zeroed heap of `heap_size` bytes
+    /// and an instruction meter from `create_instruction_meter`. The results (including errors),
+    /// the instruction counts, the input memory and the heap afterwards are compared, and if
+    /// `enable_instruction_tracing` is set so are the traces, which contain all registers of
+    /// every executed instruction. Nothing is run if the JIT is not supported on this host.
+    pub fn assert_deterministic<C: Clone, M: FnMut() -> I>(
+        executable: &mut Pin<Box<Self>>,
+        mem: &[u8],
+        heap_size: usize,
+        syscall_context: C,
+        mut create_instruction_meter: M,
+    ) -> Result<(), Divergence<E>>
+    where
+        E: PartialEq,
+    {
+        if !Self::is_jit_supported() {
+            return Ok(());
         }
-        Ok(Pin::new(Box::new(Executable::new_from_text_bytes(
-            config,
-            text_bytes,
-            syscall_registry,
-            bpf_functions,
-        ))))
+        let interpreter = Self::run_for_determinism(
+            executable,
+            mem,
+            heap_size,
+            syscall_context.clone(),
+            &mut create_instruction_meter(),
+            false,
+        )?;
+        Executable::jit_compile(executable).map_err(Divergence::Setup)?;
+        let jit = Self::run_for_determinism(
+            executable,
+            mem,
+            heap_size,
+            syscall_context,
+            &mut create_instruction_meter(),
+            true,
+        )?;
+        if interpreter.0 != jit.0 {
+            return Err(Divergence::Result(interpreter.0, jit.0));
+        }
+        if executable.get_config().enable_instruction_meter && interpreter.1 != jit.1 {
+            return Err(Divergence::InstructionCount(interpreter.1, jit.1));
+        }
+        if let Some(offset) = interpreter
+            .2
+            .iter()
+            .zip(jit.2.iter())
+            .position(|(interpreter, jit)| interpreter != jit)
+        {
+            return Err(Divergence::Memory(offset));
+        }
+        if let Some(offset) = interpreter
+            .3
+            .iter()
+            .zip(jit.3.iter())
+            .position(|(interpreter, jit)| interpreter != jit)
+        {
+            return Err(Divergence::Heap(offset));
+        }
+        // The log of the JIT can be longer, see `Tracer::compare()`
+        if let Some(entry) = interpreter
+            .4
+            .first_divergence(&jit.4)
+            .filter(|entry| *entry < interpreter.4.log.len())
+        {
+            return Err(Divergence::Trace(entry));
+        }
+        Ok(())
+    }
+
+    fn run_for_determinism<C: Clone>(
+        executable: &Pin<Box<Self>>,
+        mem: &[u8],
+        heap_size: usize,
+        syscall_context: C,
+        instruction_meter: &mut I,
+        jit: bool,
+    ) -> Result<DeterminismRun<E>, Divergence<E>> {
+        let mut mem = mem.to_vec();
+        let mut heap = vec![0u8; heap_size];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm =
+            EbpfVm::new(executable, &mut heap, vec![mem_region]).map_err(Divergence::Setup)?;
+        vm.bind_syscall_context_objects(syscall_context)
+            .map_err(Divergence::Setup)?;
+        let result = if jit {
+            vm.execute_program_jit(instruction_meter)
+        } else {
+            vm.execute_program_interpreted(instruction_meter)
+        };
+        let instruction_count = vm.get_total_instruction_count();
+        let trace = vm.get_tracer().clone();
+        drop(vm);
+        Ok((result, instruction_count, mem, heap, trace))
     }
 }
 
@@ -317,6 +821,35 @@ pub trait InstructionMeter {
     fn get_remaining(&self) -> u64;
 }
 
+/// Allows an instruction meter to be shared with syscalls
+impl<I: InstructionMeter + ?Sized> InstructionMeter for Rc<RefCell<I>> {
+    fn consume(&mut self, amount: u64) {
+        self.borrow_mut().consume(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.borrow().get_remaining()
+    }
+}
+
+/// Holds back `reserve` instructions of another instruction meter
+struct BudgetInstructionMeter<'a, I: InstructionMeter> {
+    instruction_meter: &'a mut I,
+    reserve: u64,
+}
+
+impl<'a, I: InstructionMeter> InstructionMeter for BudgetInstructionMeter<'a, I> {
+    fn consume(&mut self, amount: u64) {
+        self.instruction_meter.consume(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.instruction_meter
+            .get_remaining()
+            .saturating_sub(self.reserve)
+    }
+}
+
 /// Simple instruction meter for testing
 #[derive(Debug, PartialEq, Eq)]
 pub struct TestInstructionMeter {
@@ -372,21 +905,117 @@ impl DynamicAnalysis {
     }
 }
 
+/// Receives every instruction before the interpreter executes it
+pub trait InstructionObserver {
+    /// Called with the pc, the opcode and the registers before the instruction is executed
+    fn before(&mut self, pc: usize, opcode: u8, registers: &[u64; 11]);
+}
+
+/// Allows an instruction observer to be inspected after it was installed in a VM
+impl<O: InstructionObserver + ?Sized> InstructionObserver for Rc<RefCell<O>> {
+    fn before(&mut self, pc: usize, opcode: u8, registers: &[u64; 11]) {
+        self.borrow_mut().before(pc, opcode, registers);
+    }
+}
+
+/// Records how the registers change between passing `start_pc` and then passing `end_pc`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterDiff {
+    /// Instruction at which the registers are captured
+    pub start_pc: usize,
+    /// Instruction at which the difference to the captured registers is recorded
+    pub end_pc: usize,
+    /// Difference of every register, one entry for each pass from `start_pc` to `end_pc`
+    pub deltas: Vec<[i64; 11]>,
+    start_registers: Option<[u64; 11]>,
+}
+
+impl RegisterDiff {
+    /// Creates a diff between the registers before `start_pc` and before `end_pc`
+    pub fn new(start_pc: usize, end_pc: usize) -> Self {
+        Self {
+            start_pc,
+            end_pc,
+            ..Self::default()
+        }
+    }
+}
+
+impl InstructionObserver for RegisterDiff {
+    fn before(&mut self, pc: usize, _opcode: u8, registers: &[u64; 11]) {
+        if pc == self.end_pc {
+            if let Some(start_registers) = self.start_registers.take() {
+                let mut delta = [0i64; 11];
+                for (delta, (end, start)) in delta
+                    .iter_mut()
+                    .zip(registers.iter().zip(start_registers.iter()))
+                {
+                    *delta = end.wrapping_sub(*start) as i64;
+                }
+                self.deltas.push(delta);
+            }
+        }
+        if pc == self.start_pc {
+            self.start_registers = Some(*registers);
+        }
+    }
+}
+
+/// A syscall invocation recorded by the interpreter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyscallRecord {
+    /// Hash of the syscall
+    pub hash: u32,
+    /// Arguments passed in r1 to r5
+    pub args: [u64; 5],
+    /// Value returned in r0
+    pub result: u64,
+    /// Bytes stored by the syscall as (vm_addr, bytes after the call), in order of the stores
+    pub writes: Vec<(u64, Vec<u8>)>,
+}
+
+/// The guest memory a syscall invocation accessed, see Config::trace_syscall_accesses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallAccesses {
+    /// Instruction which called the syscall
+    pub pc: usize,
+    /// Hash of the syscall
+    pub hash: u32,
+    /// (access type, vm_addr, len) of every successful access, in order
+    pub accesses: Vec<(AccessType, u64, u64)>,
+}
+
+/// Log of the syscall invocations of the interpreter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyscallLog {
+    /// Calls the syscalls and appends a record for every successful invocation
+    Record(Vec<SyscallRecord>),
+    /// Serves the syscalls from the remaining records in order, without calling them
+    Replay(VecDeque<SyscallRecord>),
+}
+
 /// Used for instruction tracing
 #[derive(Default, Clone)]
 pub struct Tracer {
     /// Contains the state at every instruction in order of execution
     pub log: Vec<[u64; 12]>,
+    /// Only record instructions whose pc lies within `[start_pc, end_pc]`
+    pub pc_window: Option<(usize, usize)>,
 }
 
 impl Tracer {
     /// Logs the state of a single instruction
     pub fn trace(&mut self, state: [u64; 12]) {
+        if let Some((start_pc, end_pc)) = self.pc_window {
+            if !(start_pc as u64..=end_pc as u64).contains(&state[11]) {
+                return;
+            }
+        }
         self.log.push(state);
     }
 
     /// Use this method to print the log of this tracer
-    pub fn write<W: std::io::Write, E: UserDefinedError, I: InstructionMeter>(
+    pub fn write<W: std::io::Write + ?Sized, E: UserDefinedError, I: InstructionMeter>(
         &self,
         output: &mut W,
         analysis: &Analysis<E, I>,
@@ -419,6 +1048,41 @@ impl Tracer {
         Ok(())
     }
 
+    /// Serializes the log as fixed-size records of 12 little-endian u64s (11 registers, then the pc)
+    pub fn write_binary<W: std::io::Write>(&self, output: &mut W) -> Result<(), std::io::Error> {
+        let mut record = [0u8; 12 * 8];
+        for entry in self.log.iter() {
+            LittleEndian::write_u64_into(entry, &mut record);
+            output.write_all(&record)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a log written by `write_binary`
+    pub fn read_binary<R: std::io::Read>(input: &mut R) -> Result<Self, std::io::Error> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        let record_size = std::mem::size_of::<[u64; 12]>();
+        if bytes.len() % record_size != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "truncated trace record",
+            ));
+        }
+        let log = bytes
+            .chunks_exact(record_size)
+            .map(|record| {
+                let mut entry = [0u64; 12];
+                LittleEndian::read_u64_into(record, &mut entry);
+                entry
+            })
+            .collect();
+        Ok(Self {
+            log,
+            pc_window: None,
+        })
+    }
+
     /// Compares an interpreter trace and a JIT trace.
     ///
     /// The log of the JIT can be longer because it only validates the instruction meter at branches.
@@ -430,6 +1094,33 @@ impl Tracer {
         }
         interpreter == jit
     }
+
+    /// Returns the index of the first entry in which two logs differ
+    ///
+    /// If one log is a prefix of the other, that is the length of the shorter one. None if both
+    /// are identical.
+    pub fn first_divergence(&self, other: &Self) -> Option<usize> {
+        self.log
+            .iter()
+            .zip(other.log.iter())
+            .position(|(a, b)| a != b)
+            .or_else(|| {
+                if self.log.len() == other.log.len() {
+                    None
+                } else {
+                    Some(self.log.len().min(other.log.len()))
+                }
+            })
+    }
+}
+
+impl InstructionObserver for Tracer {
+    fn before(&mut self, pc: usize, _opcode: u8, registers: &[u64; 11]) {
+        let mut state = [0u64; 12];
+        state[0..11].copy_from_slice(registers);
+        state[11] = pc as u64;
+        self.trace(state);
+    }
 }
 
 /// Translates a vm_addr into a host_addr and sets the pc in the error if one occurs
@@ -440,7 +1131,59 @@ macro_rules! translate_memory_access {
             $vm_addr,
             std::mem::size_of::<$T>() as u64,
         ) {
-            Ok(host_addr) => host_addr as *mut $T,
+            Ok(host_addr) => {
+                if let Some(max_memory_accesses) = $self.executable.get_config().max_memory_accesses
+                {
+                    if $self.memory_access_count >= max_memory_accesses {
+                        return Err(EbpfError::MemoryAccessQuotaExceeded(
+                            $pc + ebpf::ELF_INSN_DUMP_OFFSET,
+                            max_memory_accesses,
+                        ));
+                    }
+                    $self.memory_access_count += 1;
+                }
+                if $access_type == AccessType::Store
+                    && $self.executable.get_config().track_store_high_water_marks
+                {
+                    $self.record_store($vm_addr, std::mem::size_of::<$T>() as u64);
+                }
+                if $self.executable.get_config().count_region_accesses {
+                    $self.count_region_access($vm_addr, $access_type);
+                }
+                if $access_type == AccessType::Load
+                    && $self.executable.get_config().track_context_reads
+                    && $vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS
+                        == ebpf::MM_INPUT_START >> ebpf::VIRTUAL_ADDRESS_BITS
+                {
+                    let offset = $vm_addr - ebpf::MM_INPUT_START;
+                    $self
+                        .context_read_offsets
+                        .extend(offset..offset + std::mem::size_of::<$T>() as u64);
+                }
+                if $self.executable.get_config().trap_uninitialized_heap_reads
+                    && $vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS
+                        == ebpf::MM_HEAP_START >> ebpf::VIRTUAL_ADDRESS_BITS
+                {
+                    if let Err(vm_addr) = $self.check_heap_access(
+                        $vm_addr,
+                        std::mem::size_of::<$T>() as u64,
+                        $access_type,
+                    ) {
+                        return Err(EbpfError::UninitializedRead(
+                            $pc + ebpf::ELF_INSN_DUMP_OFFSET,
+                            vm_addr,
+                        ));
+                    }
+                }
+                if !$self.region_access_logs.is_empty() {
+                    $self.log_region_access(
+                        $vm_addr,
+                        std::mem::size_of::<$T>() as u64,
+                        $access_type,
+                    );
+                }
+                host_addr as *mut $T
+            }
             Err(EbpfError::AccessViolation(_pc, access_type, vm_addr, len, regions)) => {
                 return Err(EbpfError::AccessViolation(
                     $pc + ebpf::ELF_INSN_DUMP_OFFSET,
@@ -459,6 +1202,20 @@ macro_rules! translate_memory_access {
                     stack_frame,
                 ));
             }
+            Err(EbpfError::RegionStraddleViolation(_pc, access_type, vm_addr, len, regions)) => {
+                return Err(EbpfError::RegionStraddleViolation(
+                    $pc + ebpf::ELF_INSN_DUMP_OFFSET,
+                    access_type,
+                    vm_addr,
+                    len,
+                    regions,
+                ));
+            }
+            Err(EbpfError::ContextWriteViolation(_pc)) => {
+                return Err(EbpfError::ContextWriteViolation(
+                    $pc + ebpf::ELF_INSN_DUMP_OFFSET,
+                ));
+            }
             _ => unreachable!(),
         }
     };
@@ -501,6 +1258,28 @@ pub struct EbpfVm<'a, E: UserDefinedError, I: InstructionMeter> {
     syscall_context_object_pool: Vec<Box<dyn SyscallObject<E> + 'a>>,
     stack: CallFrames<'a>,
     total_insn_count: u64,
+    instruction_meter_overshoot: u64,
+    output_region_index: Option<usize>,
+    last_pc: usize,
+    instruction_observer: Option<Box<dyn InstructionObserver + 'a>>,
+    store_high_water_marks: Vec<Option<u64>>,
+    region_access_counts: Vec<(u64, u64)>,
+    context_read_offsets: BTreeSet<u64>,
+    syscall_log: Option<SyscallLog>,
+    region_access_logs: BTreeMap<usize, Vec<(u64, u64, u64, AccessType)>>,
+    insn_index: u64,
+    heap_written: Vec<u64>,
+    memory_access_count: u64,
+    run_start_remaining: Rc<Cell<u64>>,
+    branch_edges: BTreeSet<(usize, usize)>,
+    current_pc: Option<Rc<Cell<u64>>>,
+    breakpoints: BTreeSet<usize>,
+    debug_session: Option<InterpreterState>,
+    syscall_accesses: Vec<SyscallAccesses>,
+    #[cfg(feature = "sha256")]
+    receipt_hasher: Option<Sha256>,
+    #[cfg(feature = "sha256")]
+    execution_receipt: [u8; 32],
 }
 
 impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
@@ -540,6 +1319,18 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
         .into_iter()
         .chain(additional_regions.into_iter())
         .collect();
+        if let Some(max_footprint_bytes) = config.max_footprint_bytes {
+            let footprint = regions.iter().map(|region| region.len).sum::<u64>();
+            if footprint > max_footprint_bytes {
+                return Err(EbpfError::FootprintExceeded(footprint, max_footprint_bytes));
+            }
+        }
+        if cfg!(not(feature = "sha256")) && config.enable_execution_receipt {
+            return Err(EbpfError::FeatureNotEnabled(
+                "Config::enable_execution_receipt",
+                "sha256",
+            ));
+        }
         let (program_vm_addr, program) = executable.get_text_bytes();
         let number_of_syscalls = executable.get_syscall_registry().get_number_of_syscalls();
         let mut vm = EbpfVm {
@@ -555,6 +1346,28 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             syscall_context_object_pool: Vec::with_capacity(number_of_syscalls),
             stack,
             total_insn_count: 0,
+            instruction_meter_overshoot: 0,
+            output_region_index: None,
+            last_pc: 0,
+            instruction_observer: None,
+            store_high_water_marks: Vec::new(),
+            region_access_counts: Vec::new(),
+            context_read_offsets: BTreeSet::new(),
+            syscall_log: None,
+            region_access_logs: BTreeMap::new(),
+            insn_index: 0,
+            heap_written: Vec::new(),
+            memory_access_count: 0,
+            run_start_remaining: Rc::new(Cell::new(0)),
+            branch_edges: BTreeSet::new(),
+            current_pc: None,
+            breakpoints: BTreeSet::new(),
+            debug_session: None,
+            syscall_accesses: Vec::new(),
+            #[cfg(feature = "sha256")]
+            receipt_hasher: None,
+            #[cfg(feature = "sha256")]
+            execution_receipt: [0; 32],
         };
         unsafe {
             libc::memcpy(
@@ -571,16 +1384,320 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
         self.total_insn_count
     }
 
+    /// Returns by how much the last program exceeded the remaining instructions
+    ///
+    /// Only weighted instruction costs (see Config::instruction_costs) can overshoot. The
+    /// instruction meter is charged no more than its remaining instructions, the rest is
+    /// reported here instead.
+    pub fn get_instruction_meter_overshoot(&self) -> u64 {
+        self.instruction_meter_overshoot
+    }
+
+    /// Returns the guest memory accessed by the syscalls of the last run
+    ///
+    /// Only recorded by the interpreter if Config::trace_syscall_accesses is set.
+    pub fn get_syscall_accesses(&self) -> &[SyscallAccesses] {
+        &self.syscall_accesses
+    }
+
+    /// Copies the contents of all writable memory, including the private copies of copy-on-write
+    /// regions, so that it can be rolled back with `restore_memory()`
+    pub fn snapshot_memory(&self) -> MemorySnapshot {
+        self.memory_mapping.snapshot()
+    }
+
+    /// Rolls all writable memory back to a snapshot taken from this VM
+    ///
+    /// Registers are not part of the snapshot, see `debug_step()` for those.
+    pub fn restore_memory(&mut self, snapshot: &MemorySnapshot) -> Result<(), EbpfError<E>> {
+        self.memory_mapping.restore(snapshot)
+    }
+
+    /// Returns the SHA-256 over the (pc, opcode) of every instruction executed by the last run
+    /// followed by its result
+    ///
+    /// Only computed by the interpreter if Config::enable_execution_receipt is set, otherwise all
+    /// zeros. Runs which take the same path and end with the same result have the same receipt.
+    #[cfg(feature = "sha256")]
+    pub fn execution_receipt(&self) -> [u8; 32] {
+        self.execution_receipt
+    }
+
+    /// Returns the (from, to) pcs of the jumps executed by the last run
+    ///
+    /// Only recorded by the interpreter if Config::track_branch_edges is set.
+    pub fn get_branch_edges(&self) -> &BTreeSet<(usize, usize)> {
+        &self.branch_edges
+    }
+
+    /// Returns a handle to the remaining instructions of the instruction meter at the start of the
+    /// current run, which is updated by every `execute_program_*` call.
+    ///
+    /// Pass it to syscalls like `BpfGetInstructionCount` to count relative to the current run.
+    pub fn get_run_start_handle(&self) -> Rc<Cell<u64>> {
+        self.run_start_remaining.clone()
+    }
+
+    /// Returns a handle which the interpreter sets to the pc of every syscall instruction
+    /// before calling the syscall, see `BpfCurrentPc`. Not supported by the JIT.
+    pub fn get_current_pc_handle(&mut self) -> Rc<Cell<u64>> {
+        self.current_pc
+            .get_or_insert_with(|| Rc::new(Cell::new(0)))
+            .clone()
+    }
+
     /// Returns the program
     pub fn get_program(&self) -> &[u8] {
         self.program
     }
 
     /// Returns the tracer
+    ///
+    /// The trace is kept when the program faults and ends with the faulting instruction.
     pub fn get_tracer(&self) -> &Tracer {
         &self.tracer
     }
 
+    /// Returns the tracer for configuration, e.g. of its pc window
+    pub fn get_tracer_mut(&mut self) -> &mut Tracer {
+        &mut self.tracer
+    }
+
+    /// Returns the pc of each active call frame and the function it is in, innermost first
+    ///
+    /// This is meant to be read after the interpreter faulted, the first entry is the faulting
+    /// instruction and the others are the call sites of the caller frames.
+    pub fn backtrace(&self) -> Vec<(usize, Option<&str>)> {
+        std::iter::once(self.last_pc)
+            .chain(
+                self.stack
+                    .get_return_pointers()
+                    .into_iter()
+                    .rev()
+                    .map(|return_ptr| return_ptr - 1),
+            )
+            .map(|pc| (pc, self.executable.lookup_function_name(pc)))
+            .collect()
+    }
+
+    /// Installs an observer which the interpreter calls before every instruction
+    ///
+    /// Not supported by the JIT, `execute_program_jit()` fails while an observer is installed.
+    pub fn set_instruction_observer(
+        &mut self,
+        instruction_observer: Option<Box<dyn InstructionObserver + 'a>>,
+    ) {
+        self.instruction_observer = instruction_observer;
+    }
+
+    /// Returns the highest offset stored to by the last run in each region, indexed like the regions
+    ///
+    /// Only recorded by the interpreter if Config::track_store_high_water_marks is set.
+    pub fn get_store_high_water_marks(&self) -> &[Option<u64>] {
+        &self.store_high_water_marks
+    }
+
+    fn record_store(&mut self, vm_addr: u64, len: u64) {
+        let index = (vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS) as usize;
+        let offset = vm_addr - self.memory_mapping.get_regions()[index].vm_addr + len - 1;
+        let high_water_mark = &mut self.store_high_water_marks[index];
+        *high_water_mark = Some(high_water_mark.map_or(offset, |mark| mark.max(offset)));
+    }
+
+    /// Returns the number of loads and stores of the last run in each region, indexed like the regions
+    ///
+    /// Only counted by the interpreter if Config::count_region_accesses is set.
+    pub fn get_region_access_counts(&self) -> &[(u64, u64)] {
+        &self.region_access_counts
+    }
+
+    fn count_region_access(&mut self, vm_addr: u64, access_type: AccessType) {
+        let counts =
+            &mut self.region_access_counts[(vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS) as usize];
+        match access_type {
+            AccessType::Store => counts.1 += 1,
+            _ => counts.0 += 1,
+        }
+    }
+
+    /// Records or replays the syscall invocations of the interpreter
+    ///
+    /// The JIT does not support syscall logs.
+    pub fn set_syscall_log(&mut self, syscall_log: Option<SyscallLog>) {
+        self.syscall_log = syscall_log;
+    }
+
+    /// Removes the syscall log, e.g. to get the records after a run
+    pub fn take_syscall_log(&mut self) -> Option<SyscallLog> {
+        self.syscall_log.take()
+    }
+
+    fn heap_bytes(&self) -> &[u8] {
+        let region = &self.memory_mapping.get_regions()
+            [(ebpf::MM_HEAP_START >> ebpf::VIRTUAL_ADDRESS_BITS) as usize];
+        unsafe { std::slice::from_raw_parts(region.host_addr as *const u8, region.len as usize) }
+    }
+
+    /// Marks the heap bytes of a store as written, or returns the first unwritten byte of a load
+    fn check_heap_access(
+        &mut self,
+        vm_addr: u64,
+        len: u64,
+        access_type: AccessType,
+    ) -> Result<(), u64> {
+        let start = (vm_addr - ebpf::MM_HEAP_START) as usize;
+        for offset in start..start + len as usize {
+            let (word, bit) = (offset / 64, 1u64 << (offset % 64));
+            if access_type == AccessType::Store {
+                self.heap_written[word] |= bit;
+            } else if self.heap_written[word] & bit == 0 {
+                return Err(ebpf::MM_HEAP_START + offset as u64);
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the heap bytes a syscall stored to as written
+    fn mark_heap_stores(&mut self, accesses: &[(AccessType, u64, u64)]) {
+        let heap_len = self.heap_bytes().len() as u64;
+        for (access_type, vm_addr, len) in accesses {
+            if *access_type != AccessType::Store
+                || *vm_addr < ebpf::MM_HEAP_START
+                || *vm_addr >= ebpf::MM_HEAP_START + heap_len
+            {
+                continue;
+            }
+            let _ = self.check_heap_access(*vm_addr, *len, AccessType::Store);
+        }
+    }
+
+    fn record_syscall(
+        &mut self,
+        hash: u32,
+        args: [u64; 5],
+        result: u64,
+        accesses: &[(AccessType, u64, u64)],
+    ) {
+        // Read back through the mapping, which knows about stack gaps and copy-on-write copies
+        let writes = accesses
+            .iter()
+            .filter(|(access_type, _vm_addr, _len)| *access_type == AccessType::Store)
+            .filter_map(|(_access_type, vm_addr, len)| {
+                let host_addr = self
+                    .memory_mapping
+                    .map::<E>(AccessType::Load, *vm_addr, *len)
+                    .ok()?;
+                let bytes =
+                    unsafe { std::slice::from_raw_parts(host_addr as *const u8, *len as usize) };
+                Some((*vm_addr, bytes.to_vec()))
+            })
+            .collect();
+        if let Some(SyscallLog::Record(records)) = &mut self.syscall_log {
+            records.push(SyscallRecord {
+                hash,
+                args,
+                result,
+                writes,
+            });
+        }
+    }
+
+    fn replay_syscall(&mut self, pc: usize, hash: u32, args: [u64; 5]) -> ProgramResult<E> {
+        let record = match &mut self.syscall_log {
+            Some(SyscallLog::Replay(records)) => records.pop_front(),
+            _ => None,
+        }
+        .filter(|record| record.hash == hash && record.args == args)
+        .ok_or(EbpfError::SyscallReplayMismatch(
+            pc + ebpf::ELF_INSN_DUMP_OFFSET,
+        ))?;
+        for (vm_addr, bytes) in record.writes.iter() {
+            let host_addr = self
+                .memory_mapping
+                .map::<E>(AccessType::Store, *vm_addr, bytes.len() as u64)
+                .map_err(|_| EbpfError::SyscallReplayMismatch(pc + ebpf::ELF_INSN_DUMP_OFFSET))?;
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), host_addr as *mut u8, bytes.len());
+            }
+        }
+        Ok(record.result)
+    }
+
+    /// Returns the sorted offsets of the input region read by the last run
+    ///
+    /// Only recorded by the interpreter if Config::track_context_reads is set.
+    pub fn get_context_read_offsets(&self) -> Vec<u64> {
+        self.context_read_offsets.iter().copied().collect()
+    }
+
+    /// Logs every access of the interpreter to the region starting at `vm_addr`
+    ///
+    /// The JIT does not support watched regions.
+    pub fn watch_region(&mut self, vm_addr: u64) -> Result<(), EbpfError<E>> {
+        let index = (vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS) as usize;
+        match self.memory_mapping.get_regions().get(index) {
+            Some(region) if index > 0 && region.vm_addr == vm_addr => {
+                self.region_access_logs.entry(index).or_default();
+                Ok(())
+            }
+            _ => Err(EbpfError::InvalidMemoryRegion(index)),
+        }
+    }
+
+    /// Returns the accesses of the last run to the watched region starting at `vm_addr`
+    ///
+    /// Each access is logged as (index of the executed instruction, offset, len, access type).
+    pub fn get_region_access_log(&self, vm_addr: u64) -> Option<&[(u64, u64, u64, AccessType)]> {
+        self.region_access_logs
+            .get(&((vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS) as usize))
+            .map(|log| log.as_slice())
+    }
+
+    fn log_region_access(&mut self, vm_addr: u64, len: u64, access_type: AccessType) {
+        let index = (vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS) as usize;
+        if let Some(log) = self.region_access_logs.get_mut(&index) {
+            let offset = vm_addr - self.memory_mapping.get_regions()[index].vm_addr;
+            log.push((self.insn_index - 1, offset, len, access_type));
+        }
+    }
+
+    /// Designates the writable region starting at `vm_addr` as output region of the program
+    pub fn set_output_region(&mut self, vm_addr: u64) -> Result<(), EbpfError<E>> {
+        let index = (vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS) as usize;
+        match self.memory_mapping.get_regions().get(index) {
+            Some(region) if index > 0 && region.vm_addr == vm_addr && region.is_writable => {
+                self.output_region_index = Some(index);
+                Ok(())
+            }
+            _ => Err(EbpfError::InvalidMemoryRegion(index)),
+        }
+    }
+
+    /// Zeroes the stack memory, e.g. between unrelated runs of the same VM
+    pub(crate) fn zero_stack(&mut self) {
+        self.stack.zero_memory();
+    }
+
+    /// Resizes the region at the given index, which must not grow beyond its host memory
+    pub(crate) fn resize_region(&mut self, index: usize, new_len: u64) -> Result<(), EbpfError<E>> {
+        self.memory_mapping.resize_region(index, new_len)
+    }
+
+    /// Returns a copy of the contents of the output region
+    ///
+    /// This is meant to be read after the program exited, it is empty if no output region was set.
+    pub fn take_output(&self) -> Vec<u8> {
+        self.output_region_index
+            .map(|index| {
+                let region = &self.memory_mapping.get_regions()[index];
+                unsafe {
+                    std::slice::from_raw_parts(region.host_addr as *const u8, region.len as usize)
+                }
+                .to_vec()
+            })
+            .unwrap_or_default()
+    }
+
     /// Initializes and binds the context object instances for all previously registered syscalls
     ///
     /// # Examples
@@ -656,6 +1773,28 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             .map(|slot| self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET + slot])
     }
 
+    /// Execute the program loaded, using the JIT compiled program if the JIT supports the host
+    /// and the executable was compiled, otherwise the interpreter
+    pub fn execute_program(&mut self, instruction_meter: &mut I) -> ProgramResult<E> {
+        if Executable::<E, I>::is_jit_supported()
+            && self.executable.get_compiled_program().is_some()
+        {
+            self.execute_program_jit(instruction_meter)
+        } else {
+            self.execute_program_interpreted(instruction_meter)
+        }
+    }
+
+    /// Execute the program loaded like `execute_program()` and return the result together with
+    /// the instructions remaining in `instruction_meter` at exit
+    pub fn execute_program_with_remaining(
+        &mut self,
+        instruction_meter: &mut I,
+    ) -> (ProgramResult<E>, u64) {
+        let result = self.execute_program(instruction_meter);
+        (result, instruction_meter.get_remaining())
+    }
+
     /// Execute the program loaded, with the given packet data.
     ///
     /// Warning: The program is executed without limiting the number of
@@ -687,53 +1826,269 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
     /// assert_eq!(res, 0);
     /// ```
     pub fn execute_program_interpreted(&mut self, instruction_meter: &mut I) -> ProgramResult<E> {
+        self.execute_program_interpreted_metered(instruction_meter)
+    }
+
+    /// Execute the program loaded, with the interpreter and at most `budget` instructions
+    ///
+    /// The budget lowers the limit of `instruction_meter` for this run only. The executed
+    /// instructions are still consumed from `instruction_meter`.
+    pub fn execute_program_interpreted_with_budget(
+        &mut self,
+        instruction_meter: &mut I,
+        budget: u64,
+    ) -> ProgramResult<E> {
+        let reserve = instruction_meter.get_remaining().saturating_sub(budget);
+        self.execute_program_interpreted_metered(&mut BudgetInstructionMeter {
+            instruction_meter,
+            reserve,
+        })
+    }
+
+    fn execute_program_interpreted_metered<M: InstructionMeter>(
+        &mut self,
+        instruction_meter: &mut M,
+    ) -> ProgramResult<E> {
+        let mut state = self.begin_interpreted_run(instruction_meter);
+        let result =
+            self.execute_program_interpreted_inner(instruction_meter, &mut state, None, false);
+        self.end_interpreted_run(
+            instruction_meter,
+            &state,
+            result.map(Option::unwrap_or_default),
+        )
+    }
+
+    /// Pauses debugging sessions before executing the instruction at `pc`
+    pub fn set_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    /// Removes a breakpoint, returns false if there was none at `pc`
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    /// Executes a single instruction with the interpreter
+    ///
+    /// Starts a debugging session if none is paused. Pass the same instruction meter to every call
+    /// of a session. Other `execute_program_*` calls abandon a paused session.
+    pub fn debug_step(&mut self, instruction_meter: &mut I) -> DebugEvent<E> {
+        self.debug_run(instruction_meter, Some(1))
+    }
+
+    /// Executes instructions with the interpreter until a breakpoint or the end of the program
+    ///
+    /// Starts a debugging session if none is paused, see `debug_step()`.
+    pub fn debug_continue(&mut self, instruction_meter: &mut I) -> DebugEvent<E> {
+        self.debug_run(instruction_meter, None)
+    }
+
+    fn debug_run(&mut self, instruction_meter: &mut I, max_steps: Option<u64>) -> DebugEvent<E> {
+        let mut state = match self.debug_session.take() {
+            Some(state) => state,
+            None => self.begin_interpreted_run(instruction_meter),
+        };
+        match self.execute_program_interpreted_inner(instruction_meter, &mut state, max_steps, true)
+        {
+            Ok(None) => {
+                if self.executable.get_config().enable_instruction_meter {
+                    instruction_meter.consume(state.last_insn_count);
+                    state.last_insn_count = 0;
+                    state.remaining_insn_count = instruction_meter.get_remaining();
+                }
+                state.resuming = true;
+                let debug_state = DebugState {
+                    pc: state.next_pc.unwrap_or_default(),
+                    registers: state.reg,
+                };
+                let event = if state.at_breakpoint {
+                    DebugEvent::Breakpoint(debug_state)
+                } else {
+                    DebugEvent::Step(debug_state)
+                };
+                self.debug_session = Some(state);
+                event
+            }
+            result => DebugEvent::Exit(self.end_interpreted_run(
+                instruction_meter,
+                &state,
+                result.map(Option::unwrap_or_default),
+            )),
+        }
+    }
+
+    fn begin_interpreted_run<M: InstructionMeter>(
+        &mut self,
+        instruction_meter: &mut M,
+    ) -> InterpreterState {
+        self.debug_session = None;
         let initial_insn_count = if self.executable.get_config().enable_instruction_meter {
             instruction_meter.get_remaining()
         } else {
             0
         };
-        let mut last_insn_count = 0;
-        let result = self.execute_program_interpreted_inner(
-            instruction_meter,
+        self.run_start_remaining.set(initial_insn_count);
+        if self.executable.get_config().track_store_high_water_marks {
+            self.store_high_water_marks = vec![None; self.memory_mapping.get_regions().len()];
+        }
+        if self.executable.get_config().count_region_accesses {
+            self.region_access_counts = vec![(0, 0); self.memory_mapping.get_regions().len()];
+        }
+        self.context_read_offsets.clear();
+        for log in self.region_access_logs.values_mut() {
+            log.clear();
+        }
+        self.insn_index = 0;
+        self.instruction_meter_overshoot = 0;
+        self.memory_access_count = 0;
+        self.branch_edges.clear();
+        self.syscall_accesses.clear();
+        #[cfg(feature = "sha256")]
+        {
+            self.receipt_hasher = if self.executable.get_config().enable_execution_receipt {
+                Some(Sha256::default())
+            } else {
+                None
+            };
+        }
+        if self.executable.get_config().trap_uninitialized_heap_reads {
+            let heap_len = self.heap_bytes().len();
+            self.heap_written = vec![0; (heap_len + 63) / 64];
+        }
+        InterpreterState {
+            reg: [0; 11],
+            next_pc: None,
             initial_insn_count,
-            &mut last_insn_count,
-        );
+            remaining_insn_count: initial_insn_count,
+            last_insn_count: 0,
+            resuming: false,
+            at_breakpoint: false,
+        }
+    }
+
+    fn end_interpreted_run<M: InstructionMeter>(
+        &mut self,
+        instruction_meter: &mut M,
+        state: &InterpreterState,
+        result: ProgramResult<E>,
+    ) -> ProgramResult<E> {
         if self.executable.get_config().enable_instruction_meter {
-            instruction_meter.consume(last_insn_count);
-            self.total_insn_count = initial_insn_count - instruction_meter.get_remaining();
+            self.consume_instructions(instruction_meter, state.last_insn_count);
+            self.total_insn_count = state.initial_insn_count - instruction_meter.get_remaining();
+        }
+        let result = self.check_exit_status(result);
+        #[cfg(feature = "sha256")]
+        if let Some(mut receipt_hasher) = self.receipt_hasher.take() {
+            match &result {
+                Ok(r0) => {
+                    receipt_hasher.update([0]);
+                    receipt_hasher.update(r0.to_le_bytes());
+                }
+                Err(err) => {
+                    receipt_hasher.update([1]);
+                    receipt_hasher.update(err.to_string().as_bytes());
+                }
+            }
+            self.execution_receipt = receipt_hasher.finalize().into();
         }
         result
     }
 
+    /// Charges the instruction meter, recording what exceeds its remaining instructions as
+    /// overshoot
+    fn consume_instructions<M: InstructionMeter>(
+        &mut self,
+        instruction_meter: &mut M,
+        amount: u64,
+    ) {
+        let remaining = instruction_meter.get_remaining();
+        self.instruction_meter_overshoot += amount.saturating_sub(remaining);
+        instruction_meter.consume(amount.min(remaining));
+    }
+
+    /// Turns an r0 outside of Config::allowed_exit_codes into an error, as well as a nonzero r0
+    /// if Config::nonzero_exit_is_failure is set
+    fn check_exit_status(&self, result: ProgramResult<E>) -> ProgramResult<E> {
+        match result {
+            Ok(code)
+                if matches!(
+                    &self.executable.get_config().allowed_exit_codes,
+                    Some(allowed_exit_codes) if !allowed_exit_codes.contains(&code)
+                ) =>
+            {
+                Err(EbpfError::InvalidExitCode(code))
+            }
+            Ok(code) if code != 0 && self.executable.get_config().nonzero_exit_is_failure => {
+                Err(EbpfError::ProgramFailed(code))
+            }
+            result => result,
+        }
+    }
+
     #[rustfmt::skip]
-    fn execute_program_interpreted_inner(
+    fn execute_program_interpreted_inner<M: InstructionMeter>(
         &mut self,
-        instruction_meter: &mut I,
-        initial_insn_count: u64,
-        last_insn_count: &mut u64,
-    ) -> ProgramResult<E> {
-        // R1 points to beginning of input memory, R10 to the stack of the first frame
-        let mut reg: [u64; 11] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, self.stack.get_frame_ptr()];
-        reg[1] = ebpf::MM_INPUT_START;
+        instruction_meter: &mut M,
+        state: &mut InterpreterState,
+        max_steps: Option<u64>,
+        debugging: bool,
+    ) -> Result<Option<u64>, EbpfError<E>> {
+        let config = self.executable.get_config();
+        let (mut reg, mut next_pc) = match state.next_pc {
+            Some(next_pc) => (state.reg, next_pc),
+            None => {
+                // R1 points to beginning of input memory, R10 to the stack of the first frame
+                self.stack.reset();
+                let reg = config.initial_registers();
+                debug_assert_eq!(reg[ebpf::FRAME_PTR_REG], self.stack.get_frame_ptr());
+                (reg, self.executable.get_entrypoint_instruction_offset()?)
+            }
+        };
+        let initial_insn_count = state.initial_insn_count;
+        let mut remaining_insn_count = state.remaining_insn_count;
+        let last_insn_count = &mut state.last_insn_count;
 
         // Loop on instructions
-        let config = self.executable.get_config();
-        let mut next_pc: usize = self.executable.get_entrypoint_instruction_offset()?;
-        let mut remaining_insn_count = initial_insn_count;
+        let instruction_costs = config.instruction_cost_table();
+        let instruction_costs = instruction_costs.as_deref();
+        let saturating_shifts = config.shift_overflow_mode == ShiftOverflowMode::Saturate;
+        let shift_overflows = move |amount: u64, width: u64| saturating_shifts && amount >= width;
+        let mut steps = 0;
+        let mut skip_breakpoint = state.resuming;
         while (next_pc + 1) * ebpf::INSN_SIZE <= self.program.len() {
-            *last_insn_count += 1;
             let pc = next_pc;
+            if debugging {
+                let at_breakpoint = !skip_breakpoint && self.breakpoints.contains(&pc);
+                if max_steps == Some(steps) || at_breakpoint {
+                    state.reg = reg;
+                    state.next_pc = Some(pc);
+                    state.remaining_insn_count = remaining_insn_count;
+                    state.at_breakpoint = max_steps != Some(steps);
+                    return Ok(None);
+                }
+                steps += 1;
+                skip_breakpoint = false;
+            }
+            self.last_pc = pc;
+            self.insn_index += 1;
             next_pc += 1;
             let mut instruction_width = 1;
             let mut insn = ebpf::get_insn_unchecked(self.program, pc);
+            *last_insn_count += instruction_costs.map_or(1, |costs| costs[insn.opc as usize]);
             let dst = insn.dst as usize;
             let src = insn.src as usize;
 
+            if let Some(instruction_observer) = self.instruction_observer.as_mut() {
+                instruction_observer.before(pc, insn.opc, &reg);
+            }
             if config.enable_instruction_tracing {
-                let mut state = [0u64; 12];
-                state[0..11].copy_from_slice(&reg);
-                state[11] = pc as u64;
-                self.tracer.trace(state);
+                self.tracer.before(pc, insn.opc, &reg);
+            }
+            #[cfg(feature = "sha256")]
+            if let Some(receipt_hasher) = self.receipt_hasher.as_mut() {
+                receipt_hasher.update((pc as u64).to_le_bytes());
+                receipt_hasher.update([insn.opc]);
             }
 
             match insn.opc {
@@ -898,10 +2253,11 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
                 ebpf::OR32_REG   =>   reg[dst] = (reg[dst] as u32             | reg[src] as u32) as u64,
                 ebpf::AND32_IMM  =>   reg[dst] = (reg[dst] as u32             & insn.imm as u32) as u64,
                 ebpf::AND32_REG  =>   reg[dst] = (reg[dst] as u32             & reg[src] as u32) as u64,
-                ebpf::LSH32_IMM  =>   reg[dst] = (reg[dst] as u32).wrapping_shl(insn.imm as u32) as u64,
-                ebpf::LSH32_REG  =>   reg[dst] = (reg[dst] as u32).wrapping_shl(reg[src] as u32) as u64,
-                ebpf::RSH32_IMM  =>   reg[dst] = (reg[dst] as u32).wrapping_shr(insn.imm as u32) as u64,
-                ebpf::RSH32_REG  =>   reg[dst] = (reg[dst] as u32).wrapping_shr(reg[src] as u32) as u64,
+                // wrapping_shl / wrapping_shr mask the shift amount to the operand width
+                ebpf::LSH32_IMM  =>   reg[dst] = if shift_overflows(insn.imm as u32 as u64, 32) { 0 } else { (reg[dst] as u32).wrapping_shl(insn.imm as u32) as u64 },
+                ebpf::LSH32_REG  =>   reg[dst] = if shift_overflows(reg[src], 32)               { 0 } else { (reg[dst] as u32).wrapping_shl(reg[src] as u32) as u64 },
+                ebpf::RSH32_IMM  =>   reg[dst] = if shift_overflows(insn.imm as u32 as u64, 32) { 0 } else { (reg[dst] as u32).wrapping_shr(insn.imm as u32) as u64 },
+                ebpf::RSH32_REG  =>   reg[dst] = if shift_overflows(reg[src], 32)               { 0 } else { (reg[dst] as u32).wrapping_shr(reg[src] as u32) as u64 },
                 ebpf::NEG32      => { reg[dst] = (reg[dst] as i32).wrapping_neg()                as u64; reg[dst] &= u32::MAX as u64; },
                 ebpf::MOD32_IMM  =>   reg[dst] = (reg[dst] as u32             % insn.imm as u32) as u64,
                 ebpf::MOD32_REG  => {
@@ -914,8 +2270,8 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
                 ebpf::XOR32_REG  =>   reg[dst] = (reg[dst] as u32            ^ reg[src]  as u32) as u64,
                 ebpf::MOV32_IMM  =>   reg[dst] = insn.imm  as u32                                as u64,
                 ebpf::MOV32_REG  =>   reg[dst] = (reg[src] as u32)                               as u64,
-                ebpf::ARSH32_IMM => { reg[dst] = (reg[dst] as i32).wrapping_shr(insn.imm as u32) as u64; reg[dst] &= u32::MAX as u64; },
-                ebpf::ARSH32_REG => { reg[dst] = (reg[dst] as i32).wrapping_shr(reg[src] as u32) as u64; reg[dst] &= u32::MAX as u64; },
+                ebpf::ARSH32_IMM => { reg[dst] = if shift_overflows(insn.imm as u32 as u64, 32) { (reg[dst] as i32 >> 31) as u64 } else { (reg[dst] as i32).wrapping_shr(insn.imm as u32) as u64 }; reg[dst] &= u32::MAX as u64; },
+                ebpf::ARSH32_REG => { reg[dst] = if shift_overflows(reg[src], 32)               { (reg[dst] as i32 >> 31) as u64 } else { (reg[dst] as i32).wrapping_shr(reg[src] as u32) as u64 }; reg[dst] &= u32::MAX as u64; },
                 ebpf::LE         => {
                     reg[dst] = match insn.imm {
                         16 => (reg[dst] as u16).to_le() as u64,
@@ -971,10 +2327,10 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
                 ebpf::OR64_REG   => reg[dst] |=  reg[src],
                 ebpf::AND64_IMM  => reg[dst] &=  insn.imm as u64,
                 ebpf::AND64_REG  => reg[dst] &=  reg[src],
-                ebpf::LSH64_IMM  => reg[dst] = reg[dst].wrapping_shl(insn.imm as u32),
-                ebpf::LSH64_REG  => reg[dst] = reg[dst].wrapping_shl(reg[src] as u32),
-                ebpf::RSH64_IMM  => reg[dst] = reg[dst].wrapping_shr(insn.imm as u32),
-                ebpf::RSH64_REG  => reg[dst] = reg[dst].wrapping_shr(reg[src] as u32),
+                ebpf::LSH64_IMM  => reg[dst] = if shift_overflows(insn.imm as u32 as u64, 64) { 0 } else { reg[dst].wrapping_shl(insn.imm as u32) },
+                ebpf::LSH64_REG  => reg[dst] = if shift_overflows(reg[src], 64)               { 0 } else { reg[dst].wrapping_shl(reg[src] as u32) },
+                ebpf::RSH64_IMM  => reg[dst] = if shift_overflows(insn.imm as u32 as u64, 64) { 0 } else { reg[dst].wrapping_shr(insn.imm as u32) },
+                ebpf::RSH64_REG  => reg[dst] = if shift_overflows(reg[src], 64)               { 0 } else { reg[dst].wrapping_shr(reg[src] as u32) },
                 ebpf::NEG64      => reg[dst] = (reg[dst] as i64).wrapping_neg() as u64,
                 ebpf::MOD64_IMM  => reg[dst] %= insn.imm  as u64,
                 ebpf::MOD64_REG  => {
@@ -987,8 +2343,8 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
                 ebpf::XOR64_REG  => reg[dst] ^= reg[src],
                 ebpf::MOV64_IMM  => reg[dst] =  insn.imm as u64,
                 ebpf::MOV64_REG  => reg[dst] =  reg[src],
-                ebpf::ARSH64_IMM => reg[dst] = (reg[dst] as i64).wrapping_shr(insn.imm as u32) as u64,
-                ebpf::ARSH64_REG => reg[dst] = (reg[dst] as i64).wrapping_shr(reg[src] as u32) as u64,
+                ebpf::ARSH64_IMM => reg[dst] = if shift_overflows(insn.imm as u32 as u64, 64) { (reg[dst] as i64 >> 63) as u64 } else { (reg[dst] as i64).wrapping_shr(insn.imm as u32) as u64 },
+                ebpf::ARSH64_REG => reg[dst] = if shift_overflows(reg[src], 64)               { (reg[dst] as i64 >> 63) as u64 } else { (reg[dst] as i64).wrapping_shr(reg[src] as u32) as u64 },
 
                 // BPF_JMP class
                 ebpf::JA         =>                                          { next_pc = (next_pc as isize + insn.off as isize) as usize; },
@@ -1039,25 +2395,15 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
                         if let Some(syscall) = self.executable.get_syscall_registry().lookup_syscall(insn.imm as u32) {
                             resolved = true;
 
-                            if config.enable_instruction_meter {
-                                let _ = instruction_meter.consume(*last_insn_count);
-                            }
-                            *last_insn_count = 0;
-                            let mut result: ProgramResult<E> = Ok(0);
-                            (unsafe { std::mem::transmute::<u64, SyscallFunction::<E, *mut u8>>(syscall.function) })(
-                                self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET + syscall.context_object_slot],
-                                reg[1],
-                                reg[2],
-                                reg[3],
-                                reg[4],
-                                reg[5],
-                                &self.memory_mapping,
-                                &mut result,
-                            );
-                            reg[0] = result?;
-                            if config.enable_instruction_meter {
-                                remaining_insn_count = instruction_meter.get_remaining();
-                            }
+                            reg[0] = self.call_syscall(
+                                pc,
+                                insn.imm as u32,
+                                syscall,
+                                [reg[1], reg[2], reg[3], reg[4], reg[5]],
+                                instruction_meter,
+                                last_insn_count,
+                                &mut remaining_insn_count,
+                            )?;
                         }
                     }
 
@@ -1092,13 +2438,33 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
                             next_pc = self.check_pc(pc, ptr)?;
                         }
                         _ => {
-                            return Ok(reg[0]);
+                            if let Some(hash) = self.executable.get_syscall_registry().get_exit_finalizer() {
+                                let syscall = self.executable.get_syscall_registry().lookup_syscall(hash)
+                                    .ok_or(EbpfError::SyscallNotRegistered(hash as usize))?;
+                                self.call_syscall(
+                                    pc,
+                                    hash,
+                                    syscall,
+                                    [reg[0], 0, 0, 0, 0],
+                                    instruction_meter,
+                                    last_insn_count,
+                                    &mut remaining_insn_count,
+                                )?;
+                            }
+                            return Ok(Some(reg[0]));
                         }
                     }
                 }
                 _ => return Err(EbpfError::UnsupportedInstruction(pc + ebpf::ELF_INSN_DUMP_OFFSET)),
             }
 
+            if config.track_branch_edges
+                && insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP
+                && !matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::EXIT)
+            {
+                self.branch_edges.insert((pc, next_pc));
+            }
+
             if config.enable_instruction_meter && *last_insn_count >= remaining_insn_count {
                 // Use `pc + instruction_width` instead of `next_pc` here because jumps and calls don't continue at the end of this instruction
                 return Err(EbpfError::ExceededMaxInstructions(pc + instruction_width + ebpf::ELF_INSN_DUMP_OFFSET, initial_insn_count));
@@ -1110,6 +2476,86 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
         ))
     }
 
+    /// Calls a syscall from the interpreter
+    ///
+    /// Syncs the instruction meter, replays or records the call, traces its memory accesses and
+    /// enforces purity and the return range.
+    #[allow(clippy::too_many_arguments)]
+    fn call_syscall<M: InstructionMeter>(
+        &mut self,
+        pc: usize,
+        hash: u32,
+        syscall: &Syscall,
+        args: [u64; 5],
+        instruction_meter: &mut M,
+        last_insn_count: &mut u64,
+        remaining_insn_count: &mut u64,
+    ) -> ProgramResult<E> {
+        let config = self.executable.get_config();
+        if config.enable_instruction_meter {
+            self.consume_instructions(instruction_meter, *last_insn_count);
+        }
+        *last_insn_count = 0;
+        if let Some(current_pc) = &self.current_pc {
+            current_pc.set(pc as u64);
+        }
+        let return_value = if let Some(SyscallLog::Replay(_)) = self.syscall_log {
+            self.replay_syscall(pc, hash, args)?
+        } else {
+            let recording = self.syscall_log.is_some();
+            let mut result: ProgramResult<E> = Ok(0);
+            let tracing =
+                config.trace_syscall_accesses || recording || config.trap_uninitialized_heap_reads;
+            if tracing {
+                self.memory_mapping.start_access_trace();
+            }
+            if syscall.is_pure {
+                self.memory_mapping.set_pure_syscall(Some(hash));
+            }
+            (unsafe { std::mem::transmute::<u64, SyscallFunction<E, *mut u8>>(syscall.function) })(
+                self.syscall_context_objects
+                    [SYSCALL_CONTEXT_OBJECTS_OFFSET + syscall.context_object_slot],
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+                args[4],
+                &self.memory_mapping,
+                &mut result,
+            );
+            if syscall.is_pure && self.memory_mapping.set_pure_syscall(None) {
+                return Err(EbpfError::PureSyscallViolation(hash));
+            }
+            let accesses = if tracing {
+                self.memory_mapping.take_access_trace()
+            } else {
+                Vec::new()
+            };
+            if config.trap_uninitialized_heap_reads {
+                self.mark_heap_stores(&accesses);
+            }
+            if recording {
+                if let Ok(return_value) = result {
+                    self.record_syscall(hash, args, return_value, &accesses);
+                }
+            }
+            if config.trace_syscall_accesses {
+                self.syscall_accesses
+                    .push(SyscallAccesses { pc, hash, accesses });
+            }
+            result?
+        };
+        if let Some(return_range) = &syscall.return_range {
+            if !return_range.contains(&return_value) {
+                return Err(EbpfError::SyscallReturnOutOfRange(hash, return_value));
+            }
+        }
+        if config.enable_instruction_meter {
+            *remaining_insn_count = instruction_meter.get_remaining();
+        }
+        Ok(return_value)
+    }
+
     fn check_pc(&self, current_pc: usize, target_pc: usize) -> Result<usize, EbpfError<E>> {
         let offset =
             target_pc
@@ -1136,16 +2582,46 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
     /// the program works with the interpreter before running the JIT-compiled version of it.
     ///
     pub fn execute_program_jit(&mut self, instruction_meter: &mut I) -> ProgramResult<E> {
+        self.debug_session = None;
         let initial_insn_count = if self.executable.get_config().enable_instruction_meter {
             instruction_meter.get_remaining()
         } else {
             0
         };
+        self.run_start_remaining.set(initial_insn_count);
         let result: ProgramResult<E> = Ok(0);
         let compiled_program = self
             .executable
             .get_compiled_program()
             .ok_or(EbpfError::JitNotCompiled)?;
+        if self
+            .memory_mapping
+            .get_regions()
+            .iter()
+            .any(|region| region.is_ring)
+        {
+            return Err(EbpfError::UnsupportedJitOption("ring buffer regions"));
+        }
+        if self
+            .memory_mapping
+            .get_regions()
+            .iter()
+            .any(|region| region.is_cow)
+        {
+            return Err(EbpfError::UnsupportedJitOption("copy-on-write regions"));
+        }
+        if self.syscall_log.is_some() {
+            return Err(EbpfError::UnsupportedJitOption("syscall logs"));
+        }
+        if !self.region_access_logs.is_empty() {
+            return Err(EbpfError::UnsupportedJitOption("watched regions"));
+        }
+        if self.current_pc.is_some() {
+            return Err(EbpfError::UnsupportedJitOption("current pc handle"));
+        }
+        if self.instruction_observer.is_some() {
+            return Err(EbpfError::UnsupportedJitOption("instruction observers"));
+        }
         let instruction_meter_final = unsafe {
             self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET - 1] =
                 &mut self.tracer as *mut _ as *mut u8;
@@ -1155,8 +2631,15 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
                 &*(self.syscall_context_objects.as_ptr() as *const JitProgramArgument),
                 instruction_meter,
             )
-            .max(0) as u64
         };
+        // Without weighted costs an exhausted meter always ends one below zero
+        self.instruction_meter_overshoot =
+            if self.executable.get_config().instruction_costs.is_some() {
+                instruction_meter_final.min(0).unsigned_abs()
+            } else {
+                0
+            };
+        let instruction_meter_final = instruction_meter_final.max(0) as u64;
         if self.executable.get_config().enable_instruction_meter {
             let remaining_insn_count = instruction_meter.get_remaining();
             let last_insn_count = remaining_insn_count - instruction_meter_final;
@@ -1165,11 +2648,11 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             // Same as:
             // self.total_insn_count = initial_insn_count - instruction_meter.get_remaining();
         }
-        match result {
+        self.check_exit_status(match result {
             Err(EbpfError::ExceededMaxInstructions(pc, _)) => {
                 Err(EbpfError::ExceededMaxInstructions(pc, initial_insn_count))
             }
             x => x,
-        }
+        })
     }
 }
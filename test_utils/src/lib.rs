@@ -11,6 +11,20 @@
 extern crate libc;
 extern crate solana_rbpf;
 
+use solana_rbpf::{
+    assembler::assemble,
+    ebpf,
+    elf::Executable,
+    memory_region::MemoryRegion,
+    static_analysis::Analysis,
+    user_error::UserError,
+    vm::{Config, EbpfVm, ProgramResult, SyscallRegistry, TestInstructionMeter, Tracer},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
 // Assembly code and data for tcp_sack testcases.
 
 pub const PROG_TCP_PORT_80: &str = "
@@ -151,3 +165,138 @@ pub const TCP_SACK_NOMATCH: [u8; 66] = [
     0x08, 0x0a, 0x00, 0x17, 0x95, 0x6f, 0x8d, 0x9d, //
     0x9e, 0x27, //
 ];
+
+/// Instruction budget given to every program of a differential corpus
+pub const CORPUS_INSTRUCTION_LIMIT: u64 = 100_000;
+
+/// A corpus fixture on which the interpreter and the JIT disagree
+#[derive(Debug)]
+pub struct CorpusDivergence {
+    /// Path of the assembly source of the fixture
+    pub path: PathBuf,
+    /// Description of what differs between the two runs
+    pub details: String,
+}
+
+/// Runs every `*.asm` fixture in `dir` in the interpreter and the JIT and reports divergences.
+///
+/// The input memory of a fixture `name.asm` is read from `name.mem` if that file exists,
+/// otherwise the program runs without input memory. The result, the instruction count and
+/// the instruction trace of both runs are compared.
+pub fn compare_interpreter_and_jit_on_corpus(dir: &Path) -> std::io::Result<Vec<CorpusDivergence>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    paths.retain(|path| path.extension().map(|ext| ext == "asm").unwrap_or(false));
+    paths.sort();
+    let mut divergences = Vec::new();
+    for path in paths {
+        let source = fs::read_to_string(&path)?;
+        let mem_path = path.with_extension("mem");
+        let mem = if mem_path.exists() {
+            fs::read(&mem_path)?
+        } else {
+            Vec::new()
+        };
+        if let Err(details) = compare_interpreter_and_jit(&source, &mem) {
+            divergences.push(CorpusDivergence { path, details });
+        }
+    }
+    Ok(divergences)
+}
+
+/// Runs a single program in the interpreter and the JIT and describes any divergence
+pub fn compare_interpreter_and_jit(source: &str, mem: &[u8]) -> Result<(), String> {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        source,
+        None,
+        config,
+        SyscallRegistry::default(),
+    )?;
+    Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable)
+        .map_err(|err| format!("JIT compilation failed: {:?}", err))?;
+    let run = |jit: bool| -> Result<(ProgramResult<UserError>, u64, Tracer), String> {
+        let mut mem = mem.to_vec();
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm =
+            EbpfVm::<UserError, TestInstructionMeter>::new(&executable, &mut [], vec![mem_region])
+                .map_err(|err| format!("VM creation failed: {:?}", err))?;
+        let mut instruction_meter = TestInstructionMeter {
+            remaining: CORPUS_INSTRUCTION_LIMIT,
+        };
+        let result = if jit {
+            vm.execute_program_jit(&mut instruction_meter)
+        } else {
+            vm.execute_program_interpreted(&mut instruction_meter)
+        };
+        Ok((
+            result,
+            vm.get_total_instruction_count(),
+            vm.get_tracer().clone(),
+        ))
+    };
+    let (result_interpreter, insn_count_interpreter, tracer_interpreter) = run(false)?;
+    let (result_jit, insn_count_jit, tracer_jit) = run(true)?;
+    if result_interpreter != result_jit {
+        return Err(format!(
+            "result differs: interpreter {:?}, JIT {:?}",
+            result_interpreter, result_jit
+        ));
+    }
+    if insn_count_interpreter != insn_count_jit {
+        return Err(format!(
+            "instruction count differs: interpreter {}, JIT {}",
+            insn_count_interpreter, insn_count_jit
+        ));
+    }
+    // The log of the JIT can be longer, see `Tracer::compare()`
+    if let Some(index) = tracer_interpreter
+        .first_divergence(&tracer_jit)
+        .filter(|index| *index < tracer_interpreter.log.len())
+    {
+        return Err(format!(
+            "trace differs at instruction {}: interpreter {:X?}, JIT {:X?}",
+            index,
+            tracer_interpreter.log.get(index),
+            tracer_jit.log.get(index)
+        ));
+    }
+    Ok(())
+}
+
+/// Assembles `source`, disassembles the result, assembles that again and asserts that both
+/// assembled programs are identical
+pub fn assert_asm_roundtrip(source: &str) {
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        source,
+        None,
+        config.clone(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut disassembly = Vec::new();
+    analysis.disassemble(&mut disassembly).unwrap();
+    let disassembly = String::from_utf8(disassembly).unwrap();
+    let reassembled = assemble::<UserError, TestInstructionMeter>(
+        &disassembly,
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap_or_else(|err| panic!("{}\n{}", err, disassembly));
+    assert_eq!(
+        executable.get_text_bytes().1,
+        reassembled.get_text_bytes().1,
+        "{}",
+        disassembly
+    );
+}
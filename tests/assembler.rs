@@ -14,7 +14,7 @@ use solana_rbpf::{
     user_error::UserError,
     vm::{Config, SyscallRegistry, TestInstructionMeter},
 };
-use test_utils::{TCP_SACK_ASM, TCP_SACK_BIN};
+use test_utils::{assert_asm_roundtrip, TCP_SACK_ASM, TCP_SACK_BIN};
 
 fn asm(src: &str) -> Result<Vec<ebpf::Insn>, String> {
     let executable = assemble::<UserError, TestInstructionMeter>(
@@ -586,6 +586,30 @@ fn test_tcp_sack() {
     assert_eq!(program, TCP_SACK_BIN.to_vec());
 }
 
+#[test]
+fn test_labels() {
+    let src = "
+        mov64 r1, 0
+    loop:
+        add64 r1, 1
+        jlt r1, 3, loop
+        jeq r1, 3, done
+        mov64 r1, 7
+    done:
+        exit";
+    assert_eq!(
+        asm(src),
+        asm("
+        mov64 r1, 0
+        add64 r1, 1
+        jlt r1, 3, -2
+        jeq r1, 3, +1
+        mov64 r1, 7
+        exit")
+    );
+    assert_asm_roundtrip(src);
+}
+
 #[test]
 fn test_error_invalid_instruction() {
     assert_eq!(asm("abcd"), Err("Invalid instruction \"abcd\"".to_string()));
@@ -599,6 +623,26 @@ fn test_error_unexpected_operands() {
     );
 }
 
+#[test]
+fn test_error_undefined_label() {
+    assert_eq!(
+        asm("mov r0, 0\nja done\nexit"),
+        Err("Label not found done at line 2".to_string())
+    );
+    assert_eq!(
+        asm("jeq r1, r2, done\nexit"),
+        Err("Label not found done at line 1".to_string())
+    );
+}
+
+#[test]
+fn test_error_duplicate_label() {
+    assert_eq!(
+        asm("done:\nexit\ndone:\nexit"),
+        Err("Duplicate label done at line 3".to_string())
+    );
+}
+
 #[test]
 fn test_error_operands_out_of_range() {
     assert_eq!(
@@ -0,0 +1,9 @@
+mov64 r0, 0x10
+mov32 r1, -1
+add64 r0, r1
+mul64 r0, 3
+xor64 r0, 0x55
+lsh64 r0, 7
+arsh64 r0, 3
+neg64 r0
+exit
@@ -0,0 +1,6 @@
+mov64 r0, 0
+mov64 r1, 10
+add64 r0, r1
+sub64 r1, 1
+jne r1, 0, -3
+exit
@@ -0,0 +1,4 @@
+mov64 r0, 1
+mov64 r1, 0
+div64 r0, r1
+exit
@@ -0,0 +1,9 @@
+ldxdw r2, [r1]
+ldxw r3, [r1+8]
+stxdw [r10-8], r2
+ldxdw r0, [r10-8]
+add64 r0, r3
+stxw [r1+12], r0
+ldxb r4, [r1+12]
+add64 r0, r4
+exit
@@ -7,12 +7,16 @@
 // copied, modified, or distributed except according to those terms.
 
 extern crate solana_rbpf;
+extern crate test_utils;
 use solana_rbpf::{
     assembler::assemble,
-    static_analysis::Analysis,
+    disassembler::DisassembledInstruction,
+    static_analysis::{Analysis, StructuralIssue},
     user_error::UserError,
     vm::{Config, SyscallRegistry, TestInstructionMeter},
 };
+use std::io::Write;
+use test_utils::assert_asm_roundtrip;
 
 // Using a macro to keep actual line numbers in failure output
 macro_rules! disasm {
@@ -379,3 +383,463 @@ fn test_large_immediate() {
     disasm!("entrypoint:\n    add64 r1, -1\n");
     disasm!("entrypoint:\n    add64 r1, -1\n");
 }
+
+#[test]
+fn test_asm_roundtrip() {
+    // BPF_LD class
+    assert_asm_roundtrip(
+        "
+        ldabsb 0x1
+        ldabsh 0x2
+        ldabsw 0x3
+        ldabsdw 0x4
+        ldindb r1, 0x1
+        ldindh r2, 0x2
+        ldindw r3, 0x3
+        ldinddw r4, 0x4
+        lddw r1, 0x1234abcd5678eeff
+        lddw r2, -1
+        exit",
+    );
+    // BPF_LDX, BPF_ST and BPF_STX classes
+    assert_asm_roundtrip(
+        "
+        ldxb r1, [r2+0x1]
+        ldxh r1, [r2-0x2]
+        ldxw r1, [r2+0x3]
+        ldxdw r1, [r10-0x8]
+        stb [r1+0x1], 1
+        sth [r1-0x2], 2
+        stw [r1+0x3], 3
+        stdw [r1-0x4], 4
+        stxb [r1+0x1], r2
+        stxh [r1-0x2], r3
+        stxw [r1+0x3], r4
+        stxdw [r10-0x8], r5
+        exit",
+    );
+    // BPF_ALU class
+    assert_asm_roundtrip(
+        "
+        add32 r1, 2
+        sub32 r1, r2
+        mul32 r1, -2
+        div32 r1, r2
+        sdiv32 r1, 3
+        or32 r1, r2
+        and32 r1, 2
+        lsh32 r1, r2
+        rsh32 r1, 31
+        neg32 r1
+        mod32 r1, r2
+        xor32 r1, 2
+        mov32 r1, -1
+        arsh32 r1, r2
+        le16 r1
+        le32 r1
+        le64 r1
+        be16 r1
+        be32 r1
+        be64 r1
+        exit",
+    );
+    // BPF_ALU64 class
+    assert_asm_roundtrip(
+        "
+        add64 r1, r2
+        sub64 r1, 2
+        mul64 r1, r2
+        div64 r1, 2
+        sdiv64 r1, r2
+        or64 r1, -2
+        and64 r1, r2
+        lsh64 r1, 63
+        rsh64 r1, r2
+        neg64 r1
+        mod64 r1, 2
+        xor64 r1, r2
+        mov64 r1, 0x7fffffff
+        arsh64 r1, 2
+        exit",
+    );
+    // BPF_JMP class
+    assert_asm_roundtrip(
+        "
+        ja +1
+        jeq r1, 1, +0
+        jeq r1, r2, +0
+        jgt r1, 1, +0
+        jgt r1, r2, +0
+        jge r1, 1, +0
+        jge r1, r2, +0
+        jlt r1, 1, +0
+        jlt r1, r2, +0
+        jle r1, 1, +0
+        jle r1, r2, +0
+        jset r1, 1, +0
+        jset r1, r2, +0
+        jne r1, 1, +0
+        jne r1, r2, +0
+        jsgt r1, 1, +0
+        jsgt r1, r2, +0
+        jsge r1, 1, +0
+        jsge r1, r2, +0
+        jslt r1, 1, +0
+        jslt r1, r2, +0
+        jsle r1, 1, -2
+        jsle r1, r2, +0
+        exit",
+    );
+    // Calls
+    assert_asm_roundtrip(
+        "
+        call function_foo
+        callx r2
+        exit
+        function_foo:
+        mov r0, 42
+        exit",
+    );
+}
+
+#[test]
+fn test_redundant_instructions() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r3, r3
+        mov r3, r2
+        add r2, 0
+        add r2, 1
+        and r2, -1
+        jeq r2, r2, +0
+        jeq r2, r2, +1
+        mov32 r4, r4
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.redundant_instructions(),
+        vec![
+            (0, "moves a register to itself"),
+            (2, "operand is the identity element"),
+            (4, "operand is the identity element"),
+            (5, "jumps to the next instruction"),
+        ]
+    );
+}
+
+#[test]
+fn test_check_structure() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 0
+        call function_foo
+        exit
+        function_foo:
+        jeq r0, 0, +1
+        mov r0, 1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(analysis.check_structure(), Ok(()));
+
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 0
+        call function_foo
+        call function_bar
+        add r0, 1
+        function_foo:
+        jeq r0, 0, +2
+        exit
+        function_bar:
+        mov r0, 2
+        mov r0, 3",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.check_structure(),
+        Err(vec![
+            StructuralIssue::MissingExit(0),
+            StructuralIssue::FallthroughIntoFunction(3),
+            StructuralIssue::JumpOutOfFunction(4, 7),
+            StructuralIssue::MissingExit(6),
+            StructuralIssue::FallthroughOffEnd(7),
+        ])
+    );
+}
+
+#[test]
+fn test_disassemble_with_raw_bytes() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r1, 42
+        lddw r0, 0x1122334455667788
+        exit",
+        None,
+        Config {
+            enable_symbol_and_section_labels: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut output = Vec::new();
+    analysis.disassemble_with_raw_bytes(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.lines().map(|line| line.trim()).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        vec![
+            "entrypoint:",
+            "b7 01 00 00 2a 00 00 00                            mov64 r1, 42",
+            "18 00 00 00 88 77 66 55 00 00 00 00 44 33 22 11    lddw r0, 0x1122334455667788",
+            "95 00 00 00 00 00 00 00                            exit",
+        ]
+    );
+}
+
+#[test]
+fn test_disassembled_instructions() {
+    let src = "entrypoint:
+    jeq r1, 4, lbb_2
+    be16 r1
+lbb_2:
+    call function_3
+    exit
+
+function_3:
+    exit
+";
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        src,
+        None,
+        Config {
+            enable_symbol_and_section_labels: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let instructions = analysis.disassembled_instructions().collect::<Vec<_>>();
+    assert_eq!(
+        instructions[0],
+        DisassembledInstruction {
+            pc: 0,
+            opcode: 0x15,
+            dst: 1,
+            src: 0,
+            off: 1,
+            imm: 4,
+            mnemonic: "jeq".to_string(),
+            operands: "r1, 4, lbb_2".to_string(),
+        }
+    );
+    assert_eq!(instructions[1].mnemonic, "be16");
+    assert_eq!(instructions[1].operands, "r1");
+    assert_eq!(instructions[2].mnemonic, "call");
+    assert_eq!(instructions[2].operands, "function_3");
+    assert_eq!(instructions[3].operands, "");
+
+    let mut output = Vec::new();
+    let sink: &mut dyn Write = &mut output;
+    analysis.disassemble(sink).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output, src);
+    let text = output
+        .lines()
+        .filter(|line| line.starts_with("    "))
+        .map(|line| line.trim().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        instructions
+            .iter()
+            .map(|insn| insn.to_string())
+            .collect::<Vec<_>>(),
+        text
+    );
+}
+
+#[test]
+fn test_jump_target_labels() {
+    let src = "entrypoint:
+    mov64 r1, 0
+    jeq r2, 0, lbb_3
+    mov64 r1, 1
+lbb_3:
+    add64 r1, 1
+    jlt r1, 3, lbb_3
+    exit
+";
+    disasm!(src);
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        src,
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut jump_targets = analysis
+        .disassembled_instructions()
+        .filter(|insn| insn.mnemonic.starts_with('j'))
+        .map(|insn| (insn.pc as isize + insn.off as isize + 1) as usize)
+        .collect::<Vec<_>>();
+    jump_targets.sort_unstable();
+    jump_targets.dedup();
+    let mut labeled_pcs = Vec::new();
+    let mut pc = 0;
+    for line in src.lines().skip(1) {
+        if let Some(label) = line.strip_suffix(':') {
+            assert_eq!(label, format!("lbb_{}", pc));
+            labeled_pcs.push(pc);
+        } else {
+            pc += 1;
+        }
+    }
+    assert_eq!(labeled_pcs, jump_targets);
+}
+
+#[test]
+fn test_instruction_at() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r1, 2
+        lddw r0, 0x1122334455667788
+        add r0, r1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.instruction_at(1),
+        Some(DisassembledInstruction {
+            pc: 1,
+            opcode: 0x18,
+            dst: 0,
+            src: 0,
+            off: 0,
+            imm: 0x1122334455667788,
+            mnemonic: "lddw".to_string(),
+            operands: "r0, 0x1122334455667788".to_string(),
+        })
+    );
+    let insn = analysis.instruction_at(3).unwrap();
+    assert_eq!(insn.to_string(), "add64 r0, r1");
+    assert_eq!((insn.dst, insn.src), (0, 1));
+    assert_eq!(analysis.instruction_at(2), None);
+    assert_eq!(analysis.instruction_at(5), None);
+}
+
+#[test]
+fn test_disassemble_range() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        jeq r0, 1, +2
+        lddw r1, 0x1122334455667788
+        add r0, r1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let lines = analysis
+        .disassembled_instructions()
+        .map(|insn| insn.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(analysis.disassemble_range(0, 6), lines);
+    assert_eq!(analysis.disassemble_range(1, 2), lines[1..2]);
+    // The lddw occupies the slots 2 and 3
+    assert_eq!(analysis.disassemble_range(1, 3), lines[1..3]);
+    assert_eq!(analysis.disassemble_range(3, 5), lines[2..4]);
+    assert_eq!(analysis.disassemble_range(4, 100), lines[3..]);
+    assert!(analysis.disassemble_range(6, 8).is_empty());
+    assert!(analysis.disassemble_range(2, 2).is_empty());
+}
+
+#[test]
+fn test_opt_in_jump_labels() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r1, 0
+        jeq r2, 0, +1
+        mov64 r1, 1
+        add64 r1, 1
+        jlt r1, 3, -2
+        ja +0
+        exit",
+        None,
+        Config {
+            enable_symbol_and_section_labels: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut default_output = Vec::new();
+    analysis.disassemble(&mut default_output).unwrap();
+    assert!(!String::from_utf8(default_output).unwrap().contains("lbl_"));
+    let mut labeled_output = Vec::new();
+    analysis
+        .disassemble_with_jump_labels(&mut labeled_output)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(labeled_output).unwrap(),
+        "entrypoint:
+    mov64 r1, 0
+    jeq r2, 0, lbl_3
+    mov64 r1, 1
+lbl_3:
+    add64 r1, 1
+    jlt r1, 3, lbl_3
+    ja lbl_6
+lbl_6:
+    exit
+"
+    );
+}
+
+#[test]
+fn test_check_structure_trailing_lddw() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 0
+        call function_foo
+        exit
+        function_foo:
+        lddw r0, 0x1122334455667788",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.check_structure(),
+        Err(vec![
+            StructuralIssue::MissingExit(3),
+            StructuralIssue::FallthroughOffEnd(3),
+        ])
+    );
+}
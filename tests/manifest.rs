@@ -0,0 +1,171 @@
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#![cfg(feature = "serde")]
+
+extern crate solana_rbpf;
+use solana_rbpf::{
+    assembler::assemble,
+    error::EbpfError,
+    manifest::{Manifest, ManifestError, ManifestProgram},
+    syscalls,
+    user_error::UserError,
+    verifier::check,
+    vm::{Config, SyscallObject, SyscallRegistry, TestInstructionMeter},
+};
+
+fn syscall_registry() -> SyscallRegistry {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfTableLookup",
+            syscalls::BpfTableLookup::init::<u64, UserError>,
+            syscalls::BpfTableLookup::call,
+        )
+        .unwrap();
+    syscall_registry
+}
+
+fn bytecode(source: &str) -> Vec<u8> {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        source,
+        None,
+        Config::default(),
+        syscall_registry(),
+    )
+    .unwrap();
+    executable.get_text_bytes().1.to_vec()
+}
+
+const MANIFEST: &str = r#"{
+    "entrypoint": "main",
+    "functions": { "main": 0, "helper": 5 },
+    "syscalls": ["BpfTableLookup"],
+    "memory": { "heap_size": 64, "input_size": 8 }
+}"#;
+
+#[test]
+fn test_manifest_load() {
+    let bytecode = bytecode(
+        "
+        mov64 r2, 1
+        mov64 r3, 0
+        syscall BpfTableLookup
+        call helper
+        exit
+        helper:
+        add64 r0, 1
+        exit",
+    );
+    let manifest = Manifest::from_json(MANIFEST).unwrap();
+    assert_eq!(manifest.memory.heap_size, 64);
+    let mut program = ManifestProgram::<UserError, TestInstructionMeter>::load(
+        &bytecode,
+        &manifest,
+        Some(&check),
+        Config::default(),
+        syscall_registry(),
+    )
+    .unwrap();
+    assert_eq!(program.executable.get_text_bytes().1, bytecode.as_slice());
+    assert_eq!(
+        program.executable.get_entrypoint_instruction_offset(),
+        Ok(0)
+    );
+    assert_eq!(program.syscalls, vec!["BpfTableLookup".to_string()]);
+    assert_eq!(program.heap.len(), 64);
+    program
+        .input
+        .as_slice_mut()
+        .copy_from_slice(&41u64.to_le_bytes());
+    let mut vm = program.create_vm().unwrap();
+    vm.bind_syscall_context_objects(0).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 8 });
+    assert_eq!(result.unwrap(), 42);
+}
+
+#[test]
+fn test_manifest_undeclared_syscall() {
+    let bytecode = bytecode(
+        "
+        syscall BpfTableLookup
+        exit",
+    );
+    let manifest =
+        Manifest::from_json(r#"{ "entrypoint": "main", "functions": { "main": 0 } }"#).unwrap();
+    let result = ManifestProgram::<UserError, TestInstructionMeter>::load(
+        &bytecode,
+        &manifest,
+        Some(&check),
+        Config::default(),
+        syscall_registry(),
+    );
+    assert!(matches!(
+        result,
+        Err(EbpfError::ManifestError(ManifestError::UndeclaredSyscall(
+            _,
+            29
+        )))
+    ));
+}
+
+#[test]
+fn test_manifest_invalid() {
+    assert!(matches!(
+        Manifest::from_json(r#"{ "functions": {} }"#),
+        Err(ManifestError::InvalidManifest(_))
+    ));
+    let manifest = Manifest::from_json(r#"{ "entrypoint": "main" }"#).unwrap();
+    let result = ManifestProgram::<UserError, TestInstructionMeter>::load(
+        &bytecode("exit"),
+        &manifest,
+        None,
+        Config::default(),
+        syscall_registry(),
+    );
+    assert!(matches!(
+        result,
+        Err(EbpfError::ManifestError(ManifestError::EntrypointNotFound(
+            _
+        )))
+    ));
+}
+
+#[test]
+fn test_manifest_name_hasher() {
+    let syscall_registry = || {
+        let mut syscall_registry =
+            SyscallRegistry::with_name_hasher(|name| name.len() as u32 + 1000);
+        syscall_registry
+            .register_syscall_by_name(
+                b"BpfTableLookup",
+                syscalls::BpfTableLookup::init::<u64, UserError>,
+                syscalls::BpfTableLookup::call,
+            )
+            .unwrap();
+        syscall_registry
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        syscall BpfTableLookup
+        exit",
+        None,
+        Config::default(),
+        syscall_registry(),
+    )
+    .unwrap();
+    let manifest = Manifest::from_json(
+        r#"{ "entrypoint": "main", "functions": { "main": 0 }, "syscalls": ["BpfTableLookup"] }"#,
+    )
+    .unwrap();
+    let program = ManifestProgram::<UserError, TestInstructionMeter>::load(
+        executable.get_text_bytes().1,
+        &manifest,
+        Some(&check),
+        Config::default(),
+        syscall_registry(),
+    )
+    .unwrap();
+    assert_eq!(program.syscalls, vec!["BpfTableLookup".to_string()]);
+}
@@ -129,7 +129,7 @@ fn test_fuzz_execute() {
                 .unwrap();
             if let Ok(executable) = Executable::<UserError, TestInstructionMeter>::from_elf(
                 bytes,
-                Some(check),
+                Some(&check),
                 Config::default(),
                 syscall_registry,
             ) {
@@ -14,17 +14,31 @@ extern crate thiserror;
 use byteorder::{ByteOrder, LittleEndian};
 #[cfg(all(not(windows), target_arch = "x86_64"))]
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
+#[cfg(all(not(windows), target_arch = "x86_64"))]
+use solana_rbpf::vm::Divergence;
 use solana_rbpf::{
     assembler::assemble,
     ebpf,
-    elf::{register_bpf_function, ElfError, Executable},
+    elf::{self, register_bpf_function, ElfError, Executable},
     error::EbpfError,
-    memory_region::{AccessType, MemoryMapping, MemoryRegion},
+    memory_region::{AccessType, MemoryMapping, MemoryRegion, MemoryRegionsBuilder},
     syscalls::{self, BpfSyscallContext, Result},
     user_error::UserError,
-    vm::{Config, EbpfVm, SyscallObject, SyscallRegistry, TestInstructionMeter},
+    verifier::check,
+    vm::{
+        Config, DebugEvent, DebugState, EbpfVm, InstructionClass, InstructionObserver,
+        RegisterDiff, ShiftOverflowMode, SyscallAccesses, SyscallLog, SyscallObject,
+        SyscallRegistry, TestInstructionMeter,
+    },
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    pin::Pin,
+    rc::Rc,
 };
-use std::{collections::BTreeMap, fs::File, io::Read};
 use test_utils::{PROG_TCP_PORT_80, TCP_SACK_ASM, TCP_SACK_MATCH, TCP_SACK_NOMATCH};
 
 macro_rules! test_interpreter_and_jit {
@@ -99,7 +113,7 @@ macro_rules! test_interpreter_and_jit_asm {
         {
             let mut syscall_registry = SyscallRegistry::default();
             $(test_interpreter_and_jit!(register, syscall_registry, $location => $syscall_init; $syscall_function);)*
-            let mut executable = assemble($source, None, $config, syscall_registry).unwrap();
+            let mut executable = assemble($source, None, $config.clone(), syscall_registry).unwrap();
             test_interpreter_and_jit!(executable, $mem, $syscall_context, $check, $expected_instruction_count);
         }
     };
@@ -124,7 +138,7 @@ macro_rules! test_interpreter_and_jit_elf {
         {
             let mut syscall_registry = SyscallRegistry::default();
             $(test_interpreter_and_jit!(register, syscall_registry, $location => $syscall_init; $syscall_function);)*
-            let mut executable = Executable::<UserError, TestInstructionMeter>::from_elf(&elf, None, $config, syscall_registry).unwrap();
+            let mut executable = Executable::<UserError, TestInstructionMeter>::from_elf(&elf, None, $config.clone(), syscall_registry).unwrap();
             test_interpreter_and_jit!(executable, $mem, $syscall_context, $check, $expected_instruction_count);
         }
     };
@@ -500,6 +514,152 @@ fn test_arsh64() {
     );
 }
 
+#[test]
+fn test_arsh32_negative() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, -0x12345678
+        arsh32 r0, 1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (-0x12345678i32 >> 1) as u32 as u64 } },
+        3
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0xffffffff87654321
+        mov32 r1, 1
+        arsh32 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (0x87654321u32 as i32 >> 1) as u32 as u64 } },
+        4
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, -0x12345678
+        arsh32 r0, 7
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (-0x12345678i32 >> 7) as u32 as u64 } },
+        3
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0xffffffff87654321
+        mov32 r1, 7
+        arsh32 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (0x87654321u32 as i32 >> 7) as u32 as u64 } },
+        4
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, -0x12345678
+        arsh32 r0, 31
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (-0x12345678i32 >> 31) as u32 as u64 } },
+        3
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0xffffffff87654321
+        mov32 r1, 31
+        arsh32 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (0x87654321u32 as i32 >> 31) as u32 as u64 } },
+        4
+    );
+}
+
+#[test]
+fn test_arsh64_negative() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, -0x12345678
+        arsh r0, 1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (-0x12345678i64 >> 1) as u64 } },
+        3
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0x8123456789abcdef
+        mov r1, 1
+        arsh r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (0x8123456789abcdefu64 as i64 >> 1) as u64 } },
+        4
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, -0x12345678
+        arsh r0, 13
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (-0x12345678i64 >> 13) as u64 } },
+        3
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0x8123456789abcdef
+        mov r1, 13
+        arsh r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (0x8123456789abcdefu64 as i64 >> 13) as u64 } },
+        4
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, -0x12345678
+        arsh r0, 63
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (-0x12345678i64 >> 63) as u64 } },
+        3
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0x8123456789abcdef
+        mov r1, 63
+        arsh r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (0x8123456789abcdefu64 as i64 >> 63) as u64 } },
+        4
+    );
+}
+
 #[test]
 fn test_lsh64_reg() {
     test_interpreter_and_jit_asm!(
@@ -548,6 +708,166 @@ fn test_rsh64_reg() {
     );
 }
 
+#[test]
+fn test_lsh32_shift_amount_masking() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 1
+        lsh32 r0, 32
+        lsh32 r0, 100
+        mov32 r1, 64
+        lsh32 r0, r1
+        mov32 r1, 33
+        lsh32 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x20 } },
+        8
+    );
+}
+
+#[test]
+fn test_rsh32_shift_amount_masking() {
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0xff00000080000000
+        rsh32 r0, 32
+        rsh32 r0, 100
+        mov32 r1, 64
+        rsh32 r0, r1
+        mov32 r1, 33
+        rsh32 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x04000000 } },
+        8
+    );
+}
+
+#[test]
+fn test_arsh32_shift_amount_masking() {
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0x80000000
+        arsh32 r0, 32
+        arsh32 r0, 100
+        mov32 r1, 64
+        arsh32 r0, r1
+        mov32 r1, 33
+        arsh32 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0xfc000000 } },
+        8
+    );
+}
+
+#[test]
+fn test_lsh64_shift_amount_masking() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 1
+        lsh64 r0, 64
+        lsh64 r0, 100
+        mov32 r1, 128
+        lsh64 r0, r1
+        mov32 r1, 65
+        lsh64 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x2000000000 } },
+        8
+    );
+}
+
+#[test]
+fn test_rsh64_shift_amount_masking() {
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0x8000000000000000
+        rsh64 r0, 64
+        rsh64 r0, 100
+        mov32 r1, 128
+        rsh64 r0, r1
+        mov32 r1, 65
+        rsh64 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x4000000 } },
+        8
+    );
+}
+
+#[test]
+fn test_arsh64_shift_amount_masking() {
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0x8000000000000000
+        arsh64 r0, 64
+        arsh64 r0, 100
+        mov32 r1, 128
+        arsh64 r0, r1
+        mov32 r1, 65
+        arsh64 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0xfffffffffc000000 } },
+        8
+    );
+}
+
+#[test]
+fn test_shift_overflow_mode() {
+    for (source, masked, saturated) in [
+        ("mov32 r0, 5\n lsh32 r0, 32\n exit", 5, 0),
+        ("mov32 r0, 5\n mov32 r1, 32\n rsh32 r0, r1\n exit", 5, 0),
+        (
+            "mov32 r0, -8\n arsh32 r0, 32\n exit",
+            0xfffffff8,
+            0xffffffff,
+        ),
+        ("mov r0, 5\n lsh r0, 64\n exit", 5, 0),
+        ("mov r0, 5\n mov r1, 64\n rsh r0, r1\n exit", 5, 0),
+        ("mov r0, -8\n arsh r0, 64\n exit", -8i64 as u64, u64::MAX),
+    ] {
+        for (shift_overflow_mode, expected) in [
+            (ShiftOverflowMode::Mask, masked),
+            (ShiftOverflowMode::Saturate, saturated),
+        ] {
+            let config = Config {
+                shift_overflow_mode,
+                ..Config::default()
+            };
+            test_interpreter_and_jit_asm!(
+                source,
+                config,
+                [],
+                (),
+                0,
+                {
+                    |_vm, res: Result| {
+                        matches!(res, Ok(value) if value == expected)
+                            || matches!(res, Err(EbpfError::UnsupportedJitOption(_)))
+                    }
+                },
+                source.lines().count() as u64
+            );
+        }
+    }
+}
+
 #[test]
 fn test_be16() {
     test_interpreter_and_jit_asm!(
@@ -1500,7 +1820,7 @@ fn test_err_ldxdw_oob() {
         {
             |_vm, res: Result| {
                 matches!(res.unwrap_err(),
-                    EbpfError::AccessViolation(pc, access_type, vm_addr, len, name)
+                    EbpfError::RegionStraddleViolation(pc, access_type, vm_addr, len, name)
                     if access_type == AccessType::Load && pc == 29 && vm_addr == 0x400000006 && len == 8 && name == "input"
                 )
             }
@@ -1510,62 +1830,1304 @@ fn test_err_ldxdw_oob() {
 }
 
 #[test]
-fn test_ldxb_all() {
+fn test_err_ldxdw_oob_keeps_trace() {
     test_interpreter_and_jit_asm!(
         "
-        mov r0, r1
-        ldxb r9, [r0+0]
-        lsh r9, 0
-        ldxb r8, [r0+1]
-        lsh r8, 4
-        ldxb r7, [r0+2]
-        lsh r7, 8
-        ldxb r6, [r0+3]
-        lsh r6, 12
-        ldxb r5, [r0+4]
-        lsh r5, 16
-        ldxb r4, [r0+5]
-        lsh r4, 20
-        ldxb r3, [r0+6]
-        lsh r3, 24
-        ldxb r2, [r0+7]
-        lsh r2, 28
-        ldxb r1, [r0+8]
-        lsh r1, 32
-        ldxb r0, [r0+9]
-        lsh r0, 36
-        or r0, r1
-        or r0, r2
-        or r0, r3
-        or r0, r4
-        or r0, r5
-        or r0, r6
-        or r0, r7
-        or r0, r8
-        or r0, r9
+        mov64 r2, 0x11
+        stxb [r1+1], r2
+        ldxdw r0, [r1+6]
         exit",
         [
-            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, //
-            0x08, 0x09, //
+            0xaa, 0xbb, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, //
+            0x77, 0x88, 0xcc, 0xdd, //
         ],
         (),
         0,
-        { |_vm, res: Result| { res.unwrap() == 0x9876543210 } },
-        31
+        {
+            |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
+                let log = &vm.get_tracer().log;
+                matches!(res.unwrap_err(), EbpfError::RegionStraddleViolation(pc, ..) if pc == 31)
+                    && log.len() == 3
+                    && log[1][2] == 0x11
+                    && log[2][11] == 2
+            }
+        },
+        3
     );
 }
 
 #[test]
-fn test_ldxh_all() {
-    test_interpreter_and_jit_asm!(
+fn test_tracer_pc_window() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
         "
-        mov r0, r1
-        ldxh r9, [r0+0]
-        be16 r9
-        lsh r9, 0
-        ldxh r8, [r0+2]
-        be16 r8
-        lsh r8, 4
+        mov64 r0, 0
+        mov64 r1, 3
+        add64 r0, r1
+        sub64 r1, 1
+        jne r1, 0, -3
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let check = |tracer: &solana_rbpf::vm::Tracer| {
+        assert_eq!(tracer.log.len(), 6);
+        assert!(tracer.log.iter().all(|entry| (2..=3).contains(&entry[11])));
+    };
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        vm.get_tracer_mut().pc_window = Some((2, 3));
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 12 });
+        assert_eq!(result.unwrap(), 6);
+        check(vm.get_tracer());
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        vm.get_tracer_mut().pc_window = Some((2, 3));
+        let result = vm.execute_program_jit(&mut TestInstructionMeter { remaining: 12 });
+        assert_eq!(result.unwrap(), 6);
+        check(vm.get_tracer());
+    }
+}
+
+#[test]
+fn test_instruction_observer() {
+    struct PcRecorder(Rc<RefCell<Vec<usize>>>);
+    impl InstructionObserver for PcRecorder {
+        fn before(&mut self, pc: usize, _opcode: u8, _registers: &[u64; 11]) {
+            self.0.borrow_mut().push(pc);
+        }
+    }
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r0, 0
+        mov64 r1, 2
+        add64 r0, r1
+        sub64 r1, 1
+        jne r1, 0, -3
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let pcs = Rc::new(RefCell::new(Vec::new()));
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        vm.set_instruction_observer(Some(Box::new(PcRecorder(pcs.clone()))));
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 9 });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(*pcs.borrow(), vec![0, 1, 2, 3, 4, 2, 3, 4, 5]);
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        vm.set_instruction_observer(Some(Box::new(PcRecorder(pcs.clone()))));
+        assert_eq!(
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 9 }),
+            Err(EbpfError::UnsupportedJitOption("instruction observers"))
+        );
+    }
+}
+
+#[test]
+fn test_register_diff() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r0, 0
+        mov64 r1, 3
+        add64 r0, 5
+        sub64 r1, 1
+        jne r1, 0, -3
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let register_diff = Rc::new(RefCell::new(RegisterDiff::new(2, 4)));
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.set_instruction_observer(Some(Box::new(register_diff.clone())));
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 12 });
+    assert_eq!(result.unwrap(), 15);
+    let mut delta = [0; 11];
+    delta[0] = 5;
+    delta[1] = -1;
+    assert_eq!(register_diff.borrow().deltas, vec![delta; 3]);
+}
+
+#[test]
+fn test_store_high_water_marks() {
+    let config = Config {
+        track_store_high_water_marks: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r1, 0x3
+        lsh64 r1, 0x20
+        stb [r1+100], 1
+        stb [r1+20], 1
+        ldxb r0, [r1+200]
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    {
+        let mut heap = vec![0u8; 4096];
+        let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 6 });
+        assert_eq!(result.unwrap(), 0);
+        let heap_index = (ebpf::MM_HEAP_START >> 32) as usize;
+        assert_eq!(vm.get_store_high_water_marks()[heap_index], Some(100));
+        assert_eq!(
+            vm.get_store_high_water_marks()[(ebpf::MM_STACK_START >> 32) as usize],
+            None
+        );
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    assert_eq!(
+        Executable::jit_compile(&mut executable),
+        Err(EbpfError::UnsupportedJitOption(
+            "Config::track_store_high_water_marks"
+        ))
+    );
+}
+
+#[test]
+fn test_execute_with_budget() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        add r0, 1
+        add r0, 1
+        add r0, 1
+        add r0, 1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 1000 };
+    let result = vm.execute_program_interpreted_with_budget(&mut instruction_meter, 6);
+    assert_eq!(result.unwrap(), 5);
+    assert_eq!(vm.get_total_instruction_count(), 6);
+    assert_eq!(instruction_meter.remaining, 994);
+    let result = vm.execute_program_interpreted_with_budget(&mut instruction_meter, 3);
+    assert!(matches!(
+        result.unwrap_err(),
+        EbpfError::ExceededMaxInstructions(32, 3)
+    ));
+    assert_eq!(vm.get_total_instruction_count(), 3);
+    assert_eq!(instruction_meter.remaining, 991);
+}
+
+#[test]
+fn test_ring_region() {
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        stb [r1+8], 42
+        stb [r1+17], 7
+        ldxb r0, [r1]
+        ldxb r2, [r1+1]
+        lsh64 r0, 8
+        or64 r0, r2
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut mem = [0u8; 8];
+    {
+        let mem_region = MemoryRegion::new_writable_ring(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 7 });
+        assert_eq!(result.unwrap(), 0x2a07);
+    }
+    assert_eq!(mem, [42, 7, 0, 0, 0, 0, 0, 0]);
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mem_region = MemoryRegion::new_writable_ring(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        assert_eq!(
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 7 }),
+            Err(EbpfError::UnsupportedJitOption("ring buffer regions"))
+        );
+    }
+}
+
+#[test]
+fn test_cow_region() {
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r0, [r1+1]
+        stb [r1+1], 42
+        ldxb r2, [r1+1]
+        lsh64 r0, 8
+        or64 r0, r2
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mem = [1u8, 2, 3, 4];
+    for _ in 0..2 {
+        let mem_region = MemoryRegion::new_cow(&mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 6 });
+        assert_eq!(result.unwrap(), 0x022a);
+    }
+    assert_eq!(mem, [1, 2, 3, 4]);
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mem_region = MemoryRegion::new_cow(&mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        assert_eq!(
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 6 }),
+            Err(EbpfError::UnsupportedJitOption("copy-on-write regions"))
+        );
+    }
+}
+
+#[test]
+fn test_trace_syscall_accesses() {
+    let config = Config {
+        trace_syscall_accesses: true,
+        ..Config::default()
+    };
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfMemCpy",
+            syscalls::BpfMemCpy::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfMemCpy::call,
+        )
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r6, r1
+        add r1, 4
+        mov r2, r6
+        mov r3, 4
+        syscall BpfMemCpy
+        ldxw r0, [r6+4]
+        exit",
+        None,
+        config,
+        syscall_registry,
+    )
+    .unwrap();
+    let mut mem = [1u8, 2, 3, 4, 0, 0, 0, 0];
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    vm.bind_syscall_context_objects(0).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 7 });
+    assert_eq!(result.unwrap(), 0x04030201);
+    assert_eq!(
+        vm.get_syscall_accesses(),
+        [SyscallAccesses {
+            pc: 4,
+            hash: ebpf::hash_symbol_name(b"BpfMemCpy"),
+            accesses: vec![
+                (AccessType::Store, ebpf::MM_INPUT_START + 4, 4),
+                (AccessType::Load, ebpf::MM_INPUT_START, 4),
+            ],
+        }]
+    );
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        drop(vm);
+        assert_eq!(
+            Executable::jit_compile(&mut executable),
+            Err(EbpfError::UnsupportedJitOption(
+                "Config::trace_syscall_accesses"
+            ))
+        );
+    }
+}
+
+#[test]
+fn test_region_access_counts() {
+    let config = Config {
+        count_region_accesses: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        stdw [r10-8], 0
+        mov64 r3, 10
+        ldxdw r0, [r10-8]
+        sub64 r3, 1
+        jne r3, 0, -3
+        mov64 r2, 0x3
+        lsh64 r2, 0x20
+        stb [r2], 1
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    {
+        let mut heap = vec![0u8; 4096];
+        let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 36 });
+        assert_eq!(result.unwrap(), 0);
+        let counts = vm.get_region_access_counts();
+        assert_eq!(counts[(ebpf::MM_STACK_START >> 32) as usize], (10, 1));
+        assert_eq!(counts[(ebpf::MM_HEAP_START >> 32) as usize], (0, 1));
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    assert_eq!(
+        Executable::jit_compile(&mut executable),
+        Err(EbpfError::UnsupportedJitOption(
+            "Config::count_region_accesses"
+        ))
+    );
+}
+
+#[test]
+fn test_context_read_offsets() {
+    let config = Config {
+        track_context_reads: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r0, [r1]
+        ldxb r2, [r1+8]
+        stb [r1+4], 1
+        stb [r10-1], 2
+        ldxb r3, [r10-1]
+        add r0, r2
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    {
+        let mut mem = [1u8; 16];
+        let mut vm = EbpfVm::new(
+            &executable,
+            &mut [],
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        )
+        .unwrap();
+        for _ in 0..2 {
+            let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 7 });
+            assert_eq!(result.unwrap(), 2);
+            assert_eq!(vm.get_context_read_offsets(), vec![0, 8]);
+        }
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    assert_eq!(
+        Executable::jit_compile(&mut executable),
+        Err(EbpfError::UnsupportedJitOption(
+            "Config::track_context_reads"
+        ))
+    );
+}
+
+#[test]
+fn test_trap_uninitialized_heap_reads() {
+    let config = Config {
+        trap_uninitialized_heap_reads: true,
+        ..Config::default()
+    };
+    let run = |source: &str| {
+        let executable = assemble::<UserError, TestInstructionMeter>(
+            source,
+            None,
+            config.clone(),
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        let mut heap = [0u8; 16];
+        let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 10 })
+    };
+    assert!(matches!(
+        run("
+        lddw r1, 0x300000000
+        ldxdw r0, [r1]
+        exit"),
+        Err(EbpfError::UninitializedRead(31, 0x300000000))
+    ));
+    assert_eq!(
+        run("
+        lddw r1, 0x300000000
+        stw [r1], 7
+        ldxw r0, [r1]
+        exit")
+        .unwrap(),
+        7
+    );
+    assert!(matches!(
+        run("
+        lddw r1, 0x300000000
+        stw [r1], 7
+        ldxdw r0, [r1]
+        exit"),
+        Err(EbpfError::UninitializedRead(32, 0x300000004))
+    ));
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        let mut executable = assemble::<UserError, TestInstructionMeter>(
+            "exit",
+            None,
+            config,
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            Executable::jit_compile(&mut executable),
+            Err(EbpfError::UnsupportedJitOption(
+                "Config::trap_uninitialized_heap_reads"
+            ))
+        );
+    }
+}
+
+#[test]
+fn test_trap_uninitialized_heap_reads_after_syscall_store() {
+    let config = Config {
+        trap_uninitialized_heap_reads: true,
+        ..Config::default()
+    };
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfMemSet",
+            syscalls::BpfMemSet::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfMemSet::call,
+        )
+        .unwrap();
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        lddw r1, 0x300000000
+        mov r2, 0
+        mov r3, 8
+        syscall BpfMemSet
+        lddw r1, 0x300000000
+        ldxdw r0, [r1]
+        exit",
+        None,
+        config,
+        syscall_registry,
+    )
+    .unwrap();
+    let mut heap = [0u8; 16];
+    let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+    vm.bind_syscall_context_objects(0).unwrap();
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 10 })
+            .unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_max_memory_accesses() {
+    let config = Config {
+        max_memory_accesses: Some(5),
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 0
+        mov r2, 10
+        ldxb r3, [r1]
+        add r0, r3
+        sub r2, 1
+        jne r2, 0, -4
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    {
+        let mut mem = [1u8; 1];
+        let mut vm = EbpfVm::new(
+            &executable,
+            &mut [],
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        )
+        .unwrap();
+        for _ in 0..2 {
+            let result =
+                vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 });
+            assert!(matches!(
+                result,
+                Err(EbpfError::MemoryAccessQuotaExceeded(31, 5))
+            ));
+            assert_eq!(vm.get_total_instruction_count(), 23);
+        }
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    assert_eq!(
+        Executable::jit_compile(&mut executable),
+        Err(EbpfError::UnsupportedJitOption(
+            "Config::max_memory_accesses"
+        ))
+    );
+}
+
+#[test]
+fn test_branch_edges() {
+    let config = Config {
+        track_branch_edges: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        jne r1, 0, +2
+        mov r0, 1
+        ja +1
+        mov r0, 2
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        // r1 points to the input region, so only the else arm is taken
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 3 });
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(
+            vm.get_branch_edges().iter().copied().collect::<Vec<_>>(),
+            vec![(0, 3)]
+        );
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    assert_eq!(
+        Executable::jit_compile(&mut executable),
+        Err(EbpfError::UnsupportedJitOption(
+            "Config::track_branch_edges"
+        ))
+    );
+}
+
+#[test]
+fn test_run_batch() {
+    // Sums the bytes following the length byte
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r2, [r1]
+        mov r0, 0
+        jeq r2, 0, +5
+        add r1, 1
+        ldxb r3, [r1]
+        add r0, r3
+        sub r2, 1
+        ja -6
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let inputs: [&[u8]; 6] = [&[3, 1, 2, 3], &[0], &[2, 200, 100], &[5, 1], &[], &[1, 9]];
+    let check = |results: Vec<Result>| {
+        assert_eq!(results.len(), inputs.len());
+        assert_eq!(results[0], Ok(6));
+        assert_eq!(results[1], Ok(0));
+        assert_eq!(results[2], Ok(300));
+        assert!(matches!(
+            results[3],
+            Err(EbpfError::AccessViolation(
+                33,
+                AccessType::Load,
+                0x400000002,
+                1,
+                "input"
+            ))
+        ));
+        assert!(matches!(
+            results[4],
+            Err(EbpfError::AccessViolation(
+                29,
+                AccessType::Load,
+                0x400000000,
+                1,
+                "input"
+            ))
+        ));
+        assert_eq!(results[5], Ok(9));
+    };
+    check(
+        Executable::run_batch(&executable, &inputs, &mut [0u8; 16], 0, || {
+            TestInstructionMeter { remaining: 1000 }
+        })
+        .unwrap(),
+    );
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        check(
+            Executable::run_batch(&executable, &inputs, &mut [0u8; 16], 0, || {
+                TestInstructionMeter { remaining: 1000 }
+            })
+            .unwrap(),
+        );
+    }
+}
+
+#[test]
+fn test_run_batch_isolates_runs() {
+    // Stores to the stack if the input starts with 1, otherwise loads what is there
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r2, [r1]
+        mov r0, 0
+        jeq r2, 0, +2
+        stb [r10-1], 42
+        exit
+        ldxb r0, [r10-1]
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let inputs: [&[u8]; 4] = [&[1], &[0], &[1], &[0]];
+    let check = |results: Vec<Result>| {
+        assert_eq!(results, vec![Ok(0), Ok(0), Ok(0), Ok(0)]);
+    };
+    check(
+        Executable::run_batch(&executable, &inputs, &mut [], 0, || TestInstructionMeter {
+            remaining: 5,
+        })
+        .unwrap(),
+    );
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        check(
+            Executable::run_batch(&executable, &inputs, &mut [], 0, || TestInstructionMeter {
+                remaining: 5,
+            })
+            .unwrap(),
+        );
+    }
+}
+
+#[test]
+fn test_execute_program_with_remaining() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 3
+        mov r1, 4
+        add r0, r1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let initial = 10;
+    let (result, remaining) =
+        vm.execute_program_with_remaining(&mut TestInstructionMeter { remaining: initial });
+    assert_eq!(result, Ok(7));
+    assert_eq!(remaining, initial - 4);
+    assert_eq!(remaining, initial - vm.get_total_instruction_count());
+
+    let (result, remaining) =
+        vm.execute_program_with_remaining(&mut TestInstructionMeter { remaining: 2 });
+    assert!(matches!(
+        result,
+        Err(EbpfError::ExceededMaxInstructions(_, 2))
+    ));
+    assert_eq!(remaining, 0);
+}
+
+#[test]
+fn test_execute_program_fallback() {
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        ldxb r2, [r1+2]
+        lsh r0, r2
+        add r0, 3
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let run = |executable: &Pin<Box<Executable<UserError, TestInstructionMeter>>>, interpreted| {
+        let mut mem = [0, 1, 5, 7];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(executable, &mut [], vec![mem_region]).unwrap();
+        let mut instruction_meter = TestInstructionMeter { remaining: 5 };
+        let result = if interpreted {
+            vm.execute_program_interpreted(&mut instruction_meter)
+        } else {
+            vm.execute_program(&mut instruction_meter)
+        };
+        (result, instruction_meter.remaining)
+    };
+    assert_eq!(run(&executable, true), (Ok(35), 0));
+    assert_eq!(run(&executable, false), run(&executable, true));
+    if !Executable::<UserError, TestInstructionMeter>::is_jit_supported() {
+        assert_eq!(
+            Executable::jit_compile(&mut executable),
+            Err(EbpfError::JitNotSupported)
+        );
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        assert!(Executable::<UserError, TestInstructionMeter>::is_jit_supported());
+        Executable::jit_compile(&mut executable).unwrap();
+        assert_eq!(run(&executable, false), run(&executable, true));
+    }
+}
+
+#[test]
+fn test_max_footprint_bytes() {
+    let mut config = Config::default();
+    let footprint = ebpf::INSN_SIZE as u64 + config.stack_size() as u64 + 64;
+    config.max_footprint_bytes = Some(footprint);
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut heap = [0u8; 64];
+    assert!(EbpfVm::new(&executable, &mut heap[..63], Vec::new()).is_ok());
+    assert!(EbpfVm::new(&executable, &mut heap, Vec::new()).is_ok());
+    let mut input = [0u8; 1];
+    assert_eq!(
+        EbpfVm::new(
+            &executable,
+            &mut heap,
+            vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)]
+        )
+        .err(),
+        Some(EbpfError::FootprintExceeded(footprint + 1, footprint))
+    );
+}
+
+#[test]
+fn test_region_access_log() {
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r2, [r1]
+        ldxh r3, [r1+1]
+        stw [r10-4], 0
+        add64 r1, 3
+        stxb [r1], r2
+        ldxdw r0, [r1-3]
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    {
+        let mut mem = [0x11u8, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+        let mut vm = EbpfVm::new(
+            &executable,
+            &mut [],
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        )
+        .unwrap();
+        assert_eq!(
+            vm.watch_region(ebpf::MM_INPUT_START + 1),
+            Err(EbpfError::InvalidMemoryRegion(4))
+        );
+        vm.watch_region(ebpf::MM_INPUT_START).unwrap();
+        assert!(vm.get_region_access_log(ebpf::MM_STACK_START).is_none());
+        for _ in 0..2 {
+            let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 7 });
+            assert_eq!(result.unwrap(), 0x8877665511332211);
+            assert_eq!(
+                vm.get_region_access_log(ebpf::MM_INPUT_START).unwrap(),
+                &[
+                    (0, 0, 1, AccessType::Load),
+                    (1, 1, 2, AccessType::Load),
+                    (4, 3, 1, AccessType::Store),
+                    (5, 0, 8, AccessType::Load),
+                ]
+            );
+        }
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        vm.watch_region(ebpf::MM_STACK_START).unwrap();
+        assert_eq!(
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 7 }),
+            Err(EbpfError::UnsupportedJitOption("watched regions"))
+        );
+    }
+}
+
+#[test]
+fn test_tracer_binary_roundtrip() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r0, 0
+        mov64 r1, 3
+        add64 r0, r1
+        sub64 r1, 1
+        jne r1, 0, -3
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 14 });
+    assert_eq!(result.unwrap(), 6);
+    let tracer = vm.get_tracer();
+    let mut bytes = Vec::new();
+    tracer.write_binary(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), tracer.log.len() * 12 * 8);
+    let read_back = solana_rbpf::vm::Tracer::read_binary(&mut bytes.as_slice()).unwrap();
+    assert_eq!(read_back.log, tracer.log);
+    assert!(solana_rbpf::vm::Tracer::read_binary(&mut &bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_tracer_first_divergence() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r1, [r1]
+        mov64 r0, 0
+        add64 r0, r1
+        jeq r1, 1, +1
+        add64 r0, 1
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let trace = |input: u8| {
+        let mut mem = [input];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 6 })
+            .unwrap();
+        vm.get_tracer().clone()
+    };
+    let tracer = trace(1);
+    assert_eq!(tracer.first_divergence(&trace(1)), None);
+    // r1 is loaded by the first instruction, so the register files differ from the second entry
+    assert_eq!(tracer.first_divergence(&trace(2)), Some(1));
+    let mut truncated = tracer.clone();
+    truncated.log.pop();
+    assert_eq!(
+        tracer.first_divergence(&truncated),
+        Some(tracer.log.len() - 1)
+    );
+    assert_eq!(
+        truncated.first_divergence(&tracer),
+        Some(tracer.log.len() - 1)
+    );
+
+    let analysis = solana_rbpf::static_analysis::Analysis::from_executable(&executable).unwrap();
+    let mut output: Vec<u8> = Vec::new();
+    let writer: &mut dyn std::io::Write = &mut output;
+    tracer.write(writer, &analysis).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap().lines().count(),
+        tracer.log.len()
+    );
+
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+        let mut mem = [1u8];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        vm.execute_program_jit(&mut TestInstructionMeter { remaining: 6 })
+            .unwrap();
+        assert_eq!(tracer.first_divergence(vm.get_tracer()), None);
+    }
+}
+
+#[test]
+fn test_constants_region() {
+    let constants = [
+        0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x00, //
+    ];
+    let mut input = [0u8; 8];
+    let mut regions = MemoryRegionsBuilder::new();
+    regions.push(MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START));
+    let constants_vm_addr = regions.push_constants(&constants);
+    let regions = regions.build();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        &format!(
+            "
+            lddw r2, {:#x}
+            ldxdw r0, [r2+8]
+            exit",
+            constants_vm_addr
+        ),
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], regions.clone()).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 3 });
+        assert_eq!(result.unwrap(), 0x00ffeeddccbbaa99);
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mut vm = EbpfVm::new(&executable, &mut [], regions).unwrap();
+        let result = vm.execute_program_jit(&mut TestInstructionMeter { remaining: 3 });
+        assert_eq!(result.unwrap(), 0x00ffeeddccbbaa99);
+    }
+}
+
+#[test]
+fn test_output_region() {
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        lddw r2, 0x500000000
+        lddw r3, 0x1122334455667788
+        stxdw [r2], r3
+        stdw [r2+8], 42
+        mov64 r0, 0
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let run = |executable: &Pin<Box<Executable<UserError, TestInstructionMeter>>>, jit: bool| {
+        let mut output = [0u8; 16];
+        let mut vm = EbpfVm::new(
+            executable,
+            &mut [],
+            vec![
+                MemoryRegion::new_writable(&mut [], ebpf::MM_INPUT_START),
+                MemoryRegion::new_writable(&mut output, 0x500000000),
+            ],
+        )
+        .unwrap();
+        vm.set_output_region(0x500000000).unwrap();
+        let result = if jit {
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 6 })
+        } else {
+            vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 6 })
+        };
+        assert_eq!(result.unwrap(), 0);
+        let output = vm.take_output();
+        assert_eq!(output.len(), 16);
+        assert_eq!(LittleEndian::read_u64(&output[0..8]), 0x1122334455667788);
+        assert_eq!(LittleEndian::read_u64(&output[8..16]), 42);
+    };
+    run(&executable, false);
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        run(&executable, true);
+    }
+}
+
+#[test]
+fn test_err_output_region_not_writable() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(
+        &executable,
+        &mut [],
+        vec![MemoryRegion::new_readonly(&[0u8; 8], ebpf::MM_INPUT_START)],
+    )
+    .unwrap();
+    assert!(vm.take_output().is_empty());
+    assert_eq!(
+        vm.set_output_region(ebpf::MM_INPUT_START),
+        Err(EbpfError::InvalidMemoryRegion(4))
+    );
+    assert_eq!(
+        vm.set_output_region(0x500000000),
+        Err(EbpfError::InvalidMemoryRegion(5))
+    );
+}
+
+#[test]
+fn test_err_context_write() {
+    test_interpreter_and_jit_asm!(
+        "
+        ldxb r0, [r1]
+        stb [r1+1], 0
+        exit",
+        {
+            Config {
+                reject_context_writes: true,
+                ..Config::default()
+            }
+        },
+        [0x11, 0x22],
+        (),
+        0,
+        {
+            |_vm, res: Result| {
+                matches!(res.unwrap_err(),
+                    EbpfError::ContextWriteViolation(pc) if pc == 30
+                )
+            }
+        },
+        2
+    );
+}
+
+#[test]
+fn test_context_write_allowed_by_default() {
+    test_interpreter_and_jit_asm!(
+        "
+        stb [r1+1], 0x33
+        ldxb r0, [r1+1]
+        exit",
+        [0x11, 0x22],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x33 } },
+        3
+    );
+}
+
+#[test]
+fn test_patch_instruction() {
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r0, 1
+        lddw r1, 0x1122334455667788
+        exit",
+        Some(&check),
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mov = ebpf::Insn {
+        ptr: 0,
+        opc: ebpf::MOV64_IMM,
+        dst: 0,
+        src: 0,
+        off: 0,
+        imm: 42,
+    };
+    executable
+        .patch_instruction(0, &mov.to_array(), Some(&check))
+        .unwrap();
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 3 });
+        assert_eq!(result.unwrap(), 42);
+    }
+    // Both slots of the lddw are rejected, as is out of bounds
+    for pc in [1, 2] {
+        assert_eq!(
+            executable.patch_instruction(pc, &mov.to_array(), Some(&check)),
+            Err(EbpfError::InvalidInstruction(
+                pc + ebpf::ELF_INSN_DUMP_OFFSET
+            ))
+        );
+    }
+    assert_eq!(
+        executable.patch_instruction(4, &mov.to_array(), Some(&check)),
+        Err(EbpfError::ElfError(ElfError::ValueOutOfBounds))
+    );
+    // A patch which fails verification is reverted
+    let invalid = ebpf::Insn {
+        dst: 11,
+        ..mov.clone()
+    };
+    assert!(matches!(
+        executable.patch_instruction(0, &invalid.to_array(), Some(&check)),
+        Err(EbpfError::VerifierError(_))
+    ));
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 3 });
+        assert_eq!(result.unwrap(), 42);
+    }
+    // Without a verifier the patch is applied unchecked
+    let write_r10 = ebpf::Insn {
+        dst: 10,
+        ..mov.clone()
+    };
+    assert!(matches!(
+        executable.patch_instruction(0, &write_r10.to_array(), Some(&check)),
+        Err(EbpfError::VerifierError(_))
+    ));
+    executable
+        .patch_instruction(0, &write_r10.to_array(), None)
+        .unwrap();
+    assert_eq!(ebpf::get_insn(executable.get_text_bytes().1, 0).dst, 10);
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 3 });
+    assert_eq!(result.unwrap(), 0);
+}
+
+#[test]
+fn test_patch_instruction_call() {
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r0, 1
+        exit
+        mov64 r0, 7
+        exit",
+        Some(&check),
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let call = ebpf::Insn {
+        ptr: 0,
+        opc: ebpf::CALL_IMM,
+        dst: 0,
+        src: 1,
+        off: 0,
+        imm: 1,
+    };
+    // The relative call is resolved to the function at pc 2
+    executable
+        .patch_instruction(0, &call.to_array(), Some(&check))
+        .unwrap();
+    assert_eq!(
+        ebpf::get_insn(executable.get_text_bytes().1, 0).imm as u32,
+        elf::hash_bpf_function(2, "function_2")
+    );
+    assert_eq!(
+        executable.lookup_bpf_function(elf::hash_bpf_function(2, "function_2")),
+        Some(2)
+    );
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 4 });
+        assert_eq!(result.unwrap(), 7);
+    }
+    // A call out of bounds is rejected
+    let call = ebpf::Insn { imm: 9, ..call };
+    assert_eq!(
+        executable.patch_instruction(1, &call.to_array(), Some(&check)),
+        Err(EbpfError::ElfError(ElfError::RelativeJumpOutOfBounds(
+            1 + ebpf::ELF_INSN_DUMP_OFFSET
+        )))
+    );
+}
+
+#[test]
+fn test_ldxb_all() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, r1
+        ldxb r9, [r0+0]
+        lsh r9, 0
+        ldxb r8, [r0+1]
+        lsh r8, 4
+        ldxb r7, [r0+2]
+        lsh r7, 8
+        ldxb r6, [r0+3]
+        lsh r6, 12
+        ldxb r5, [r0+4]
+        lsh r5, 16
+        ldxb r4, [r0+5]
+        lsh r4, 20
+        ldxb r3, [r0+6]
+        lsh r3, 24
+        ldxb r2, [r0+7]
+        lsh r2, 28
+        ldxb r1, [r0+8]
+        lsh r1, 32
+        ldxb r0, [r0+9]
+        lsh r0, 36
+        or r0, r1
+        or r0, r2
+        or r0, r3
+        or r0, r4
+        or r0, r5
+        or r0, r6
+        or r0, r7
+        or r0, r8
+        or r0, r9
+        exit",
+        [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, //
+            0x08, 0x09, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x9876543210 } },
+        31
+    );
+}
+
+#[test]
+fn test_ldxh_all() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, r1
+        ldxh r9, [r0+0]
+        be16 r9
+        lsh r9, 0
+        ldxh r8, [r0+2]
+        be16 r8
+        lsh r8, 4
         ldxh r7, [r0+4]
         be16 r7
         lsh r7, 8
@@ -1992,6 +3554,83 @@ fn test_exit() {
     );
 }
 
+#[test]
+fn test_nonzero_exit_is_failure() {
+    for nonzero_exit_is_failure in [false, true] {
+        let config = Config {
+            enable_instruction_tracing: true,
+            nonzero_exit_is_failure,
+            ..Config::default()
+        };
+        test_interpreter_and_jit_asm!(
+            "
+            mov r0, 7
+            exit",
+            config,
+            [],
+            (),
+            0,
+            {
+                |_vm, res: Result| {
+                    if nonzero_exit_is_failure {
+                        matches!(res.unwrap_err(), EbpfError::ProgramFailed(7))
+                    } else {
+                        res.unwrap() == 7
+                    }
+                }
+            },
+            2
+        );
+    }
+    let config = Config {
+        enable_instruction_tracing: true,
+        nonzero_exit_is_failure: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 0
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0 } },
+        2
+    );
+}
+
+#[test]
+fn test_allowed_exit_codes() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        allowed_exit_codes: Some([0, 7].into()),
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 7
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 7 } },
+        2
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 8
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { matches!(res.unwrap_err(), EbpfError::InvalidExitCode(8)) } },
+        2
+    );
+}
+
 #[test]
 fn test_early_exit() {
     test_interpreter_and_jit_asm!(
@@ -2488,6 +4127,103 @@ fn test_jslt_reg() {
     );
 }
 
+#[test]
+fn test_initial_registers() {
+    let config = Config {
+        dynamic_stack_frames: false,
+        ..Config::default()
+    };
+    let initial_registers = config.initial_registers();
+    assert_eq!(initial_registers[1], ebpf::MM_INPUT_START);
+    assert_eq!(
+        initial_registers[10],
+        ebpf::MM_STACK_START + config.stack_frame_size as u64
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, r10
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == config.initial_registers()[10] } },
+        2
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, r6
+        or r0, r7
+        or r0, r8
+        or r0, r9
+        or r0, r2
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0 } },
+        6
+    );
+    let config = Config {
+        dynamic_stack_frames: true,
+        ..Config::default()
+    };
+    assert_eq!(
+        config.initial_registers()[10],
+        ebpf::MM_STACK_START + config.stack_size() as u64
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, r10
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == config.initial_registers()[10] } },
+        2
+    );
+}
+
+#[test]
+fn test_initial_registers_after_fault_in_callee() {
+    let config = Config {
+        dynamic_stack_frames: false,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r2, [r1]
+        stb [r1], 0
+        jeq r2, 0, +1
+        call function_foo
+        mov r0, r10
+        exit
+        function_foo:
+        ldxdw r0, [r0]
+        exit",
+        None,
+        config.clone(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut mem = [1u8];
+    let mut vm = EbpfVm::new(
+        &executable,
+        &mut [],
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+    )
+    .unwrap();
+    assert!(matches!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 10 }),
+        Err(EbpfError::AccessViolation(..))
+    ));
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 10 }),
+        Ok(config.initial_registers()[10])
+    );
+}
+
 // Call Stack
 
 #[test]
@@ -2807,6 +4543,103 @@ fn test_entrypoint_exit() {
     }
 }
 
+#[test]
+fn test_nested_calls_fixed_and_dynamic_stack_frames() {
+    // Without moving r11 the callees share the frame of the caller when the stack frames are
+    // dynamic, so these only keep values in registers. The results must not depend on the mode.
+    for dynamic_stack_frames in [false, true] {
+        let config = Config {
+            dynamic_stack_frames,
+            ..Config::default()
+        };
+        let source = "
+            entrypoint:
+            stdw [r10-8], 100
+            mov r6, 7
+            call foo
+            ldxdw r1, [r10-8]
+            add r0, r1
+            add r0, r6
+            exit
+            foo:
+            mov r6, 1
+            call bar
+            add r0, r6
+            exit
+            bar:
+            mov r6, 2
+            mov r0, 30
+            add r0, r6
+            exit";
+        assemble::<UserError, TestInstructionMeter>(
+            source,
+            Some(&solana_rbpf::verifier::check),
+            config.clone(),
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        test_interpreter_and_jit_asm!(
+            source,
+            config,
+            [],
+            (),
+            0,
+            { |_vm, res: Result| { res.unwrap() == 140 } },
+            15
+        );
+    }
+}
+
+#[test]
+fn test_recursion_to_max_call_depth() {
+    let config = Config {
+        max_call_depth: 5,
+        ..Config::default()
+    };
+    // The entrypoint and the first call of foo take two frames, so foo can recurse three times
+    test_interpreter_and_jit_asm!(
+        "
+        mov r1, 3
+        call foo
+        exit
+        foo:
+        jeq r1, 0, +2
+        sub r1, 1
+        call foo
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0 } },
+        17
+    );
+    test_interpreter_and_jit_asm!(
+        "
+        mov r1, 4
+        call foo
+        exit
+        foo:
+        jeq r1, 0, +2
+        sub r1, 1
+        call foo
+        exit",
+        config,
+        [],
+        (),
+        0,
+        {
+            |_vm, res: Result| {
+                matches!(res.unwrap_err(),
+                    EbpfError::CallDepthExceeded(pc, depth)
+                    if pc == 34 && depth == 5
+                )
+            }
+        },
+        14
+    );
+}
+
 #[test]
 fn test_stack_call_depth_tracking() {
     for dynamic_stack_frames in [false, true] {
@@ -2865,6 +4698,73 @@ fn test_stack_call_depth_tracking() {
     }
 }
 
+#[test]
+fn test_err_callx_recursion_too_deep() {
+    let config = Config {
+        max_call_depth: 4,
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    // The verifier can not bound the recursion depth of callx, so it is enforced at runtime
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r8, 0x1
+        lsh64 r8, 0x20
+        or64 r8, 0x20
+        callx r8
+        add64 r6, 1
+        callx r8
+        exit",
+        config,
+        [],
+        (),
+        0,
+        {
+            |_vm, res: Result| {
+                matches!(res.unwrap_err(),
+                    EbpfError::CallDepthExceeded(pc, depth)
+                    if pc == 29 + 5 && depth == config.max_call_depth
+                )
+            }
+        },
+        10
+    );
+}
+
+#[test]
+fn test_backtrace_nested_call() {
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r1, 1
+        call foo
+        exit
+        foo:
+        call bar
+        exit
+        bar:
+        ldxb r0, [r1]
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 4 });
+    assert!(matches!(
+        result.unwrap_err(),
+        EbpfError::AccessViolation(pc, AccessType::Load, 1, 1, "unknown") if pc == 29 + 5
+    ));
+    assert_eq!(
+        vm.backtrace(),
+        vec![(5, Some("bar")), (3, Some("foo")), (1, Some("entrypoint"))]
+    );
+}
+
 #[test]
 fn test_err_mem_access_out_of_bound() {
     let mem = [0; 512];
@@ -3385,34 +5285,583 @@ fn test_call_memfrob() {
             b"BpfMemFrob" => syscalls::BpfMemFrob::init::<BpfSyscallContext, UserError>; syscalls::BpfMemFrob::call,
         ),
         0,
-        { |_vm, res: Result| { res.unwrap() == 0x102292e2f2c0708 } },
-        7
+        { |_vm, res: Result| { res.unwrap() == 0x102292e2f2c0708 } },
+        7
+    );
+}
+
+#[test]
+fn test_syscall_with_context() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r1, 0xAA
+        mov64 r2, 0xBB
+        mov64 r3, 0xCC
+        mov64 r4, 0xDD
+        mov64 r5, 0xEE
+        syscall SyscallWithContext
+        mov64 r0, 0x0
+        exit",
+        [],
+        (
+            b"SyscallWithContext" => syscalls::SyscallWithContext::init::< syscalls::BpfSyscallContext, UserError>; syscalls::SyscallWithContext::call
+        ),
+        42,
+        { |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
+            let syscall_context_object = unsafe { &*(vm.get_syscall_context_object(syscalls::SyscallWithContext::call as usize).unwrap() as *const syscalls::SyscallWithContext) };
+            assert_eq!(syscall_context_object.context, 84);
+            res.unwrap() == 0
+        }},
+        8
+    );
+}
+
+#[test]
+fn test_syscall_scratch() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r1, 0x1234
+        syscall BpfScratchSet
+        mov64 r1, 0
+        syscall BpfScratchGet
+        exit",
+        [],
+        (
+            b"BpfScratchSet" => syscalls::BpfScratchSet::init::<syscalls::BpfScratchContext, UserError>; syscalls::BpfScratchSet::call,
+            b"BpfScratchGet" => syscalls::BpfScratchGet::init::<syscalls::BpfScratchContext, UserError>; syscalls::BpfScratchGet::call,
+        ),
+        Rc::new(RefCell::new(0u64)),
+        { |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
+            let syscall_context_object = unsafe { &*(vm.get_syscall_context_object(syscalls::BpfScratchSet::call as *const () as usize).unwrap() as *const syscalls::BpfScratchSet) };
+            assert_eq!(*syscall_context_object.scratch.borrow(), 0x1234);
+            res.unwrap() == 0x1234
+        }},
+        5
+    );
+}
+
+#[test]
+fn test_syscall_print_sink() {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let sink: syscalls::BpfPrintSink = Some(output.clone());
+    test_interpreter_and_jit_asm!(
+        "
+        mov r1, 0x6968
+        stxh [r10-8], r1
+        mov r1, r10
+        add r1, -8
+        mov r2, 2
+        syscall BpfSyscallString
+        mov r1, 1
+        mov r2, 2
+        mov r3, 3
+        syscall BpfTracePrintf
+        exit",
+        [],
+        (
+            b"BpfSyscallString" => syscalls::BpfSyscallString::init_with_sink::<syscalls::BpfPrintSink, UserError>; syscalls::BpfSyscallString::call,
+            b"BpfTracePrintf" => syscalls::BpfTracePrintf::init_with_sink::<syscalls::BpfPrintSink, UserError>; syscalls::BpfTracePrintf::call,
+        ),
+        sink.clone(),
+        { |_vm, res: Result| {
+            let expected = b"log: hi\nBpfTracePrintf: 0x3, 0x0, 0x0\n";
+            let printed = output.borrow()[output.borrow().len() - expected.len()..] == expected[..];
+            printed && res.unwrap() == "BpfTracePrintf: 0x3, 0x0, 0x0\n".len() as u64
+        }},
+        11
+    );
+}
+
+#[test]
+fn test_err_fault_keeps_sink_and_output() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfSyscallString",
+            syscalls::BpfSyscallString::init_with_sink::<syscalls::BpfPrintSink, UserError>,
+            syscalls::BpfSyscallString::call,
+        )
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r1, 0x6968
+        stxh [r10-8], r1
+        mov r1, r10
+        add r1, -8
+        mov r2, 2
+        syscall BpfSyscallString
+        lddw r2, 0x500000000
+        lddw r3, 0x1122334455667788
+        stxdw [r2], r3
+        ldxdw r0, [r2+8]
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    let run = |executable: &Pin<Box<Executable<UserError, TestInstructionMeter>>>, jit: bool| {
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let sink: syscalls::BpfPrintSink = Some(printed.clone());
+        let mut output = [0u8; 8];
+        let mut vm = EbpfVm::new(
+            executable,
+            &mut [],
+            vec![
+                MemoryRegion::new_writable(&mut [], ebpf::MM_INPUT_START),
+                MemoryRegion::new_writable(&mut output, 0x500000000),
+            ],
+        )
+        .unwrap();
+        vm.set_output_region(0x500000000).unwrap();
+        vm.bind_syscall_context_objects(sink).unwrap();
+        let result = if jit {
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 12 })
+        } else {
+            vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 12 })
+        };
+        assert!(matches!(
+            result,
+            Err(EbpfError::AccessViolation(pc, AccessType::Load, 0x500000008, 8, _)) if pc == 40
+        ));
+        assert_eq!(printed.borrow().as_slice(), b"log: hi\n");
+        assert_eq!(
+            LittleEndian::read_u64(&vm.take_output()),
+            0x1122334455667788
+        );
+    };
+    run(&executable, false);
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        run(&executable, true);
+    }
+}
+
+#[test]
+fn test_syscall_log_record_and_replay() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfScratchSet",
+            syscalls::BpfScratchSet::init::<syscalls::BpfScratchContext, UserError>,
+            syscalls::BpfScratchSet::call,
+        )
+        .unwrap();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfScratchGet",
+            syscalls::BpfScratchGet::init::<syscalls::BpfScratchContext, UserError>,
+            syscalls::BpfScratchGet::call,
+        )
+        .unwrap();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfMemFrob",
+            syscalls::BpfMemFrob::init::<syscalls::BpfScratchContext, UserError>,
+            syscalls::BpfMemFrob::call,
+        )
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r1, 0x1234
+        syscall BpfScratchSet
+        syscall BpfScratchGet
+        mov64 r6, r0
+        lddw r1, 0x400000000
+        mov64 r2, 4
+        syscall BpfMemFrob
+        lddw r1, 0x400000000
+        ldxb r0, [r1]
+        add64 r0, r6
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    let records = {
+        let scratch = Rc::new(RefCell::new(0u64));
+        let mut mem = [1u8, 2, 3, 4];
+        let mut vm = EbpfVm::new(
+            &executable,
+            &mut [],
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        )
+        .unwrap();
+        vm.bind_syscall_context_objects(scratch.clone()).unwrap();
+        vm.set_syscall_log(Some(SyscallLog::Record(Vec::new())));
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 14 });
+        assert_eq!(result.unwrap(), 0x1234 + 43);
+        assert_eq!(*scratch.borrow(), 0x1234);
+        let records = match vm.take_syscall_log() {
+            Some(SyscallLog::Record(records)) => records,
+            _ => panic!("syscall log is not recording"),
+        };
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1].result, 0x1234);
+        assert_eq!(
+            records[2].writes,
+            vec![(ebpf::MM_INPUT_START, vec![43, 40, 41, 46])]
+        );
+        records
+    };
+    {
+        let scratch = Rc::new(RefCell::new(7u64));
+        let mut mem = [1u8, 2, 3, 4];
+        {
+            let mut vm = EbpfVm::new(
+                &executable,
+                &mut [],
+                vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            )
+            .unwrap();
+            vm.bind_syscall_context_objects(scratch.clone()).unwrap();
+            vm.set_syscall_log(Some(SyscallLog::Replay(records.clone().into())));
+            let result =
+                vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 14 });
+            assert_eq!(result.unwrap(), 0x1234 + 43);
+            assert_eq!(
+                vm.take_syscall_log(),
+                Some(SyscallLog::Replay(Default::default()))
+            );
+        }
+        assert_eq!(*scratch.borrow(), 7);
+        assert_eq!(mem, [43, 40, 41, 46]);
+    }
+    {
+        let mut records = records.clone();
+        records[0].args[0] = 0x4321;
+        let mut mem = [1u8, 2, 3, 4];
+        let mut vm = EbpfVm::new(
+            &executable,
+            &mut [],
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        )
+        .unwrap();
+        vm.bind_syscall_context_objects(Rc::new(RefCell::new(0u64)))
+            .unwrap();
+        vm.set_syscall_log(Some(SyscallLog::Replay(records.into())));
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 14 });
+        assert_eq!(result, Err(EbpfError::SyscallReplayMismatch(30)));
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        vm.bind_syscall_context_objects(Rc::new(RefCell::new(0u64)))
+            .unwrap();
+        vm.set_syscall_log(Some(SyscallLog::Record(Vec::new())));
+        assert_eq!(
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 14 }),
+            Err(EbpfError::UnsupportedJitOption("syscall logs"))
+        );
+    }
+}
+
+#[test]
+fn test_syscall_log_gapped_stack() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfMemFrob",
+            syscalls::BpfMemFrob::init::<syscalls::BpfScratchContext, UserError>,
+            syscalls::BpfMemFrob::call,
+        )
+        .unwrap();
+    // The second frame lies behind a gap in the virtual address space
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        call function_foo
+        exit
+        function_foo:
+        stw [r10-8], 0x01020304
+        mov64 r1, r10
+        sub64 r1, 8
+        mov64 r2, 4
+        syscall BpfMemFrob
+        ldxw r0, [r10-8]
+        exit",
+        None,
+        Config {
+            dynamic_stack_frames: false,
+            enable_stack_frame_gaps: true,
+            ..Config::default()
+        },
+        syscall_registry,
+    )
+    .unwrap();
+    let config = executable.get_config();
+    let vm_addr = ebpf::MM_STACK_START + config.stack_frame_size as u64 * 3 - 8;
+    let records = {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        vm.bind_syscall_context_objects(Rc::new(RefCell::new(0u64)))
+            .unwrap();
+        vm.set_syscall_log(Some(SyscallLog::Record(Vec::new())));
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 9 });
+        assert_eq!(result.unwrap(), 0x2b28292e);
+        match vm.take_syscall_log() {
+            Some(SyscallLog::Record(records)) => records,
+            _ => panic!("syscall log is not recording"),
+        }
+    };
+    assert_eq!(
+        records[0].writes,
+        vec![(vm_addr, vec![0x2e, 0x29, 0x28, 0x2b])]
+    );
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.bind_syscall_context_objects(Rc::new(RefCell::new(0u64)))
+        .unwrap();
+    vm.set_syscall_log(Some(SyscallLog::Replay(records.into())));
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 9 });
+    assert_eq!(result.unwrap(), 0x2b28292e);
+}
+
+#[test]
+fn test_err_syscall_return_out_of_range() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfScratchGet",
+            syscalls::BpfScratchGet::init::<syscalls::BpfScratchContext, UserError>,
+            syscalls::BpfScratchGet::call,
+        )
+        .unwrap();
+    let hash = ebpf::hash_symbol_name(b"BpfScratchGet");
+    syscall_registry
+        .set_syscall_return_range::<UserError>(hash, 0..=1)
+        .unwrap();
+    assert_eq!(
+        syscall_registry.set_syscall_return_range::<UserError>(hash + 1, 0..=1),
+        Err(EbpfError::SyscallNotRegistered(hash as usize + 1))
+    );
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        syscall BpfScratchGet
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    let mut executable = executable;
+    test_interpreter_and_jit!(
+        executable,
+        [],
+        Rc::new(RefCell::new(5u64)),
+        {
+            |_vm, res: Result| {
+                res == Err(EbpfError::SyscallReturnOutOfRange(hash, 5))
+                    || res == Err(EbpfError::UnsupportedJitOption("syscall return ranges"))
+            }
+        },
+        1
+    );
+}
+
+struct ExitRecorder {
+    exit_code: u64,
+    calls: u64,
+}
+impl ExitRecorder {
+    fn init<C>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {
+            exit_code: 0,
+            calls: 0,
+        })
+    }
+}
+impl SyscallObject<UserError> for ExitRecorder {
+    fn call(
+        &mut self,
+        exit_code: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &MemoryMapping,
+        result: &mut Result,
+    ) {
+        self.exit_code = exit_code;
+        self.calls += 1;
+        *result = Ok(0);
+    }
+}
+
+#[test]
+fn test_exit_finalizer() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"ExitRecorder",
+            ExitRecorder::init::<BpfSyscallContext>,
+            ExitRecorder::call,
+        )
+        .unwrap();
+    let hash = ebpf::hash_symbol_name(b"ExitRecorder");
+    assert_eq!(
+        syscall_registry.set_exit_finalizer::<UserError>(hash + 1),
+        Err(EbpfError::SyscallNotRegistered(hash as usize + 1))
+    );
+    syscall_registry
+        .set_exit_finalizer::<UserError>(hash)
+        .unwrap();
+    // Returning from foo is not the exit of the program
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        call foo
+        add r0, 1
+        exit
+        foo:
+        mov r0, 41
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    let mut executable = executable;
+    let function = executable
+        .get_syscall_registry()
+        .lookup_syscall(hash)
+        .unwrap()
+        .function as usize;
+    test_interpreter_and_jit!(
+        executable,
+        [],
+        0,
+        {
+            |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
+                if res == Err(EbpfError::UnsupportedJitOption("exit finalizers")) {
+                    return true;
+                }
+                let recorder = unsafe {
+                    &*(vm.get_syscall_context_object(function).unwrap() as *const ExitRecorder)
+                };
+                recorder.exit_code == 42 && recorder.calls == 1 && res.unwrap() == 42
+            }
+        },
+        5
+    );
+
+    // The finalizer is dispatched like any other syscall, so its return range is checked
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"ExitRecorder",
+            ExitRecorder::init::<BpfSyscallContext>,
+            ExitRecorder::call,
+        )
+        .unwrap();
+    syscall_registry
+        .set_exit_finalizer::<UserError>(hash)
+        .unwrap();
+    syscall_registry
+        .set_syscall_return_range::<UserError>(hash, 1..=1)
+        .unwrap();
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 42
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.bind_syscall_context_objects(0).unwrap();
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 2 }),
+        Err(EbpfError::SyscallReturnOutOfRange(hash, 0))
+    );
+}
+
+#[test]
+fn test_err_pure_syscall_violation() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfMemSet",
+            syscalls::BpfMemSet::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfMemSet::call,
+        )
+        .unwrap();
+    let hash = ebpf::hash_symbol_name(b"BpfMemSet");
+    syscall_registry
+        .set_syscall_pure::<UserError>(hash)
+        .unwrap();
+    assert_eq!(
+        syscall_registry.set_syscall_pure::<UserError>(hash + 1),
+        Err(EbpfError::SyscallNotRegistered(hash as usize + 1))
+    );
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r1, r10
+        sub r1, 8
+        mov r2, 0x2a
+        mov r3, 8
+        syscall BpfMemSet
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    let mut executable = executable;
+    test_interpreter_and_jit!(
+        executable,
+        [],
+        0,
+        {
+            |_vm, res: Result| {
+                res == Err(EbpfError::PureSyscallViolation(hash))
+                    || res == Err(EbpfError::UnsupportedJitOption("pure syscalls"))
+            }
+        },
+        5
     );
 }
 
 #[test]
-fn test_syscall_with_context() {
-    test_interpreter_and_jit_asm!(
+fn test_pure_syscall_load() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfStrLen",
+            syscalls::BpfStrLen::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfStrLen::call,
+        )
+        .unwrap();
+    syscall_registry
+        .set_syscall_pure::<UserError>(ebpf::hash_symbol_name(b"BpfStrLen"))
+        .unwrap();
+    let executable = assemble::<UserError, TestInstructionMeter>(
         "
-        mov64 r1, 0xAA
-        mov64 r2, 0xBB
-        mov64 r3, 0xCC
-        mov64 r4, 0xDD
-        mov64 r5, 0xEE
-        syscall SyscallWithContext
-        mov64 r0, 0x0
+        mov r2, 16
+        syscall BpfStrLen
         exit",
-        [],
-        (
-            b"SyscallWithContext" => syscalls::SyscallWithContext::init::< syscalls::BpfSyscallContext, UserError>; syscalls::SyscallWithContext::call
-        ),
-        42,
-        { |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
-            let syscall_context_object = unsafe { &*(vm.get_syscall_context_object(syscalls::SyscallWithContext::call as usize).unwrap() as *const syscalls::SyscallWithContext) };
-            assert_eq!(syscall_context_object.context, 84);
-            res.unwrap() == 0
-        }},
-        8
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    let mut executable = executable;
+    test_interpreter_and_jit!(
+        executable,
+        [b'a', b'b', b'c', 0],
+        0,
+        {
+            |_vm, res: Result| {
+                res == Ok(3) || res == Err(EbpfError::UnsupportedJitOption("pure syscalls"))
+            }
+        },
+        3
     );
 }
 
@@ -3494,37 +5943,588 @@ fn test_nested_vm_syscall() {
         EbpfError::CallDepthExceeded(pc, depth)
         if pc == 33 && depth == 0
     ));
-}
+}
+
+#[test]
+fn test_syscall_instructions_executed() {
+    type SharedInstructionMeter = Rc<RefCell<TestInstructionMeter>>;
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfGetInstructionCount",
+            syscalls::BpfGetInstructionCount::init::<
+                syscalls::BpfInstructionCountContext,
+                UserError,
+            >,
+            syscalls::BpfGetInstructionCount::call,
+        )
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, SharedInstructionMeter>(
+        "
+        syscall BpfGetInstructionCount
+        mov64 r6, r0
+        mov64 r1, 0
+        add64 r1, 1
+        jne r1, 5, -2
+        syscall BpfGetInstructionCount
+        sub64 r0, r6
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    fn run(executable: &Pin<Box<Executable<UserError, SharedInstructionMeter>>>, jit: bool) -> u64 {
+        let instruction_meter = Rc::new(RefCell::new(TestInstructionMeter { remaining: 100 }));
+        let mut vm = EbpfVm::new(executable, &mut [], Vec::new()).unwrap();
+        vm.bind_syscall_context_objects(syscalls::BpfInstructionCountContext {
+            instruction_meter: instruction_meter.clone(),
+            run_start_remaining: vm.get_run_start_handle(),
+        })
+        .unwrap();
+        let result = if jit {
+            vm.execute_program_jit(&mut instruction_meter.clone())
+        } else {
+            vm.execute_program_interpreted(&mut instruction_meter.clone())
+        };
+        assert_eq!(vm.get_total_instruction_count(), 16);
+        result.unwrap()
+    }
+    // Two movs, the loop and the second syscall itself
+    assert_eq!(run(&executable, false), 2 + 5 * 2 + 1);
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        assert_eq!(run(&executable, true), 2 + 5 * 2 + 1);
+    }
+}
+
+#[test]
+fn test_syscall_instructions_executed_per_run() {
+    type SharedInstructionMeter = Rc<RefCell<TestInstructionMeter>>;
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfInstructionsExecuted",
+            syscalls::BpfInstructionsExecuted::init::<
+                syscalls::BpfInstructionCountContext,
+                UserError,
+            >,
+            syscalls::BpfInstructionsExecuted::call,
+        )
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, SharedInstructionMeter>(
+        "
+        syscall BpfInstructionsExecuted
+        mov64 r6, r0
+        mov64 r1, 0
+        add64 r1, 1
+        jne r1, 3, -2
+        syscall BpfInstructionsExecuted
+        lsh64 r0, 32
+        or64 r0, r6
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    fn run(executable: &Pin<Box<Executable<UserError, SharedInstructionMeter>>>, jit: bool) {
+        let instruction_meter = Rc::new(RefCell::new(TestInstructionMeter { remaining: 100 }));
+        let mut vm = EbpfVm::new(executable, &mut [], Vec::new()).unwrap();
+        vm.bind_syscall_context_objects(syscalls::BpfInstructionCountContext {
+            instruction_meter: instruction_meter.clone(),
+            run_start_remaining: vm.get_run_start_handle(),
+        })
+        .unwrap();
+        // The count restarts with every run
+        for _ in 0..2 {
+            let result = if jit {
+                vm.execute_program_jit(&mut instruction_meter.clone())
+            } else {
+                vm.execute_program_interpreted(&mut instruction_meter.clone())
+            }
+            .unwrap();
+            let (first, second) = (result & 0xffffffff, result >> 32);
+            assert_eq!(first, 1);
+            assert_eq!(second, 1 + 2 + 3 * 2 + 1);
+            assert!(second > first);
+        }
+    }
+    run(&executable, false);
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        run(&executable, true);
+    }
+}
+
+#[cfg(feature = "sha256")]
+#[test]
+fn test_execution_receipt() {
+    let config = Config {
+        enable_execution_receipt: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r2, [r1]
+        jeq r2, 0, +2
+        mov r0, 1
+        exit
+        mov r0, 1
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let receipt = |input: u8| {
+        let mut mem = [input];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        assert_eq!(
+            vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 4 })
+                .unwrap(),
+            1
+        );
+        let first = vm.execution_receipt();
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 4 })
+            .unwrap();
+        assert_eq!(vm.execution_receipt(), first);
+        first
+    };
+    assert_ne!(receipt(0), [0; 32]);
+    assert_eq!(receipt(0), receipt(0));
+    assert_eq!(receipt(1), receipt(2));
+    assert_ne!(receipt(0), receipt(1));
+}
+
+#[cfg(not(feature = "sha256"))]
+#[test]
+fn test_execution_receipt_requires_sha256() {
+    let config = Config {
+        enable_execution_receipt: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 0
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    assert!(matches!(
+        EbpfVm::new(&executable, &mut [], Vec::new()),
+        Err(EbpfError::FeatureNotEnabled(
+            "Config::enable_execution_receipt",
+            "sha256"
+        ))
+    ));
+}
+
+#[test]
+fn test_snapshot_restore_memory() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        lddw r1, 0x300000000
+        ldxb r0, [r1]
+        add r0, 1
+        stxb [r1], r0
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut heap = [0u8; 8];
+    let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 6 })
+            .unwrap(),
+        1
+    );
+    let snapshot = vm.snapshot_memory();
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 6 })
+            .unwrap(),
+        2
+    );
+    assert_ne!(vm.snapshot_memory(), snapshot);
+    vm.restore_memory(&snapshot).unwrap();
+    assert_eq!(vm.snapshot_memory(), snapshot);
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 6 })
+            .unwrap(),
+        2
+    );
+    drop(vm);
+    assert_eq!(heap[0], 2);
+}
+
+#[test]
+fn test_debug_breakpoint_step_resume() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        add r0, 2
+        mov r1, 5
+        add r0, r1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 10 };
+    vm.set_breakpoint(2);
+
+    let event = vm.debug_continue(&mut instruction_meter);
+    let registers = match event {
+        DebugEvent::Breakpoint(DebugState { pc: 2, registers }) => registers,
+        event => panic!("unexpected {:?}", event),
+    };
+    assert_eq!(registers[0], 3);
+    assert_eq!(instruction_meter.remaining, 8);
+
+    let event = vm.debug_step(&mut instruction_meter);
+    let registers = match event {
+        DebugEvent::Step(DebugState { pc: 3, registers }) => registers,
+        event => panic!("unexpected {:?}", event),
+    };
+    assert_eq!(registers[0], 3);
+    assert_eq!(registers[1], 5);
+
+    assert_eq!(
+        vm.debug_continue(&mut instruction_meter),
+        DebugEvent::Exit(Ok(8))
+    );
+    assert_eq!(vm.get_total_instruction_count(), 5);
+    assert_eq!(instruction_meter.remaining, 5);
+
+    // A breakpoint at the entrypoint stops before the first instruction
+    assert!(vm.remove_breakpoint(2));
+    vm.set_breakpoint(0);
+    assert!(matches!(
+        vm.debug_step(&mut TestInstructionMeter { remaining: 10 }),
+        DebugEvent::Breakpoint(DebugState { pc: 0, .. })
+    ));
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 10 }),
+        Ok(8)
+    );
+}
+
+#[test]
+fn test_syscall_registry_name_hasher() {
+    fn fnv1a(name: &str) -> u32 {
+        name.bytes().fold(0x811c9dc5, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x01000193)
+        })
+    }
+    let mut syscall_registry = SyscallRegistry::with_name_hasher(fnv1a);
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfSaturatingAdd",
+            syscalls::BpfSaturatingAdd::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfSaturatingAdd::call,
+        )
+        .unwrap();
+    let hash = fnv1a("BpfSaturatingAdd");
+    assert_ne!(hash, ebpf::hash_symbol_name(b"BpfSaturatingAdd"));
+    assert_eq!(syscall_registry.hash_name(b"BpfSaturatingAdd"), hash);
+    assert!(syscall_registry.lookup_syscall(hash).is_some());
+
+    // A call encoded with the custom hash
+    let mut prog = vec![
+        0xb7, 0x01, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, // mov r1, 5
+        0xb7, 0x02, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, // mov r2, 7
+        0x85, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // call hash
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // exit
+    ];
+    prog[20..24].copy_from_slice(&hash.to_le_bytes());
+    let config = Config::default();
+    let mut bpf_functions = BTreeMap::new();
+    register_bpf_function(
+        &config,
+        &mut bpf_functions,
+        &syscall_registry,
+        0,
+        "entrypoint",
+    )
+    .unwrap();
+    let executable = Executable::<UserError, TestInstructionMeter>::from_text_bytes(
+        &prog,
+        None,
+        config,
+        syscall_registry,
+        bpf_functions,
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.bind_syscall_context_objects(0).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 4 });
+    assert_eq!(result.unwrap(), 12);
+
+    // The assembler hashes syscall names with the registry too
+    let mut syscall_registry = SyscallRegistry::with_name_hasher(fnv1a);
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfSaturatingAdd",
+            syscalls::BpfSaturatingAdd::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfSaturatingAdd::call,
+        )
+        .unwrap();
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r1, 5
+        mov r2, 7
+        syscall BpfSaturatingAdd
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    assert_eq!(executable.get_text_bytes().1[20..24], hash.to_le_bytes());
+
+    // Collisions of the custom hash are still detected
+    let mut syscall_registry = SyscallRegistry::with_name_hasher(|_name| 42);
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfSaturatingAdd",
+            syscalls::BpfSaturatingAdd::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfSaturatingAdd::call,
+        )
+        .unwrap();
+    assert_eq!(
+        syscall_registry.register_syscall_by_name(
+            b"BpfSaturatingSub",
+            syscalls::BpfSaturatingSub::init::<BpfSyscallContext, UserError>,
+            syscalls::BpfSaturatingSub::call,
+        ),
+        Err(EbpfError::SycallAlreadyRegistered(42))
+    );
+}
+
+#[test]
+fn test_syscall_current_pc() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"BpfCurrentPc",
+            syscalls::BpfCurrentPc::init::<Rc<Cell<u64>>, UserError>,
+            syscalls::BpfCurrentPc::call,
+        )
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        syscall BpfCurrentPc
+        mov64 r6, r0
+        mov64 r7, 0
+        syscall BpfCurrentPc
+        lsh64 r0, 32
+        or64 r0, r6
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let current_pc = vm.get_current_pc_handle();
+        vm.bind_syscall_context_objects(current_pc).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 7 });
+        assert_eq!(result.unwrap(), 3 << 32);
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let current_pc = vm.get_current_pc_handle();
+        vm.bind_syscall_context_objects(current_pc).unwrap();
+        assert_eq!(
+            vm.execute_program_jit(&mut TestInstructionMeter { remaining: 7 }),
+            Err(EbpfError::UnsupportedJitOption("current pc handle"))
+        );
+    }
+}
+
+// Elf
+
+#[test]
+fn test_load_elf() {
+    test_interpreter_and_jit_elf!(
+        "tests/elfs/noop.so",
+        [],
+        (
+            b"log" => syscalls::BpfSyscallString::init::<BpfSyscallContext, UserError>; syscalls::BpfSyscallString::call,
+            b"log_64" => syscalls::BpfSyscallU64::init::<BpfSyscallContext, UserError>; syscalls::BpfSyscallU64::call,
+        ),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0 } },
+        11
+    );
+}
+
+#[test]
+fn test_load_elf_empty_noro() {
+    test_interpreter_and_jit_elf!(
+        "tests/elfs/noro.so",
+        [],
+        (
+            b"log_64" => syscalls::BpfSyscallU64::init::<BpfSyscallContext, UserError>; syscalls::BpfSyscallU64::call,
+        ),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0 } },
+        8
+    );
+}
+
+#[test]
+fn test_load_elf_rewrite_immediates() {
+    let mut file = File::open("tests/elfs/noro.so").unwrap();
+    let mut elf = Vec::new();
+    file.read_to_end(&mut elf).unwrap();
+    let mut parameters = BTreeMap::new();
+    parameters.insert("result".to_string(), 42);
+    let syscall_registry = || {
+        let mut syscall_registry = SyscallRegistry::default();
+        syscall_registry
+            .register_syscall_by_name(
+                b"log_64",
+                syscalls::BpfSyscallU64::init::<BpfSyscallContext, UserError>,
+                syscalls::BpfSyscallU64::call,
+            )
+            .unwrap();
+        syscall_registry
+    };
+
+    // mov64 r0, 0
+    let executable = Executable::<UserError, TestInstructionMeter>::from_elf_with_rewriter(
+        &elf,
+        Some(&check),
+        Config::default(),
+        syscall_registry(),
+        &parameters,
+        |pc, insn, parameters| {
+            if pc == 6 && insn.opc == ebpf::MOV64_IMM {
+                parameters.get("result").copied()
+            } else {
+                None
+            }
+        },
+    )
+    .unwrap();
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    let mut executable = executable;
+    test_interpreter_and_jit!(
+        executable,
+        [],
+        0,
+        { |_vm, res: Result| { res.unwrap() == 42 } },
+        8
+    );
+
+    // The immediate does not fit
+    assert!(matches!(
+        Executable::<UserError, TestInstructionMeter>::from_elf_with_rewriter(
+            &elf,
+            Some(&check),
+            Config::default(),
+            syscall_registry(),
+            &parameters,
+            |pc, _insn, _parameters| if pc == 6 { Some(1 << 32) } else { None },
+        ),
+        Err(EbpfError::InvalidInstruction(35))
+    ));
 
-// Elf
+    // call log_64
+    assert!(matches!(
+        Executable::<UserError, TestInstructionMeter>::from_elf_with_rewriter(
+            &elf,
+            Some(&check),
+            Config::default(),
+            syscall_registry(),
+            &parameters,
+            |_pc, insn, _parameters| if insn.opc == ebpf::CALL_IMM {
+                Some(0)
+            } else {
+                None
+            },
+        ),
+        Err(EbpfError::InvalidInstruction(34))
+    ));
+}
 
 #[test]
-fn test_load_elf() {
-    test_interpreter_and_jit_elf!(
-        "tests/elfs/noop.so",
+fn test_load_elf_rewrite_lddw_immediate() {
+    let mut file = File::open("tests/elfs/reloc_64_64.so").unwrap();
+    let mut elf = Vec::new();
+    file.read_to_end(&mut elf).unwrap();
+    let executable = Executable::<UserError, TestInstructionMeter>::from_elf_with_rewriter(
+        &elf,
+        Some(&check),
+        Config::default(),
+        SyscallRegistry::default(),
+        &BTreeMap::new(),
+        |_pc, insn, _parameters| {
+            if insn.opc == ebpf::LD_DW_IMM {
+                Some(0x1122334455667788)
+            } else {
+                None
+            }
+        },
+    )
+    .unwrap();
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    let mut executable = executable;
+    test_interpreter_and_jit!(
+        executable,
         [],
-        (
-            b"log" => syscalls::BpfSyscallString::init::<BpfSyscallContext, UserError>; syscalls::BpfSyscallString::call,
-            b"log_64" => syscalls::BpfSyscallU64::init::<BpfSyscallContext, UserError>; syscalls::BpfSyscallU64::call,
-        ),
         0,
-        { |_vm, res: Result| { res.unwrap() == 0 } },
-        11
+        { |_vm, res: Result| { res.unwrap() == 0x1122334455667788 } },
+        2
     );
 }
 
 #[test]
-fn test_load_elf_empty_noro() {
-    test_interpreter_and_jit_elf!(
-        "tests/elfs/noro.so",
-        [],
-        (
-            b"log_64" => syscalls::BpfSyscallU64::init::<BpfSyscallContext, UserError>; syscalls::BpfSyscallU64::call,
-        ),
-        0,
-        { |_vm, res: Result| { res.unwrap() == 0 } },
-        8
+fn test_raw_instruction_at() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r1, 2
+        lddw r0, 0x1122334455667788
+        add r0, r1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let insn = executable.raw_instruction_at(0).unwrap();
+    assert_eq!(
+        (insn.ptr, insn.opc, insn.dst, insn.src, insn.off, insn.imm),
+        (0, ebpf::MOV64_IMM, 1, 0, 0, 2)
+    );
+    let insn = executable.raw_instruction_at(1).unwrap();
+    assert_eq!(
+        (insn.ptr, insn.opc, insn.dst, insn.imm),
+        (1, ebpf::LD_DW_IMM, 0, 0x1122334455667788)
     );
+    assert!(executable.raw_instruction_at(2).is_none());
+    assert_eq!(
+        executable.raw_instruction_at(3).unwrap().opc,
+        ebpf::ADD64_REG
+    );
+    assert_eq!(executable.raw_instruction_at(4).unwrap().opc, ebpf::EXIT);
+    assert!(executable.raw_instruction_at(5).is_none());
 }
 
 #[test]
@@ -3562,6 +6562,68 @@ fn test_load_elf_rodata() {
     }
 }
 
+#[test]
+fn test_to_elf_roundtrip() {
+    for (source, optimize_rodata, expected_result) in [
+        ("tests/elfs/rodata.so", false, 42),
+        ("tests/elfs/rodata.so", true, 42),
+        ("tests/elfs/rodata_high_vaddr.so", true, 42),
+        ("tests/elfs/relative_call.so", true, 2),
+        ("tests/elfs/syscall_static.so", true, 0),
+    ] {
+        let mut file = File::open(source).unwrap();
+        let mut elf = Vec::new();
+        file.read_to_end(&mut elf).unwrap();
+        let config = Config {
+            enable_symbol_and_section_labels: true,
+            optimize_rodata,
+            ..Config::default()
+        };
+        let load = |elf: &[u8]| {
+            let mut syscall_registry = SyscallRegistry::default();
+            syscall_registry
+                .register_syscall_by_name(
+                    b"log",
+                    syscalls::BpfSyscallString::init::<BpfSyscallContext, UserError>,
+                    syscalls::BpfSyscallString::call,
+                )
+                .unwrap();
+            Executable::<UserError, TestInstructionMeter>::from_elf(
+                elf,
+                None,
+                config.clone(),
+                syscall_registry,
+            )
+            .unwrap()
+        };
+        let run = |executable: &Pin<Box<Executable<UserError, TestInstructionMeter>>>| {
+            let mut mem = [1u8];
+            let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+            let mut vm = EbpfVm::new(executable, &mut [], vec![mem_region]).unwrap();
+            vm.bind_syscall_context_objects(0).unwrap();
+            let result = vm
+                .execute_program_interpreted(&mut TestInstructionMeter { remaining: 1000 })
+                .unwrap();
+            (result, vm.get_total_instruction_count())
+        };
+        let executable = load(&elf);
+        let reloaded = load(&executable.to_elf().unwrap());
+        assert_eq!(executable.get_text_bytes(), reloaded.get_text_bytes());
+        assert_eq!(
+            executable.get_ro_region().vm_addr,
+            reloaded.get_ro_region().vm_addr
+        );
+        assert_eq!(executable.get_ro_section(), reloaded.get_ro_section());
+        assert_eq!(
+            executable.get_function_symbols(),
+            reloaded.get_function_symbols()
+        );
+        let (result, instruction_count) = run(&executable);
+        assert_eq!(result, expected_result);
+        assert_eq!(run(&reloaded), (result, instruction_count));
+    }
+}
+
 #[test]
 fn test_load_elf_rodata_high_vaddr() {
     test_interpreter_and_jit_elf!(
@@ -3764,6 +6826,204 @@ fn test_err_instruction_count_lddw_capped() {
     );
 }
 
+#[test]
+fn test_instruction_costs() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r0, 6
+        mul64 r0, 7
+        div64 r0, 2
+        exit",
+        {
+            let mut config = Config {
+                enable_instruction_tracing: true,
+                ..Config::default()
+            };
+            config.set_instruction_costs(&[(ebpf::MUL64_IMM, 5), (ebpf::DIV64_IMM, 10)].into());
+            config
+        },
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res == Ok(21) } },
+        1 + 5 + 10 + 1
+    );
+}
+
+#[test]
+fn test_err_instruction_costs_capped() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r0, 6
+        mul64 r0, 7
+        div64 r0, 2
+        exit",
+        {
+            let mut config = Config::default();
+            config.set_instruction_costs(&[(ebpf::MUL64_IMM, 5), (ebpf::DIV64_IMM, 10)].into());
+            config
+        },
+        [],
+        (),
+        0,
+        {
+            |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
+                // The division costs 10 with only 4 remaining
+                matches!(res,
+                    Err(EbpfError::ExceededMaxInstructions(pc, initial_insn_count))
+                    if pc == 32 && initial_insn_count == 10
+                ) && vm.get_instruction_meter_overshoot() == 6
+            }
+        },
+        10
+    );
+}
+
+#[test]
+fn test_instruction_class_costs() {
+    assert_eq!(
+        InstructionClass::of(ebpf::LD_DW_IMM),
+        InstructionClass::Load
+    );
+    assert_eq!(
+        InstructionClass::of(ebpf::ST_W_REG),
+        InstructionClass::Store
+    );
+    assert_eq!(InstructionClass::of(ebpf::ADD64_IMM), InstructionClass::Alu);
+    assert_eq!(
+        InstructionClass::of(ebpf::MOD32_REG),
+        InstructionClass::Division
+    );
+    assert_eq!(
+        InstructionClass::of(ebpf::SDIV64_IMM),
+        InstructionClass::Division
+    );
+    assert_eq!(InstructionClass::of(ebpf::JEQ_IMM), InstructionClass::Jump);
+    assert_eq!(InstructionClass::of(ebpf::CALL_IMM), InstructionClass::Call);
+    assert_eq!(InstructionClass::of(ebpf::EXIT), InstructionClass::Exit);
+
+    // Fits the budget with one unit per instruction
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r0, 42
+        div64 r0, 2
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 21 } },
+        3
+    );
+
+    // Exceeds the same budget once division is weighted
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r0, 42
+        div64 r0, 2
+        exit",
+        {
+            let mut config = Config::default();
+            config.set_instruction_class_costs(&[(InstructionClass::Division, 10)].into());
+            config
+        },
+        [],
+        (),
+        0,
+        {
+            |_vm, res: Result| {
+                matches!(res,
+                    Err(EbpfError::ExceededMaxInstructions(pc, initial_insn_count))
+                    if pc == 31 && initial_insn_count == 3
+                )
+            }
+        },
+        3
+    );
+}
+
+#[test]
+fn test_instruction_costs_branches_and_syscalls() {
+    // Loops are profiled at the branches
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r1, 0
+        mov64 r0, 0
+        add64 r0, 3
+        add64 r1, 1
+        jlt r1, 4, -3
+        exit",
+        {
+            let mut config = Config::default();
+            config.set_instruction_class_costs(
+                &[(InstructionClass::Alu, 2), (InstructionClass::Jump, 3)].into(),
+            );
+            config
+        },
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 12 } },
+        2 + 2 + 4 * (2 + 2 + 3) + 1
+    );
+
+    // Runs out in the third iteration
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r1, 0
+        mov64 r0, 0
+        add64 r0, 3
+        add64 r1, 1
+        jlt r1, 4, -3
+        exit",
+        {
+            let mut config = Config::default();
+            config.set_instruction_class_costs(
+                &[(InstructionClass::Alu, 2), (InstructionClass::Jump, 3)].into(),
+            );
+            config
+        },
+        [],
+        (),
+        0,
+        {
+            |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
+                matches!(res,
+                    Err(EbpfError::ExceededMaxInstructions(pc, initial_insn_count))
+                    if pc == 32 && initial_insn_count == 20
+                ) && vm.get_instruction_meter_overshoot() == 0
+            }
+        },
+        20
+    );
+
+    // The syscall still runs when its call exceeds the budget
+    test_interpreter_and_jit_asm!(
+        "
+        mov64 r1, 0xAA
+        syscall BpfSyscallU64
+        exit",
+        {
+            let mut config = Config::default();
+            config.set_instruction_class_costs(&[(InstructionClass::Call, 10)].into());
+            config
+        },
+        [],
+        (
+            b"BpfSyscallU64" => syscalls::BpfSyscallU64::init::<BpfSyscallContext, UserError>; syscalls::BpfSyscallU64::call,
+        ),
+        0,
+        {
+            |vm: &EbpfVm<UserError, TestInstructionMeter>, res: Result| {
+                matches!(res,
+                    Err(EbpfError::ExceededMaxInstructions(pc, initial_insn_count))
+                    if pc == 31 && initial_insn_count == 5
+                ) && vm.get_instruction_meter_overshoot() == 6
+            }
+        },
+        5
+    );
+}
+
 #[test]
 fn test_non_terminate_early() {
     test_interpreter_and_jit_asm!(
@@ -4419,7 +7679,7 @@ fn execute_generated_program(prog: &[u8]) -> bool {
     .unwrap();
     let executable = Executable::<UserError, TestInstructionMeter>::from_text_bytes(
         prog,
-        Some(solana_rbpf::verifier::check),
+        Some(&solana_rbpf::verifier::check),
         config,
         syscall_registry,
         bpf_functions,
@@ -4502,3 +7762,159 @@ fn test_total_chaos() {
         execute_generated_program(&program);
     }
 }
+
+#[cfg(all(not(windows), target_arch = "x86_64"))]
+#[test]
+fn test_corpus_interpreter_and_jit_agree() {
+    let divergences =
+        test_utils::compare_interpreter_and_jit_on_corpus(std::path::Path::new("tests/corpus"))
+            .unwrap();
+    assert!(divergences.is_empty(), "{:#?}", divergences);
+}
+
+#[test]
+fn test_estimate_jit_cost() {
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        TCP_SACK_ASM,
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let estimate = executable.estimate_jit_cost();
+    assert_eq!(estimate.instructions, 44);
+    assert!(estimate.bytes <= estimate.max_bytes);
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::jit_compile(&mut executable).unwrap();
+        let actual = executable
+            .get_compiled_program()
+            .unwrap()
+            .machine_code_length();
+        assert!(actual <= estimate.max_bytes);
+        assert!(estimate.bytes / 2 <= actual && actual <= estimate.bytes * 2);
+    }
+}
+
+#[test]
+fn test_is_jit_recommended() {
+    let config = Config {
+        jit_threshold_instructions: 10,
+        ..Config::default()
+    };
+    let small = assemble::<UserError, TestInstructionMeter>(
+        "exit",
+        None,
+        config.clone(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    assert!(!small.is_jit_recommended());
+    let large = assemble::<UserError, TestInstructionMeter>(
+        TCP_SACK_ASM,
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    assert!(large.is_jit_recommended());
+    let small = assemble::<UserError, TestInstructionMeter>(
+        "exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    assert!(small.is_jit_recommended());
+}
+
+#[test]
+fn test_random_seed_reproducible() {
+    fn run(random_seed: Option<u64>) -> (u64, Vec<[u64; 12]>, usize) {
+        let config = Config {
+            enable_instruction_tracing: true,
+            noop_instruction_ratio: 0.5,
+            random_seed,
+            ..Config::default()
+        };
+        #[allow(unused_mut)]
+        let mut executable = assemble::<UserError, TestInstructionMeter>(
+            "
+            ldxdw r0, [r10-8]
+            add64 r0, 0x12345678
+            mov64 r1, 3
+            mul64 r0, r1
+            exit",
+            None,
+            config,
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        let (result, log) = {
+            let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+            let result = vm
+                .execute_program_interpreted(&mut TestInstructionMeter { remaining: 5 })
+                .unwrap();
+            (result, vm.get_tracer().log.clone())
+        };
+        #[cfg(all(not(windows), target_arch = "x86_64"))]
+        let machine_code_length = {
+            Executable::jit_compile(&mut executable).unwrap();
+            let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+            let jit_result = vm.execute_program_jit(&mut TestInstructionMeter { remaining: 5 });
+            assert_eq!(jit_result.unwrap(), result);
+            executable
+                .get_compiled_program()
+                .unwrap()
+                .machine_code_length()
+        };
+        #[cfg(any(windows, not(target_arch = "x86_64")))]
+        let machine_code_length = 0;
+        (result, log, machine_code_length)
+    }
+    let first = run(Some(42));
+    assert_eq!(first.0, 0x12345678 * 3);
+    assert_eq!(run(Some(42)), first);
+}
+
+#[test]
+#[cfg(all(not(windows), target_arch = "x86_64"))]
+fn test_assert_deterministic() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxdw r0, [r1]
+        add64 r0, 1
+        stxdw [r1+8], r0
+        lddw r2, 0x300000000
+        stxdw [r2], r0
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mem = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(
+        Executable::assert_deterministic(&mut executable, &mem, 8, 0, || TestInstructionMeter {
+            remaining: 6
+        }),
+        Ok(())
+    );
+
+    // Starve the JIT run of instructions
+    let mut remaining = vec![6, 5].into_iter();
+    assert!(matches!(
+        Executable::assert_deterministic(&mut executable, &mem, 8, 0, || TestInstructionMeter {
+            remaining: remaining.next().unwrap()
+        }),
+        Err(Divergence::Result(
+            Ok(2),
+            Err(EbpfError::ExceededMaxInstructions(_, 5))
+        ))
+    ));
+}
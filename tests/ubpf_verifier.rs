@@ -26,12 +26,14 @@ use solana_rbpf::{
     assembler::assemble,
     ebpf,
     elf::Executable,
-    error::UserDefinedError,
+    error::{EbpfError, UserDefinedError},
     user_error::UserError,
-    verifier::{check, VerifierError},
+    verifier::{
+        check, DefaultVerifier, IncrementalVerifier, Verifier, VerifierError, VerifierPipeline,
+    },
     vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter},
 };
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, convert::TryInto};
 use thiserror::Error;
 
 /// Error definitions
@@ -48,7 +50,7 @@ fn test_verifier_success() {
         "
         mov32 r0, 0xBEE
         exit",
-        Some(|_prog: &[u8], _config: &Config| Ok(())),
+        Some(&|_prog: &[u8], _config: &Config| Ok(())),
         Config::default(),
         SyscallRegistry::default(),
     )
@@ -67,7 +69,7 @@ fn test_verifier_fail() {
         "
         mov32 r0, 0xBEE
         exit",
-        Some(verifier_fail),
+        Some(&verifier_fail),
         Config::default(),
         SyscallRegistry::default(),
     )
@@ -82,13 +84,40 @@ fn test_verifier_err_div_by_zero_imm() {
         mov32 r0, 1
         div32 r0, 0
         exit",
-        Some(check),
+        Some(&check),
         Config::default(),
         SyscallRegistry::default(),
     )
     .unwrap();
 }
 
+#[test]
+fn test_verifier_err_div_mod_by_zero_imm() {
+    for mnemonic in ["div", "mod", "div32", "mod32", "div64", "mod64"] {
+        let executable = assemble::<UserError, TestInstructionMeter>(
+            &format!(
+                "
+                mov r0, 1
+                mov r1, 0
+                {} r0, r1
+                {} r0, 0
+                exit",
+                mnemonic, mnemonic
+            ),
+            None,
+            Config::default(),
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            check(executable.get_text_bytes().1, &Config::default()),
+            Err(VerifierError::DivisionByZero(32)),
+            "{}",
+            mnemonic
+        );
+    }
+}
+
 #[test]
 #[should_panic(expected = "UnsupportedLEBEArgument(29)")]
 fn test_verifier_err_endian_size() {
@@ -99,7 +128,7 @@ fn test_verifier_err_endian_size() {
     ];
     let _ = Executable::<UserError, TestInstructionMeter>::from_text_bytes(
         prog,
-        Some(check),
+        Some(&check),
         Config::default(),
         SyscallRegistry::default(),
         BTreeMap::default(),
@@ -117,7 +146,7 @@ fn test_verifier_err_incomplete_lddw() {
     ];
     let _ = Executable::<UserError, TestInstructionMeter>::from_text_bytes(
         prog,
-        Some(check),
+        Some(&check),
         Config::default(),
         SyscallRegistry::default(),
         BTreeMap::default(),
@@ -135,7 +164,7 @@ fn test_verifier_err_invalid_reg_dst() {
                 "
                 mov r11, 1
                 exit",
-                Some(check),
+                Some(&check),
                 Config {
                     dynamic_stack_frames,
                     ..Config::default()
@@ -158,7 +187,7 @@ fn test_verifier_err_invalid_reg_src() {
                 "
                 mov r0, r11
                 exit",
-                Some(check),
+                Some(&check),
                 Config {
                     dynamic_stack_frames,
                     ..Config::default()
@@ -178,7 +207,7 @@ fn test_verifier_resize_stack_ptr_success() {
         sub r11, 1
         add r11, 1
         exit",
-        Some(check),
+        Some(&check),
         Config {
             dynamic_stack_frames: true,
             enable_stack_frame_gaps: false,
@@ -197,7 +226,7 @@ fn test_verifier_err_jmp_lddw() {
         ja +1
         lddw r0, 0x1122334455667788
         exit",
-        Some(check),
+        Some(&check),
         Config::default(),
         SyscallRegistry::default(),
     )
@@ -211,13 +240,45 @@ fn test_verifier_err_jmp_out() {
         "
         ja +2
         exit",
-        Some(check),
+        Some(&check),
         Config::default(),
         SyscallRegistry::default(),
     )
     .unwrap();
 }
 
+#[test]
+fn test_verifier_err_jmp_targets() {
+    for (assembly, expected) in [
+        (
+            "mov r0, 0\n jeq r0, 0, +2\n exit",
+            VerifierError::JumpOutOfCode(4, 30),
+        ),
+        (
+            "mov r0, 0\n ja -3\n exit",
+            VerifierError::JumpOutOfCode(usize::MAX, 30),
+        ),
+        (
+            "lddw r0, 0x1122334455667788\n jne r0, 0, -2\n exit",
+            VerifierError::JumpToMiddleOfLDDW(1, 31),
+        ),
+    ] {
+        let executable = assemble::<UserError, TestInstructionMeter>(
+            assembly,
+            None,
+            Config::default(),
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            check(executable.get_text_bytes().1, &Config::default()),
+            Err(expected),
+            "{}",
+            assembly
+        );
+    }
+}
+
 #[test]
 #[should_panic(expected = "UnknownOpCode(6, 29)")]
 fn test_verifier_err_unknown_opcode() {
@@ -227,7 +288,7 @@ fn test_verifier_err_unknown_opcode() {
     ];
     let _ = Executable::<UserError, TestInstructionMeter>::from_text_bytes(
         prog,
-        Some(check),
+        Some(&check),
         Config::default(),
         SyscallRegistry::default(),
         BTreeMap::default(),
@@ -242,7 +303,7 @@ fn test_verifier_err_write_r10() {
         "
         mov r10, 1
         exit",
-        Some(check),
+        Some(&check),
         Config::default(),
         SyscallRegistry::default(),
     )
@@ -279,7 +340,7 @@ fn test_verifier_err_all_shift_overflows() {
         let assembly = format!("\n{}\nexit", overflowing_instruction);
         let result = assemble::<UserError, TestInstructionMeter>(
             &assembly,
-            Some(check),
+            Some(&check),
             Config::default(),
             SyscallRegistry::default(),
         );
@@ -314,7 +375,7 @@ fn test_verifier_err_ldabs_ldind_disabled() {
             let assembly = format!("\n{}\nexit", instruction);
             let result = assemble::<UserError, TestInstructionMeter>(
                 &assembly,
-                Some(check),
+                Some(&check),
                 Config {
                     disable_deprecated_load_instructions,
                     ..Config::default()
@@ -352,7 +413,7 @@ fn test_sdiv_disabled() {
             let assembly = format!("\n{}\nexit", instruction);
             let result = assemble::<UserError, TestInstructionMeter>(
                 &assembly,
-                Some(check),
+                Some(&check),
                 Config {
                     enable_sdiv,
                     ..Config::default()
@@ -375,3 +436,382 @@ fn test_sdiv_disabled() {
         }
     }
 }
+
+#[test]
+fn test_verifier_max_distinct_syscalls() {
+    let assembly = "
+        syscall bpf_a
+        syscall bpf_b
+        syscall bpf_a
+        syscall bpf_c
+        exit";
+    for (max_distinct_syscalls, expected) in [
+        (
+            Some(2),
+            Err("Executable constructor VerifierError(TooManyDistinctSyscalls(2, 32))"),
+        ),
+        (Some(3), Ok(())),
+        (None, Ok(())),
+    ] {
+        let result = assemble::<UserError, TestInstructionMeter>(
+            assembly,
+            Some(&check),
+            Config {
+                max_distinct_syscalls,
+                ..Config::default()
+            },
+            SyscallRegistry::default(),
+        );
+        assert_eq!(result.map(|_| ()), expected.map_err(|err| err.to_string()));
+    }
+}
+
+#[test]
+fn test_verifier_max_callees_per_function() {
+    let assembly = "
+        call function_a
+        call function_b
+        call function_c
+        call function_a
+        exit
+        function_a:
+        exit
+        function_b:
+        exit
+        function_c:
+        exit";
+    for (max_callees_per_function, expected) in [
+        (
+            Some(2),
+            Err("Executable constructor VerifierError(TooManyCallees(2, 31))"),
+        ),
+        (Some(3), Ok(())),
+        (None, Ok(())),
+    ] {
+        let result = assemble::<UserError, TestInstructionMeter>(
+            assembly,
+            Some(&check),
+            Config {
+                max_callees_per_function,
+                ..Config::default()
+            },
+            SyscallRegistry::default(),
+        );
+        assert_eq!(result.map(|_| ()), expected.map_err(|err| err.to_string()));
+    }
+}
+
+#[test]
+fn test_from_text_asm() {
+    let executable = Executable::<UserError, TestInstructionMeter>::from_text_asm(
+        "mov r0, 3\n exit",
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 2 })
+            .unwrap(),
+        3
+    );
+    assert!(matches!(
+        Executable::<UserError, TestInstructionMeter>::from_text_asm(
+            "mov r0, 3\n foo",
+            Config::default(),
+            SyscallRegistry::default(),
+        ),
+        Err(EbpfError::AssemblerError(_))
+    ));
+    assert!(matches!(
+        Executable::<UserError, TestInstructionMeter>::from_text_asm(
+            "div r0, 0\n exit",
+            Config::default(),
+            SyscallRegistry::default(),
+        ),
+        Err(EbpfError::VerifierError(VerifierError::DivisionByZero(29)))
+    ));
+}
+
+#[test]
+fn test_verifier_custom() {
+    struct RejectCalls;
+    impl Verifier for RejectCalls {
+        fn verify(&self, prog: &[u8], config: &Config) -> Result<(), VerifierError> {
+            DefaultVerifier.verify(prog, config)?;
+            for insn_ptr in 0..prog.len() / ebpf::INSN_SIZE {
+                let insn = ebpf::get_insn(prog, insn_ptr);
+                if insn.opc == ebpf::CALL_IMM || insn.opc == ebpf::CALL_REG {
+                    return Err(VerifierError::DisallowedOpCode(
+                        insn.opc,
+                        insn_ptr + ebpf::ELF_INSN_DUMP_OFFSET,
+                    ));
+                }
+            }
+            Ok(())
+        }
+    }
+    let assembly = "
+        call function_foo
+        exit
+        function_foo:
+        mov r0, 1
+        exit";
+    assemble::<UserError, TestInstructionMeter>(
+        assembly,
+        Some(&DefaultVerifier),
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let result = assemble::<UserError, TestInstructionMeter>(
+        assembly,
+        Some(&RejectCalls),
+        Config::default(),
+        SyscallRegistry::default(),
+    );
+    assert_eq!(
+        result.map(|_| ()),
+        Err(format!(
+            "Executable constructor VerifierError(DisallowedOpCode({}, 29))",
+            ebpf::CALL_IMM
+        ))
+    );
+}
+
+#[test]
+fn test_verifier_frame_pointer_arithmetic() {
+    let config = Config {
+        reject_frame_pointer_arithmetic: true,
+        ..Config::default()
+    };
+    for assembly in [
+        "mov r1, r10\n add r1, -8\n exit",
+        "mov r1, 0x100\n sub r1, r10\n exit",
+        "mov r1, 0x100\n add r1, r10\n exit",
+    ] {
+        assemble::<UserError, TestInstructionMeter>(
+            assembly,
+            Some(&check),
+            config.clone(),
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+    }
+    for (assembly, expected) in [
+        (
+            "mul r10, 2\n exit",
+            "Executable constructor VerifierError(IllegalFramePointerArithmetic(29))",
+        ),
+        (
+            "mov r1, 0\n mul r1, r10\n exit",
+            "Executable constructor VerifierError(IllegalFramePointerArithmetic(30))",
+        ),
+        (
+            "mov r1, 0\n xor32 r1, r10\n exit",
+            "Executable constructor VerifierError(IllegalFramePointerArithmetic(30))",
+        ),
+        (
+            "mov r10, r1\n exit",
+            "Executable constructor VerifierError(CannotWriteR10(29))",
+        ),
+        (
+            "add r10, -8\n exit",
+            "Executable constructor VerifierError(CannotWriteR10(29))",
+        ),
+    ] {
+        let result = assemble::<UserError, TestInstructionMeter>(
+            assembly,
+            Some(&check),
+            config.clone(),
+            SyscallRegistry::default(),
+        );
+        assert_eq!(result.map(|_| ()), Err(expected.to_string()));
+    }
+}
+
+#[test]
+fn test_verifier_allowed_opcodes() {
+    let mut config = Config::default();
+    config.set_allowed_opcodes(&[ebpf::MOV64_IMM, ebpf::ADD64_REG, ebpf::EXIT]);
+    assemble::<UserError, TestInstructionMeter>(
+        "mov r0, 1\n mov r1, 2\n add r0, r1\n exit",
+        Some(&check),
+        config.clone(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let result = assemble::<UserError, TestInstructionMeter>(
+        "mov r0, 1\n add r0, 2\n exit",
+        Some(&check),
+        config,
+        SyscallRegistry::default(),
+    );
+    assert_eq!(
+        result.map(|_| ()),
+        Err("Executable constructor VerifierError(DisallowedOpCode(7, 30))".to_string())
+    );
+}
+
+#[test]
+fn test_verifier_error_config_options() {
+    let mut config = Config::default();
+    config.set_allowed_opcodes(&[ebpf::MOV64_IMM, ebpf::EXIT]);
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "mov r0, 1\n add r0, 2\n exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let err = check(executable.get_text_bytes().1, &config).unwrap_err();
+    assert_eq!(err, VerifierError::DisallowedOpCode(ebpf::ADD64_IMM, 30));
+    assert_eq!(err.config_options(), &["allowed_opcodes"]);
+    let err = check(
+        &[ebpf::SDIV64_REG, 0, 0, 0, 0, 0, 0, 0],
+        &Config {
+            enable_sdiv: false,
+            ..Config::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err.config_options(), &["enable_sdiv"]);
+    assert!(VerifierError::NoProgram.config_options().is_empty());
+}
+
+#[test]
+fn test_verifier_unconditional_self_loop() {
+    let config = Config {
+        reject_unconditional_self_loops: true,
+        ..Config::default()
+    };
+    let result = assemble::<UserError, TestInstructionMeter>(
+        "mov r0, 1\n ja -1\n exit",
+        Some(&check),
+        config.clone(),
+        SyscallRegistry::default(),
+    );
+    assert_eq!(
+        result.map(|_| ()),
+        Err("Executable constructor VerifierError(UnconditionalSelfLoop(30))".to_string())
+    );
+    assemble::<UserError, TestInstructionMeter>(
+        "mov r0, 1\n jeq r0, 1, -1\n exit",
+        Some(&check),
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    assemble::<UserError, TestInstructionMeter>(
+        "mov r0, 1\n ja -1\n exit",
+        Some(&check),
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_verifier_incremental() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        lddw r1, 0x1122334455667788
+        jeq r0, 1, +1
+        mov r0, 2
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let prog = executable.get_text_bytes().1;
+    let mut verifier = IncrementalVerifier::new(Config::default());
+    for insn in prog.chunks_exact(ebpf::INSN_SIZE) {
+        verifier.push(insn.try_into().unwrap()).unwrap();
+    }
+    assert_eq!(verifier.finalize().unwrap(), prog);
+
+    // An invalid opcode is reported as soon as it is pushed
+    let mut verifier = IncrementalVerifier::new(Config::default());
+    verifier.push(prog[0..8].try_into().unwrap()).unwrap();
+    assert_eq!(
+        verifier.push(&[0x06, 0, 0, 0, 0, 0, 0, 0]),
+        Err(VerifierError::UnknownOpCode(0x06, 30))
+    );
+
+    // Jump targets are only checked when finalizing
+    let mut verifier = IncrementalVerifier::new(Config::default());
+    let ja = ebpf::Insn {
+        opc: ebpf::JA,
+        off: 4,
+        ..ebpf::Insn::default()
+    };
+    verifier.push(&ja.to_array()).unwrap();
+    verifier
+        .push(prog[prog.len() - 8..].try_into().unwrap())
+        .unwrap();
+    assert_eq!(
+        verifier.finalize(),
+        Err(VerifierError::JumpOutOfCode(5, 29))
+    );
+}
+
+#[test]
+fn test_verifier_pipeline() {
+    fn ban_mul(prog: &[u8], _config: &Config) -> Result<(), VerifierError> {
+        for (insn_ptr, insn) in prog.chunks_exact(ebpf::INSN_SIZE).enumerate() {
+            if insn[0] == ebpf::MUL64_IMM {
+                return Err(VerifierError::UnknownOpCode(
+                    ebpf::MUL64_IMM,
+                    insn_ptr + ebpf::ELF_INSN_DUMP_OFFSET,
+                ));
+            }
+        }
+        Ok(())
+    }
+    let mut pipeline = VerifierPipeline::new();
+    pipeline.push(check).push(ban_mul);
+    let config = Config::default();
+
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r0, 1
+        mul64 r0, 2
+        exit",
+        None,
+        config.clone(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let prog = executable.get_text_bytes().1;
+    assert!(check(prog, &config).is_ok());
+    assert_eq!(
+        pipeline.verify(prog, &config),
+        Err(VerifierError::UnknownOpCode(ebpf::MUL64_IMM, 30))
+    );
+
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov64 r0, 1
+        mul64 r0, 2
+        div64 r0, 0
+        exit",
+        None,
+        config.clone(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let prog = executable.get_text_bytes().1;
+    assert_eq!(
+        pipeline.verify(prog, &config),
+        Err(VerifierError::DivisionByZero(31))
+    );
+    assert_eq!(
+        pipeline.verify_all(prog, &config),
+        vec![
+            VerifierError::DivisionByZero(31),
+            VerifierError::UnknownOpCode(ebpf::MUL64_IMM, 30)
+        ]
+    );
+}